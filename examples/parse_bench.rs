@@ -0,0 +1,39 @@
+//! Times `Lox::parse` on a large generated script, the kind of input the
+//! scanner and parser are tuned for. Run it with
+//! `cargo run --release --example parse_bench [lines]`.
+
+use std::time::{Duration, Instant};
+
+use primox::Lox;
+
+const RUNS: usize = 10;
+
+fn main() {
+    let lines: usize = std::env::args().nth(1).and_then(|n| n.parse().ok()).unwrap_or(50_000);
+    // Only the oldest expression grammar, so that timings can be compared
+    // across the history of the parser.
+    let mut source = String::new();
+    for i in 0..lines {
+        source += &format!("print (1 * {i} + (\"s{i}\" - 2.5) / -2) >= {i} == !nil;\n\"a\" + \"b{i}\" != (true);\n");
+    }
+
+    let mut lox = Lox::new();
+    let mut times: Vec<Duration> = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            let program = lox.parse(&source);
+            let elapsed = start.elapsed();
+            assert!(program.is_some(), "the generated script should parse");
+            elapsed
+        })
+        .collect();
+    times.sort();
+    println!(
+        "{} lines, {} KiB: median {:?}, best {:?} over {} runs",
+        2 * lines,
+        source.len() / 1024,
+        times[RUNS / 2],
+        times[0],
+        RUNS
+    );
+}
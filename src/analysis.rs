@@ -0,0 +1,478 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::ast::{Ast, ExprId, Span, StmtId};
+use crate::expr::*;
+use crate::interner::{Interner, Symbol};
+use crate::limits;
+use crate::stmt::*;
+use crate::token::LiteralToken;
+
+/// The names a program declares and where each is used, for editors. It
+/// comes from a static pass like the resolver's, so it knows nothing that
+/// only running the program would tell.
+#[derive(Debug, Default)]
+pub(crate) struct Analysis {
+    pub(crate) definitions: Vec<Definition>,
+    pub(crate) references: Vec<Reference>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Definition {
+    pub(crate) name: Rc<str>,
+    pub(crate) kind: DefinitionKind,
+    /// The name where it is declared.
+    pub(crate) span: Span,
+    /// The whole declaration, such as a function with its body.
+    pub(crate) range: Span,
+    /// Where the name can be used from its declaration on, or `None` for
+    /// a global, which can be used anywhere.
+    pub(crate) scope: Option<Span>,
+    /// The function declared in, as an index into the definitions.
+    pub(crate) container: Option<usize>,
+    /// What the value is, when it shows from the declaration alone:
+    /// "int", "string", "list", "function", ...
+    pub(crate) inferred: Option<&'static str>,
+    /// The parameters, for a function.
+    pub(crate) params: Vec<Rc<str>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DefinitionKind {
+    Function,
+    Import,
+    Parameter,
+    Variable,
+}
+
+/// A use of a name, with the definition it refers to. Names declared
+/// nowhere, such as natives, have none.
+#[derive(Debug)]
+pub(crate) struct Reference {
+    pub(crate) name: Rc<str>,
+    pub(crate) span: Span,
+    pub(crate) definition: Option<usize>,
+}
+
+impl Definition {
+    /// A line of Lox describing the definition, as shown on hover.
+    pub(crate) fn signature(&self) -> String {
+        match self.kind {
+            DefinitionKind::Function => format!("fun {}({})", self.name, self.params.join(", ")),
+            DefinitionKind::Import => format!("module {}", self.name),
+            DefinitionKind::Parameter => format!("param {}", self.name),
+            DefinitionKind::Variable => match self.inferred {
+                Some("function") => format!("var {} = fun ({})", self.name, self.params.join(", ")),
+                Some(kind) => format!("var {}: {}", self.name, kind),
+                None => format!("var {}", self.name),
+            },
+        }
+    }
+
+    /// Whether the name can be used at `offset`.
+    pub(crate) fn is_visible_at(&self, offset: usize) -> bool {
+        match self.scope {
+            Some(scope) => self.span.start <= offset && offset <= scope.end,
+            None => true,
+        }
+    }
+}
+
+fn contains(span: Span, offset: usize) -> bool {
+    span.start <= offset && offset <= span.end
+}
+
+impl Analysis {
+    /// The definition of the name at `offset`, whether that is where the
+    /// name is declared or where it is used.
+    pub(crate) fn definition_at(&self, offset: usize) -> Option<usize> {
+        let declared = self.definitions.iter().position(|definition| contains(definition.span, offset));
+        declared.or_else(|| self.reference_at(offset).and_then(|reference| reference.definition))
+    }
+
+    pub(crate) fn reference_at(&self, offset: usize) -> Option<&Reference> {
+        self.references.iter().find(|reference| contains(reference.span, offset))
+    }
+
+    /// Where the definition is used, in the order of the source.
+    pub(crate) fn references_to(&self, definition: usize) -> Vec<Span> {
+        let references = self.references.iter().filter(|reference| reference.definition == Some(definition));
+        references.map(|reference| reference.span).collect()
+    }
+
+    /// The definitions that can be used at `offset`, innermost first, each
+    /// name only once.
+    pub(crate) fn visible_at(&self, offset: usize) -> Vec<&Definition> {
+        let mut visible: Vec<&Definition> = vec![];
+        // Later declarations are nested deeper or shadow earlier ones.
+        for definition in self.definitions.iter().rev() {
+            if definition.is_visible_at(offset) && !visible.iter().any(|other| other.name == definition.name) {
+                visible.push(definition);
+            }
+        }
+        visible
+    }
+}
+
+/// Builds an `Analysis`, walking a tree the way the interpreter would
+/// scope it.
+pub(crate) struct Analyzer<'a> {
+    interner: &'a Interner,
+    analysis: Analysis,
+    // The local scopes around the current node, innermost last, with the
+    // region of each.
+    scopes: Vec<(Span, HashMap<Symbol, usize>)>,
+    globals: HashMap<Symbol, usize>,
+    // Uses of names not declared locally. Globals can be declared after
+    // their uses, so these are resolved once the whole tree is walked.
+    unresolved: Vec<(Symbol, usize)>,
+    // The function being walked, if any.
+    container: Option<usize>,
+}
+
+impl<'a> Analyzer<'a> {
+    pub(crate) fn new(interner: &'a Interner) -> Self {
+        Self {
+            interner,
+            analysis: Analysis::default(),
+            scopes: vec![],
+            globals: HashMap::new(),
+            unresolved: vec![],
+            container: None,
+        }
+    }
+
+    pub(crate) fn analyze(mut self, ast: &Ast) -> Analysis {
+        for &statement in &ast.statements {
+            self.analyze_stmt(ast, statement);
+        }
+
+        for (symbol, reference) in self.unresolved {
+            self.analysis.references[reference].definition = self.globals.get(&symbol).copied();
+        }
+        self.analysis
+    }
+
+    fn analyze_stmt(&mut self, ast: &Ast, statement: StmtId) {
+        limits::with_stack(|| statement.accept(ast, self))
+    }
+
+    fn analyze_expr(&mut self, ast: &Ast, expr: ExprId) {
+        limits::with_stack(|| expr.accept(ast, self))
+    }
+
+    fn analyze_exprs(&mut self, ast: &Ast, exprs: &[ExprId]) {
+        for &expr in exprs {
+            self.analyze_expr(ast, expr);
+        }
+    }
+
+    /// Walks `walk` in a new scope covering `region`.
+    fn scoped(&mut self, region: Span, walk: impl FnOnce(&mut Self)) {
+        self.scopes.push((region, HashMap::new()));
+        walk(self);
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: Identifier, kind: DefinitionKind, range: Span) -> usize {
+        let index = self.analysis.definitions.len();
+        self.analysis.definitions.push(Definition {
+            name: self.interner.resolve(name.symbol),
+            kind,
+            span: name.span,
+            range,
+            scope: self.scopes.last().map(|&(region, _)| region),
+            container: self.container,
+            inferred: None,
+            params: vec![],
+        });
+        match self.scopes.last_mut() {
+            Some((_, scope)) => scope.insert(name.symbol, index),
+            None => self.globals.insert(name.symbol, index),
+        };
+        index
+    }
+
+    fn reference(&mut self, name: Identifier) {
+        let index = self.analysis.references.len();
+        let local = self.scopes.iter().rev().find_map(|(_, scope)| scope.get(&name.symbol).copied());
+        if local.is_none() {
+            self.unresolved.push((name.symbol, index));
+        }
+        self.analysis.references.push(Reference {
+            name: self.interner.resolve(name.symbol),
+            span: name.span,
+            definition: local,
+        });
+    }
+
+    /// Walks a function's parameters and body as part of `definition`.
+    fn function(&mut self, ast: &Ast, definition: usize, function: ExprId) {
+        let Expr::Lambda(lambda) = &ast[function] else {
+            return self.analyze_expr(ast, function);
+        };
+        let params = lambda.params.iter().map(|param| self.interner.resolve(param.symbol)).collect();
+        self.analysis.definitions[definition].params = params;
+
+        let container = self.container.replace(definition);
+        self.analyze_expr(ast, function);
+        self.container = container;
+    }
+
+    /// What `expr` evaluates to, if it shows without running it.
+    fn infer(ast: &Ast, expr: ExprId) -> Option<&'static str> {
+        let kind = match &ast[expr] {
+            Expr::Grouping(grouping) => return Self::infer(ast, grouping.expression),
+            Expr::Lambda(_) => "function",
+            Expr::List(_) => "list",
+            Expr::Map(_) => "map",
+            Expr::Template(_) => "string",
+            Expr::Literal(literal) => match literal.value {
+                LiteralToken::Null => "nil",
+                LiteralToken::Bool(_) => "bool",
+                LiteralToken::Int(_) => "int",
+                LiteralToken::BigInt(_) => "bigint",
+                LiteralToken::Decimal(_) => "decimal",
+                LiteralToken::Number(_) => "number",
+                LiteralToken::String(_) => "string",
+            },
+            _ => return None,
+        };
+        Some(kind)
+    }
+}
+
+impl StmtVisitor<()> for Analyzer<'_> {
+    fn visit_block_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &BlockStmt) {
+        self.scoped(ast.stmts.span(id), |analyzer| {
+            for &statement in &stmt.statements {
+                analyzer.analyze_stmt(ast, statement);
+            }
+        });
+    }
+
+    fn visit_break_stmt(&mut self, _: &Ast, _: StmtId, _: &BreakStmt) {}
+
+    fn visit_continue_stmt(&mut self, _: &Ast, _: StmtId, _: &ContinueStmt) {}
+
+    fn visit_expression_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &ExpressionStmt) {
+        self.analyze_expr(ast, stmt.expression);
+    }
+
+    fn visit_for_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &ForStmt) {
+        self.scoped(ast.stmts.span(id), |analyzer| {
+            if let Some(initializer) = stmt.initializer {
+                analyzer.analyze_stmt(ast, initializer);
+            }
+            if let Some(condition) = stmt.condition {
+                analyzer.analyze_expr(ast, condition);
+            }
+            if let Some(increment) = stmt.increment {
+                analyzer.analyze_expr(ast, increment);
+            }
+            analyzer.analyze_stmt(ast, stmt.body);
+        });
+    }
+
+    fn visit_for_in_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &ForInStmt) {
+        self.analyze_expr(ast, stmt.iterable);
+        self.scoped(ast.stmts.span(id), |analyzer| {
+            analyzer.declare(stmt.name, DefinitionKind::Variable, stmt.name.span);
+            analyzer.analyze_stmt(ast, stmt.body);
+        });
+    }
+
+    fn visit_function_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &FunctionStmt) {
+        let definition = self.declare(stmt.name, DefinitionKind::Function, ast.stmts.span(id));
+        self.analysis.definitions[definition].inferred = Some("function");
+        self.function(ast, definition, stmt.function);
+    }
+
+    fn visit_if_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &IfStmt) {
+        self.analyze_expr(ast, stmt.condition);
+        self.analyze_stmt(ast, stmt.then_branch);
+        if let Some(else_branch) = stmt.else_branch {
+            self.analyze_stmt(ast, else_branch);
+        }
+    }
+
+    fn visit_import_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &ImportStmt) {
+        let definition = self.declare(stmt.name, DefinitionKind::Import, ast.stmts.span(id));
+        self.analysis.definitions[definition].inferred = Some("module");
+    }
+
+    fn visit_print_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &PrintStmt) {
+        self.analyze_expr(ast, stmt.expression);
+    }
+
+    fn visit_return_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &ReturnStmt) {
+        if let Some(value) = stmt.value {
+            self.analyze_expr(ast, value);
+        }
+    }
+
+    fn visit_throw_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &ThrowStmt) {
+        self.analyze_expr(ast, stmt.value);
+    }
+
+    fn visit_try_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &TryStmt) {
+        self.analyze_stmt(ast, stmt.body);
+        if let Some(catch) = &stmt.catch {
+            self.scoped(catch.name.span.to(ast.stmts.span(catch.body)), |analyzer| {
+                analyzer.declare(catch.name, DefinitionKind::Variable, catch.name.span);
+                analyzer.analyze_stmt(ast, catch.body);
+            });
+        }
+        if let Some(finally) = stmt.finally {
+            self.analyze_stmt(ast, finally);
+        }
+    }
+
+    fn visit_var_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &VarStmt) {
+        let range = ast.stmts.span(id);
+        let Some(initializer) = stmt.initializer else {
+            self.declare(stmt.name, DefinitionKind::Variable, range);
+            return;
+        };
+
+        let inferred = Self::infer(ast, initializer);
+        // A function can call itself by the name it is stored in.
+        if inferred == Some("function") {
+            let definition = self.declare(stmt.name, DefinitionKind::Variable, range);
+            self.analysis.definitions[definition].inferred = inferred;
+            self.function(ast, definition, initializer);
+        } else {
+            self.analyze_expr(ast, initializer);
+            let definition = self.declare(stmt.name, DefinitionKind::Variable, range);
+            self.analysis.definitions[definition].inferred = inferred;
+        }
+    }
+
+    fn visit_while_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &WhileStmt) {
+        self.analyze_expr(ast, stmt.condition);
+        self.analyze_stmt(ast, stmt.body);
+    }
+}
+
+impl ExprVisitor<()> for Analyzer<'_> {
+    fn visit_assign_expr(&mut self, ast: &Ast, _: ExprId, expr: &Assign) {
+        self.analyze_expr(ast, expr.value);
+        self.reference(expr.name);
+    }
+
+    fn visit_binary_expr(&mut self, ast: &Ast, _: ExprId, expr: &Binary) {
+        self.analyze_exprs(ast, &[expr.left, expr.right]);
+    }
+
+    fn visit_call_expr(&mut self, ast: &Ast, _: ExprId, expr: &Call) {
+        self.analyze_expr(ast, expr.callee);
+        self.analyze_exprs(ast, &expr.arguments);
+    }
+
+    fn visit_get_expr(&mut self, ast: &Ast, _: ExprId, expr: &Get) {
+        self.analyze_expr(ast, expr.object);
+    }
+
+    fn visit_grouping_expr(&mut self, ast: &Ast, _: ExprId, expr: &Grouping) {
+        self.analyze_expr(ast, expr.expression);
+    }
+
+    fn visit_lambda_expr(&mut self, ast: &Ast, id: ExprId, expr: &Lambda) {
+        self.scoped(ast.exprs.span(id), |analyzer| {
+            for &param in &expr.params {
+                analyzer.declare(param, DefinitionKind::Parameter, param.span);
+            }
+            for &statement in &expr.body {
+                analyzer.analyze_stmt(ast, statement);
+            }
+        });
+    }
+
+    fn visit_list_expr(&mut self, ast: &Ast, _: ExprId, expr: &List) {
+        self.analyze_exprs(ast, &expr.elements);
+    }
+
+    fn visit_literal_expr(&mut self, _: &Ast, _: ExprId, _: &Literal) {}
+
+    fn visit_logical_expr(&mut self, ast: &Ast, _: ExprId, expr: &Logical) {
+        self.analyze_exprs(ast, &[expr.left, expr.right]);
+    }
+
+    fn visit_map_expr(&mut self, ast: &Ast, _: ExprId, expr: &Map) {
+        for &(key, value) in &expr.entries {
+            self.analyze_exprs(ast, &[key, value]);
+        }
+    }
+
+    fn visit_set_expr(&mut self, ast: &Ast, _: ExprId, expr: &Set) {
+        self.analyze_exprs(ast, &[expr.object, expr.value]);
+    }
+
+    fn visit_set_subscript_expr(&mut self, ast: &Ast, _: ExprId, expr: &SetSubscript) {
+        self.analyze_exprs(ast, &[expr.object, expr.index, expr.value]);
+    }
+
+    fn visit_subscript_expr(&mut self, ast: &Ast, _: ExprId, expr: &Subscript) {
+        self.analyze_exprs(ast, &[expr.object, expr.index]);
+    }
+
+    fn visit_template_expr(&mut self, ast: &Ast, _: ExprId, expr: &Template) {
+        self.analyze_exprs(ast, &expr.parts);
+    }
+
+    fn visit_ternary_expr(&mut self, ast: &Ast, _: ExprId, expr: &Ternary) {
+        self.analyze_exprs(ast, &[expr.left, expr.mid, expr.right]);
+    }
+
+    fn visit_unary_expr(&mut self, ast: &Ast, _: ExprId, expr: &Unary) {
+        self.analyze_expr(ast, expr.right);
+    }
+
+    fn visit_update_expr(&mut self, ast: &Ast, _: ExprId, expr: &Update) {
+        self.analyze_expr(ast, expr.target);
+    }
+
+    fn visit_variable_expr(&mut self, _: &Ast, _: ExprId, expr: &Variable) {
+        self.reference(expr.name);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    use super::*;
+
+    fn analyze(source: &str) -> Analysis {
+        let interner = Rc::new(Interner::new());
+        let scanner = Scanner::new(source, interner.clone());
+        let ast = Parser::new(scanner, interner.clone()).parse().unwrap();
+        Analyzer::new(&interner).analyze(&ast)
+    }
+
+    #[test]
+    fn test_analysis() {
+        let source = "fun f(a) { var x = a; { var x = 1.5; x; } return g(x); }\nvar g = (b) => b;\nprint len([f]);";
+        let analysis = analyze(source);
+        let at = |text: &str, nth: usize| source.match_indices(text).nth(nth).unwrap().0;
+        let name = |definition: Option<usize>| definition.map(|i| analysis.definitions[i].signature());
+
+        assert_eq!(name(analysis.definition_at(at("= a", 0) + 2)), Some("param a".to_string()));
+        assert_eq!(name(analysis.definition_at(at("x;", 0))), Some("var x: number".to_string()));
+        assert_eq!(name(analysis.definition_at(at("x)", 0))), Some("var x".to_string()));
+        assert_eq!(name(analysis.definition_at(at("g(", 0))), Some("var g = fun (b)".to_string()));
+        assert_eq!(analysis.definition_at(at("len", 0)), None);
+        assert_eq!(analysis.reference_at(at("len", 0)).unwrap().name.as_ref(), "len");
+
+        let f = analysis.definition_at(at("f", 1)).unwrap();
+        assert_eq!(analysis.references_to(f), vec![Span::new(at("f", 2), at("f", 2) + 1, 3)]);
+        assert_eq!(analysis.definitions[analysis.definition_at(at("b", 1)).unwrap()].container, analysis.definition_at(at("g", 1)));
+
+        let nested = format!("var a = {}a{};", "(".repeat(10000), ")".repeat(10000));
+        assert_eq!(analyze(&nested).references.len(), 1);
+
+        let visible: Vec<_> = analysis.visible_at(at("return", 0)).iter().map(|d| d.signature()).collect();
+        assert_eq!(visible, vec!["var g = fun (b)", "var x", "param a", "fun f(a)"]);
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::ops::{Index, IndexMut};
+use std::path::PathBuf;
+
+use crate::expr::Expr;
+use crate::stmt::Stmt;
+
+/// A stable handle to a node stored in an `Arena`. Handles are plain indices,
+/// so they are cheap to copy and can key side tables (resolver depths, type
+/// annotations, coverage counters, ...).
+pub(crate) struct NodeId<T> {
+    index: u32,
+    marker: PhantomData<fn() -> T>,
+}
+
+pub(crate) type ExprId = NodeId<Expr>;
+pub(crate) type StmtId = NodeId<Stmt>;
+
+impl<T> NodeId<T> {
+    fn new(index: usize) -> Self {
+        Self {
+            index: index as u32,
+            marker: PhantomData,
+        }
+    }
+
+    pub(crate) fn index(self) -> usize {
+        self.index as usize
+    }
+}
+
+// Derives would needlessly require `T` to implement these traits too.
+impl<T> Clone for NodeId<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for NodeId<T> {}
+
+impl<T> PartialEq for NodeId<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for NodeId<T> {}
+
+impl<T> Hash for NodeId<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+    }
+}
+
+impl<T> Debug for NodeId<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.index)
+    }
+}
+
+/// A region of the source: byte offsets `start..end`, and the line on which
+/// it starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct Span {
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) line: usize,
+}
+
+impl Span {
+    pub(crate) fn new(start: usize, end: usize, line: usize) -> Self {
+        Self { start, end, line }
+    }
+
+    /// The span covering both `self` and `other`, which must come after it.
+    pub(crate) fn to(self, other: Span) -> Span {
+        Span::new(self.start, other.end, self.line)
+    }
+}
+
+/// Owns every node of one kind, along with the span each was parsed from.
+#[derive(Debug)]
+pub(crate) struct Arena<T> {
+    nodes: Vec<T>,
+    spans: Vec<Span>,
+}
+
+impl<T> Arena<T> {
+    pub(crate) fn alloc(&mut self, node: impl Into<T>, span: Span) -> NodeId<T> {
+        let id = NodeId::new(self.nodes.len());
+        self.nodes.push(node.into());
+        self.spans.push(span);
+        id
+    }
+
+    pub(crate) fn span(&self, id: NodeId<T>) -> Span {
+        self.spans[id.index()]
+    }
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Self {
+            nodes: vec![],
+            spans: vec![],
+        }
+    }
+}
+
+impl<T> Index<NodeId<T>> for Arena<T> {
+    type Output = T;
+
+    fn index(&self, id: NodeId<T>) -> &T {
+        &self.nodes[id.index()]
+    }
+}
+
+impl<T> IndexMut<NodeId<T>> for Arena<T> {
+    fn index_mut(&mut self, id: NodeId<T>) -> &mut T {
+        &mut self.nodes[id.index()]
+    }
+}
+
+/// A parsed program: the arenas holding its nodes, and its top-level
+/// statements in order.
+#[derive(Debug, Default)]
+pub(crate) struct Ast {
+    pub(crate) exprs: Arena<Expr>,
+    pub(crate) stmts: Arena<Stmt>,
+    pub(crate) statements: Vec<StmtId>,
+    /// How many scopes out each local variable is declared, keyed by the
+    /// variable or assignment expression, as the resolver found. Globals
+    /// are missing.
+    pub(crate) locals: HashMap<ExprId, usize>,
+    /// The file the program was read from, if any. Its imports are
+    /// relative to the file's directory.
+    pub(crate) path: Option<PathBuf>,
+}
+
+impl Index<ExprId> for Ast {
+    type Output = Expr;
+
+    fn index(&self, id: ExprId) -> &Expr {
+        &self.exprs[id]
+    }
+}
+
+impl IndexMut<ExprId> for Ast {
+    fn index_mut(&mut self, id: ExprId) -> &mut Expr {
+        &mut self.exprs[id]
+    }
+}
+
+impl Index<StmtId> for Ast {
+    type Output = Stmt;
+
+    fn index(&self, id: StmtId) -> &Stmt {
+        &self.stmts[id]
+    }
+}
+
+impl IndexMut<StmtId> for Ast {
+    fn index_mut(&mut self, id: StmtId) -> &mut Stmt {
+        &mut self.stmts[id]
+    }
+}
@@ -1,69 +1,148 @@
+use crate::ast::{Ast, ExprId};
 use crate::expr::*;
+use crate::interner::Interner;
 
-pub(crate) struct AstPrinter;
+pub(crate) struct AstPrinter<'a> {
+    interner: &'a Interner,
+}
+
+impl AstPrinter<'_> {
+    pub(crate) fn print(ast: &Ast, interner: &Interner, e: ExprId) -> String {
+        e.accept(ast, &mut AstPrinter { interner })
+    }
 
-impl AstPrinter {
-    pub(crate) fn print(e: Expr) -> String {
-        match e {
-            Expr::Binary(e) => Self::print_binary_expr(*e),
-            Expr::Grouping(e) => Self::print_grouping_expr(*e),
-            Expr::Literal(e) => Self::print_literal_expr(*e),
-            Expr::Ternary(e) => Self::print_ternary_expr(*e),
-            Expr::Unary(e) => Self::print_unary_expr(*e),
+    fn parenthesize(&mut self, ast: &Ast, name: &str, exprs: &[ExprId]) -> String {
+        let mut result = format!("({}", name);
+        for expr in exprs {
+            result.push(' ');
+            result.push_str(&expr.accept(ast, self));
         }
+        result.push(')');
+        result
     }
+}
 
-    fn print_binary_expr(e: Binary) -> String {
+impl ExprVisitor<String> for AstPrinter<'_> {
+    fn visit_assign_expr(&mut self, ast: &Ast, _: ExprId, e: &Assign) -> String {
+        let equals = e.operator.map_or("=".to_string(), |operator| format!("{}=", operator.ty));
+        let name = format!("{} {}", equals, self.interner.resolve(e.name.symbol));
+        self.parenthesize(ast, &name, &[e.value])
+    }
+
+    fn visit_binary_expr(&mut self, ast: &Ast, _: ExprId, e: &Binary) -> String {
         format!(
             "({} {} {})",
-            e.operator.lexeme,
-            Self::print(e.left),
-            Self::print(e.right)
+            e.operator.ty,
+            e.left.accept(ast, self),
+            e.right.accept(ast, self)
         )
     }
 
-    fn print_grouping_expr(e: Grouping) -> String {
-        format!("(group {})", Self::print(e.expression))
+    fn visit_call_expr(&mut self, ast: &Ast, _: ExprId, e: &Call) -> String {
+        let mut exprs = vec![e.callee];
+        exprs.extend(&e.arguments);
+        self.parenthesize(ast, "call", &exprs)
+    }
+
+    fn visit_get_expr(&mut self, ast: &Ast, _: ExprId, e: &Get) -> String {
+        let name = format!(". {}", self.interner.resolve(e.name.symbol));
+        self.parenthesize(ast, &name, &[e.object])
     }
 
-    fn print_literal_expr(e: Literal) -> String {
+    fn visit_grouping_expr(&mut self, ast: &Ast, _: ExprId, e: &Grouping) -> String {
+        format!("(group {})", e.expression.accept(ast, self))
+    }
+
+    fn visit_lambda_expr(&mut self, _: &Ast, _: ExprId, e: &Lambda) -> String {
+        let params: Vec<_> = e.params.iter().map(|param| self.interner.resolve(param.symbol)).collect();
+        format!("(fun ({}))", params.join(" "))
+    }
+
+    fn visit_list_expr(&mut self, ast: &Ast, _: ExprId, e: &List) -> String {
+        self.parenthesize(ast, "list", &e.elements)
+    }
+
+    fn visit_literal_expr(&mut self, _: &Ast, _: ExprId, e: &Literal) -> String {
         e.value.print()
     }
 
-    fn print_ternary_expr(e: Ternary) -> String {
+    fn visit_logical_expr(&mut self, ast: &Ast, _: ExprId, e: &Logical) -> String {
+        format!(
+            "({} {} {})",
+            e.operator.ty,
+            e.left.accept(ast, self),
+            e.right.accept(ast, self)
+        )
+    }
+
+    fn visit_map_expr(&mut self, ast: &Ast, _: ExprId, e: &Map) -> String {
+        let exprs: Vec<ExprId> = e.entries.iter().flat_map(|&(k, v)| [k, v]).collect();
+        self.parenthesize(ast, "map", &exprs)
+    }
+
+    fn visit_set_expr(&mut self, ast: &Ast, _: ExprId, e: &Set) -> String {
+        let equals = e.operator.map_or("=".to_string(), |operator| format!("{}=", operator.ty));
+        let name = format!(".{} {}", equals, self.interner.resolve(e.name.symbol));
+        self.parenthesize(ast, &name, &[e.object, e.value])
+    }
+
+    fn visit_set_subscript_expr(&mut self, ast: &Ast, _: ExprId, e: &SetSubscript) -> String {
+        let name = e.operator.map_or("[]=".to_string(), |operator| format!("[]{}=", operator.ty));
+        self.parenthesize(ast, &name, &[e.object, e.index, e.value])
+    }
+
+    fn visit_subscript_expr(&mut self, ast: &Ast, _: ExprId, e: &Subscript) -> String {
+        self.parenthesize(ast, "[]", &[e.object, e.index])
+    }
+
+    fn visit_template_expr(&mut self, ast: &Ast, _: ExprId, e: &Template) -> String {
+        self.parenthesize(ast, "template", &e.parts)
+    }
+
+    fn visit_ternary_expr(&mut self, ast: &Ast, _: ExprId, e: &Ternary) -> String {
         format!(
             "(?: {} {} {})",
-            Self::print(e.left),
-            Self::print(e.mid),
-            Self::print(e.right),
+            e.left.accept(ast, self),
+            e.mid.accept(ast, self),
+            e.right.accept(ast, self),
         )
     }
 
-    fn print_unary_expr(e: Unary) -> String {
-        format!("({} {})", e.operator.lexeme, Self::print(e.right))
+    fn visit_unary_expr(&mut self, ast: &Ast, _: ExprId, e: &Unary) -> String {
+        format!("({} {})", e.operator.ty, e.right.accept(ast, self))
+    }
+
+    fn visit_update_expr(&mut self, ast: &Ast, _: ExprId, e: &Update) -> String {
+        let name = if e.prefix { e.operator.ty.to_string() } else { format!("post{}", e.operator.ty) };
+        self.parenthesize(ast, &name, &[e.target])
+    }
+
+    fn visit_variable_expr(&mut self, _: &Ast, _: ExprId, e: &Variable) -> String {
+        self.interner.resolve(e.name.symbol).to_string()
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::token::{LiteralToken, Token};
+    use crate::ast::Span;
+    use crate::token::LiteralToken;
     use crate::token_type::TokenType;
 
     use super::*;
 
     #[test]
     fn test_print() {
-        let expr: Expr = Binary::new(
-            Unary::new(
-                Token::new(TokenType::MINUS, "-", LiteralToken::Null, 1),
-                Literal::new(LiteralToken::Number(123.0)).into(),
-            )
-            .into(),
-            Token::new(TokenType::STAR, "*", LiteralToken::Null, 1),
-            Grouping::new(Literal::new(LiteralToken::Number(45.67)).into()).into(),
-        )
-        .into();
+        let mut ast = Ast::default();
+        let span = Span::default();
+        let n123 = ast.exprs.alloc(Literal::new(LiteralToken::Int(123)), span);
+        let minus = Operator::new(TokenType::MINUS, span);
+        let left = ast.exprs.alloc(Unary::new(minus, n123), span);
+        let n4567 = ast.exprs.alloc(Literal::new(LiteralToken::Number(45.67)), span);
+        let right = ast.exprs.alloc(Grouping::new(n4567), span);
+        let star = Operator::new(TokenType::STAR, span);
+        let expr = ast.exprs.alloc(Binary::new(left, star, right), span);
 
-        assert_eq!(AstPrinter::print(expr), "(* (- 123) (group 45.67))");
+        let interner = Interner::new();
+        assert_eq!(AstPrinter::print(&ast, &interner, expr), "(* (- 123) (group 45.67))");
     }
 }
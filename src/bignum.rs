@@ -0,0 +1,679 @@
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::ops::{Add, Mul, Neg, Sub};
+use std::time::Instant;
+
+// Limbs hold nine decimal digits each, which keeps parsing and printing
+// simple at some cost in arithmetic speed.
+const BASE: u64 = 1_000_000_000;
+const BASE_DIGITS: usize = 9;
+
+// How many limb operations the slow loops do between looks at the clock.
+const WORK_PER_CHECK: usize = 1 << 20;
+
+// The arithmetic runs behind operator traits, so the deadline it gives up
+// at is set for the thread rather than passed in.
+thread_local! {
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+    static WORK: Cell<usize> = const { Cell::new(0) };
+    static EXPIRED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `f`, whose multiplications and divisions give up once `deadline`
+/// passes, leaving meaningless results. Returns `None` if they did, as
+/// one operation on large enough numbers can take minutes.
+pub(crate) fn with_deadline<R>(deadline: Option<Instant>, f: impl FnOnce() -> R) -> Option<R> {
+    if deadline.is_none() {
+        return Some(f());
+    }
+    let previous = DEADLINE.replace(deadline);
+    let result = f();
+    DEADLINE.set(previous);
+    (!EXPIRED.replace(false)).then_some(result)
+}
+
+/// Counts `work` more limb operations, and whether the deadline set by
+/// `with_deadline` has passed.
+fn out_of_time(work: usize) -> bool {
+    let Some(deadline) = DEADLINE.get() else {
+        return false;
+    };
+    if EXPIRED.get() {
+        return true;
+    }
+    let work = WORK.get() + work;
+    WORK.set(work % WORK_PER_CHECK);
+    if work >= WORK_PER_CHECK && Instant::now() >= deadline {
+        EXPIRED.set(true);
+    }
+    EXPIRED.get()
+}
+
+/// An integer of any size.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct BigInt {
+    negative: bool,
+    // Least significant first, without trailing zeros. Zero has no limbs
+    // and is never negative.
+    limbs: Vec<u32>,
+}
+
+/// An exact decimal number, `unscaled / 10^scale`. The scale is kept as
+/// written, so `1.10d` still shows two decimal places.
+#[derive(Debug, Clone)]
+pub(crate) struct Decimal {
+    unscaled: BigInt,
+    scale: u32,
+}
+
+impl BigInt {
+    fn from_limbs(negative: bool, mut limbs: Vec<u32>) -> Self {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        let negative = negative && !limbs.is_empty();
+        Self { negative, limbs }
+    }
+
+    fn from_u64(mut n: u64) -> Vec<u32> {
+        let mut limbs = vec![];
+        while n > 0 {
+            limbs.push((n % BASE) as u32);
+            n /= BASE;
+        }
+        limbs
+    }
+
+    /// Parses an optionally signed run of decimal digits.
+    pub(crate) fn parse(text: &str) -> Option<Self> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        let limbs = digits
+            .as_bytes()
+            .rchunks(BASE_DIGITS)
+            .map(|chunk| std::str::from_utf8(chunk).unwrap().parse().unwrap())
+            .collect();
+        Some(Self::from_limbs(negative, limbs))
+    }
+
+    /// The integer equal to `n`, if `n` is a finite whole number.
+    pub(crate) fn from_f64(n: f64) -> Option<Self> {
+        if !n.is_finite() || n.fract() != 0.0 {
+            return None;
+        }
+        if n.abs() < u64::MAX as f64 {
+            return Some(Self::from_limbs(n < 0.0, Self::from_u64(n.abs() as u64)));
+        }
+
+        // Large floats are `mantissa * 2^exponent` with a positive exponent.
+        let bits = n.to_bits();
+        let mut exponent = ((bits >> 52) & 0x7ff) as i32 - 1075;
+        let mut limbs = Self::from_u64((bits & ((1 << 52) - 1)) | (1 << 52));
+        while exponent > 0 {
+            let shift = exponent.min(29);
+            limbs = mul_small(&limbs, 1 << shift);
+            exponent -= shift;
+        }
+        Some(Self::from_limbs(n < 0.0, limbs))
+    }
+
+    fn pow10(n: u32) -> Self {
+        let mut limbs = vec![0; n as usize / BASE_DIGITS];
+        limbs.push(10u32.pow(n % BASE_DIGITS as u32));
+        Self::from_limbs(false, limbs)
+    }
+
+    pub(crate) fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub(crate) fn to_i64(&self) -> Option<i64> {
+        let mut n: i128 = 0;
+        for &limb in self.limbs.iter().rev() {
+            n = n * BASE as i128 + limb as i128;
+            if n > i64::MAX as i128 + 1 {
+                return None;
+            }
+        }
+        i64::try_from(if self.negative { -n } else { n }).ok()
+    }
+
+    /// The nearest float.
+    pub(crate) fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap()
+    }
+
+    /// Roughly the base-10 logarithm of the magnitude, which is one less
+    /// than the number of digits. Zero for zero.
+    pub(crate) fn log10(&self) -> f64 {
+        match self.limbs.last() {
+            Some(&top) => ((self.limbs.len() - 1) * BASE_DIGITS) as f64 + (top as f64).log10(),
+            None => 0.0,
+        }
+    }
+
+    fn is_odd(&self) -> bool {
+        self.limbs.first().is_some_and(|limb| limb % 2 == 1)
+    }
+
+    /// Divides, rounding the quotient toward zero. The remainder takes the
+    /// sign of `self`. Returns `None` when dividing by zero.
+    pub(crate) fn div_rem(&self, other: &BigInt) -> Option<(BigInt, BigInt)> {
+        if other.is_zero() {
+            return None;
+        }
+        let (quotient, remainder) = div_rem_magnitudes(&self.limbs, &other.limbs);
+        Some((
+            Self::from_limbs(self.negative != other.negative, quotient),
+            Self::from_limbs(self.negative, remainder),
+        ))
+    }
+
+    /// Divides, rounding the quotient toward negative infinity. The
+    /// remainder takes the sign of `other`.
+    pub(crate) fn div_floor(&self, other: &BigInt) -> Option<(BigInt, BigInt)> {
+        let (quotient, remainder) = self.div_rem(other)?;
+        if !remainder.is_zero() && remainder.negative != other.negative {
+            Some((&quotient - &BigInt::from(1), &remainder + other))
+        } else {
+            Some((quotient, remainder))
+        }
+    }
+
+    pub(crate) fn pow(&self, mut exponent: u32) -> BigInt {
+        let (mut result, mut base) = (BigInt::from(1), self.clone());
+        while exponent > 0 {
+            if exponent % 2 == 1 {
+                result = &result * &base;
+            }
+            base = &base * &base;
+            exponent /= 2;
+        }
+        result
+    }
+
+    /// `self * 2^n`.
+    pub(crate) fn shl(&self, n: u32) -> BigInt {
+        if self.is_zero() {
+            return self.clone();
+        }
+        self * &BigInt::from(2).pow(n)
+    }
+
+    /// `self / 2^n`, rounded toward negative infinity like `>>` on `i64`.
+    pub(crate) fn shr(&self, n: u32) -> BigInt {
+        // A limb holds fewer than 30 bits, so shifting by as many bits as
+        // all the limbs have leaves only the sign.
+        if n as usize >= self.limbs.len() * 30 {
+            return BigInt::from(if self.negative { -1 } else { 0 });
+        }
+        self.div_floor(&BigInt::from(2).pow(n)).unwrap().0
+    }
+
+    /// Applies a bitwise operator to the two's complement forms of `self`
+    /// and `other`, 32 bits at a time.
+    pub(crate) fn bitwise(&self, other: &BigInt, op: fn(u32, u32) -> u32) -> BigInt {
+        let (mut a, a_sign) = self.to_words();
+        let (mut b, b_sign) = other.to_words();
+        let len = a.len().max(b.len());
+        a.resize(len, a_sign);
+        b.resize(len, b_sign);
+
+        let sign = op(a_sign, b_sign);
+        let word = BigInt::from(1 << 32);
+        let mut result = BigInt::from(0);
+        for (&x, &y) in a.iter().zip(&b).rev() {
+            result = &(&result * &word) + &BigInt::from(op(x, y) as i64);
+        }
+        if sign != 0 {
+            // The words stand for a negative number, `2^(32 * len)` less.
+            result = &result - &word.pow(len as u32);
+        }
+        result
+    }
+
+    /// The 32-bit words of `self` in two's complement, least significant
+    /// first, and the word that repeats beyond them.
+    fn to_words(&self) -> (Vec<u32>, u32) {
+        let word = BigInt::from(1 << 32);
+        let minus_one = BigInt::from(-1);
+        let mut n = self.clone();
+        let mut words = vec![];
+        // Flooring division leaves the remainder nonnegative, which is the
+        // next word even for negative numbers.
+        while !n.is_zero() && n != minus_one {
+            let (quotient, remainder) = n.div_floor(&word).unwrap();
+            words.push(remainder.to_i64().unwrap() as u32);
+            n = quotient;
+        }
+        (words, if n.is_zero() { 0 } else { u32::MAX })
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(n: i64) -> Self {
+        Self::from_limbs(n < 0, Self::from_u64(n.unsigned_abs()))
+    }
+}
+
+fn cmp_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut sum = vec![];
+    let mut carry = 0;
+    for i in 0..a.len().max(b.len()) {
+        let n = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        sum.push((n % BASE) as u32);
+        carry = n / BASE;
+    }
+    sum.push(carry as u32);
+    sum
+}
+
+/// `a - b`, where `a` is at least `b`.
+fn sub_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut difference = vec![];
+    let mut borrow = 0;
+    for (i, &limb) in a.iter().enumerate() {
+        let subtrahend = *b.get(i).unwrap_or(&0) as i64 + borrow;
+        let mut n = limb as i64 - subtrahend;
+        borrow = 0;
+        if n < 0 {
+            n += BASE as i64;
+            borrow = 1;
+        }
+        difference.push(n as u32);
+    }
+    while difference.last() == Some(&0) {
+        difference.pop();
+    }
+    difference
+}
+
+fn mul_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut product = vec![0u64; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        if out_of_time(b.len()) {
+            break;
+        }
+        let mut carry = 0;
+        for (j, &y) in b.iter().enumerate() {
+            let n = product[i + j] + x as u64 * y as u64 + carry;
+            product[i + j] = n % BASE;
+            carry = n / BASE;
+        }
+        product[i + b.len()] += carry;
+    }
+    product.into_iter().map(|n| n as u32).collect()
+}
+
+fn mul_small(a: &[u32], m: u32) -> Vec<u32> {
+    let mut product = vec![];
+    let mut carry = 0;
+    for &limb in a {
+        let n = limb as u64 * m as u64 + carry;
+        product.push((n % BASE) as u32);
+        carry = n / BASE;
+    }
+    while carry > 0 {
+        product.push((carry % BASE) as u32);
+        carry /= BASE;
+    }
+    product
+}
+
+/// Long division, one limb of the quotient at a time. Each limb is found
+/// by binary search, which is slow but simple.
+fn div_rem_magnitudes(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    let mut quotient = vec![0; a.len()];
+    let mut remainder: Vec<u32> = vec![];
+    for i in (0..a.len()).rev() {
+        // Each limb takes a multiplication for every step of the search.
+        if out_of_time(b.len() * 32) {
+            break;
+        }
+        remainder.insert(0, a[i]);
+        while remainder.last() == Some(&0) {
+            remainder.pop();
+        }
+
+        let (mut low, mut high) = (0, BASE as u32 - 1);
+        while low < high {
+            let mid = low + (high - low).div_ceil(2);
+            if cmp_magnitudes(&mul_small(b, mid), &remainder) == Ordering::Greater {
+                high = mid - 1;
+            } else {
+                low = mid;
+            }
+        }
+        quotient[i] = low;
+        remainder = sub_magnitudes(&remainder, &mul_small(b, low));
+    }
+    (quotient, remainder)
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::from_limbs(self.negative, add_magnitudes(&self.limbs, &other.limbs));
+        }
+        match cmp_magnitudes(&self.limbs, &other.limbs) {
+            Ordering::Less => BigInt::from_limbs(other.negative, sub_magnitudes(&other.limbs, &self.limbs)),
+            _ => BigInt::from_limbs(self.negative, sub_magnitudes(&self.limbs, &other.limbs)),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        BigInt::from_limbs(self.negative != other.negative, mul_magnitudes(&self.limbs, &other.limbs))
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::from_limbs(!self.negative, self.limbs.clone())
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_magnitudes(&self.limbs, &other.limbs),
+            (true, true) => cmp_magnitudes(&other.limbs, &self.limbs),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for BigInt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some((most, rest)) = self.limbs.split_last() else {
+            return write!(f, "0");
+        };
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", most)?;
+        for limb in rest.iter().rev() {
+            write!(f, "{:09}", limb)?;
+        }
+        Ok(())
+    }
+}
+
+impl Decimal {
+    /// How many decimal places a quotient is rounded to, unless its
+    /// operands already have more.
+    const DIVISION_SCALE: u32 = 28;
+
+    pub(crate) fn new(unscaled: BigInt, scale: u32) -> Self {
+        Self { unscaled, scale }
+    }
+
+    /// Parses an optionally signed number with an optional fractional part.
+    pub(crate) fn parse(text: &str) -> Option<Self> {
+        match text.split_once('.') {
+            Some((whole, fraction)) => {
+                if fraction.is_empty() || fraction.starts_with(['-', '+']) {
+                    return None;
+                }
+                let unscaled = BigInt::parse(&format!("{}{}", whole, fraction))?;
+                Some(Self::new(unscaled, fraction.len() as u32))
+            }
+            None => Some(Self::new(BigInt::parse(text)?, 0)),
+        }
+    }
+
+    /// The decimal written the same as `n`, if `n` is finite.
+    pub(crate) fn from_f64(n: f64) -> Option<Self> {
+        // `Display` for floats writes the shortest digits that round-trip,
+        // and never an exponent.
+        Self::parse(&n.to_string())
+    }
+
+    /// The unscaled value at a scale at least `self.scale`.
+    fn rescale(&self, scale: u32) -> BigInt {
+        &self.unscaled * &BigInt::pow10(scale - self.scale)
+    }
+
+    fn aligned(&self, other: &Decimal) -> (BigInt, BigInt, u32) {
+        let scale = self.scale.max(other.scale);
+        (self.rescale(scale), other.rescale(scale), scale)
+    }
+
+    /// The same number without trailing zeros after the decimal point.
+    pub(crate) fn normalized(&self) -> Decimal {
+        let mut decimal = self.clone();
+        let ten = BigInt::from(10);
+        while decimal.scale > 0 {
+            let (quotient, remainder) = decimal.unscaled.div_rem(&ten).unwrap();
+            if !remainder.is_zero() {
+                break;
+            }
+            decimal = Decimal::new(quotient, decimal.scale - 1);
+        }
+        decimal
+    }
+
+    /// The decimal point and what follows it removed, if it is all zeros.
+    pub(crate) fn to_bigint(&self) -> Option<BigInt> {
+        let normalized = self.normalized();
+        (normalized.scale == 0).then_some(normalized.unscaled)
+    }
+
+    /// Drops the fractional part, rounding toward zero.
+    pub(crate) fn trunc(&self) -> BigInt {
+        self.unscaled.div_rem(&BigInt::pow10(self.scale)).unwrap().0
+    }
+
+    /// The nearest float.
+    pub(crate) fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap()
+    }
+
+    /// Roughly one less than the number of digits the decimal is written
+    /// with: the base-10 logarithm of its unscaled value, or its scale if
+    /// that is larger.
+    pub(crate) fn log10(&self) -> f64 {
+        self.unscaled.log10().max(self.scale as f64)
+    }
+
+    /// Raises to `exponent`. Returns `None` when the scale of the result
+    /// would not fit in a `u32`.
+    pub(crate) fn pow(&self, exponent: u32) -> Option<Decimal> {
+        let scale = self.scale.checked_mul(exponent)?;
+        Some(Decimal::new(self.unscaled.pow(exponent), scale))
+    }
+
+    /// Divides, rounding half to even at `DIVISION_SCALE` places, then
+    /// drops trailing zeros down to the larger scale of the operands.
+    /// Returns `None` when dividing by zero.
+    pub(crate) fn div(&self, other: &Decimal) -> Option<Decimal> {
+        let min_scale = self.scale.max(other.scale);
+        let scale = min_scale.max(Self::DIVISION_SCALE);
+        let numerator = &self.unscaled * &BigInt::pow10(scale + other.scale - self.scale);
+        let (mut quotient, remainder) = numerator.div_rem(&other.unscaled)?;
+
+        let twice_remainder = &remainder * &BigInt::from(2);
+        let half = cmp_magnitudes(&twice_remainder.limbs, &other.unscaled.limbs);
+        if half == Ordering::Greater || (half == Ordering::Equal && quotient.is_odd()) {
+            let away = BigInt::from(if self.unscaled.negative != other.unscaled.negative { -1 } else { 1 });
+            quotient = &quotient + &away;
+        }
+
+        let mut decimal = Decimal::new(quotient, scale).normalized();
+        if decimal.scale < min_scale {
+            decimal = Decimal::new(decimal.rescale(min_scale), min_scale);
+        }
+        Some(decimal)
+    }
+
+    /// Divides, rounding the quotient toward negative infinity. The
+    /// quotient is a whole number, and the remainder takes the sign of
+    /// `other`. Returns `None` when dividing by zero.
+    pub(crate) fn div_floor(&self, other: &Decimal) -> Option<(Decimal, Decimal)> {
+        let (a, b, scale) = self.aligned(other);
+        let (quotient, remainder) = a.div_floor(&b)?;
+        Some((Decimal::new(quotient, 0), Decimal::new(remainder, scale)))
+    }
+}
+
+impl From<BigInt> for Decimal {
+    fn from(n: BigInt) -> Self {
+        Decimal::new(n, 0)
+    }
+}
+
+impl Add for &Decimal {
+    type Output = Decimal;
+
+    fn add(self, other: &Decimal) -> Decimal {
+        let (a, b, scale) = self.aligned(other);
+        Decimal::new(&a + &b, scale)
+    }
+}
+
+impl Sub for &Decimal {
+    type Output = Decimal;
+
+    fn sub(self, other: &Decimal) -> Decimal {
+        let (a, b, scale) = self.aligned(other);
+        Decimal::new(&a - &b, scale)
+    }
+}
+
+impl Mul for &Decimal {
+    type Output = Decimal;
+
+    fn mul(self, other: &Decimal) -> Decimal {
+        Decimal::new(&self.unscaled * &other.unscaled, self.scale + other.scale)
+    }
+}
+
+impl Neg for &Decimal {
+    type Output = Decimal;
+
+    fn neg(self) -> Decimal {
+        Decimal::new(-&self.unscaled, self.scale)
+    }
+}
+
+// Decimals compare by value: `1.10d == 1.1d`.
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (a, b, _) = self.aligned(other);
+        a.cmp(&b)
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for Decimal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let digits = BigInt::from_limbs(false, self.unscaled.limbs.clone()).to_string();
+        let scale = self.scale as usize;
+        // Pad with zeros so there is a digit before the point. A format
+        // width can't, as it is bounded well below the scales possible.
+        let padding = (scale + 1).saturating_sub(digits.len());
+        let digits = "0".repeat(padding) + &digits;
+        if self.unscaled.negative {
+            write!(f, "-")?;
+        }
+        let (whole, fraction) = digits.split_at(digits.len() - scale);
+        if scale == 0 {
+            write!(f, "{}", whole)
+        } else {
+            write!(f, "{}.{}", whole, fraction)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn big(text: &str) -> BigInt {
+        BigInt::parse(text).unwrap()
+    }
+
+    fn dec(text: &str) -> Decimal {
+        Decimal::parse(text).unwrap()
+    }
+
+    #[test]
+    fn test_bigint() {
+        let a = big("123456789012345678901234567890");
+        let b = big("-987654321098765432");
+        assert_eq!((&a + &b).to_string(), "123456789011358024580135802458");
+        assert_eq!((&b - &a).to_string(), "-123456789013333333222333333322");
+        assert_eq!((&a * &b).to_string(), "-121932631137021795212620027521140070120989178480");
+
+        let (q, r) = a.div_floor(&b).unwrap();
+        assert_eq!((q.to_string(), r.to_string()), ("-124999998861".to_string(), "-61728367271605062".to_string()));
+        assert_eq!(&(&q * &b) + &r, a);
+        assert!(a.div_rem(&BigInt::from(0)).is_none());
+
+        assert_eq!(BigInt::from(i64::MIN).to_i64(), Some(i64::MIN));
+        assert_eq!(big("9223372036854775808").to_i64(), None);
+        assert_eq!(BigInt::from_f64(2f64.powi(70)).unwrap().to_string(), "1180591620717411303424");
+    }
+
+    #[test]
+    fn test_decimal() {
+        assert_eq!((&dec("1.10") + &dec("2.205")).to_string(), "3.305");
+        assert_eq!((&dec("0.1") * &dec("-0.2")).to_string(), "-0.02");
+        assert_eq!(dec("1.10").div(&dec("2")).unwrap().to_string(), "0.55");
+        assert_eq!(dec("2").div(&dec("3")).unwrap().to_string(), "0.6666666666666666666666666667");
+        assert_eq!(dec("-7.5").div_floor(&dec("2")).unwrap().1.to_string(), "0.5");
+        assert_eq!(dec("1.10"), dec("1.1"));
+        assert_eq!(Decimal::from_f64(0.1).unwrap().to_string(), "0.1");
+
+        // Scales too large for a format width still print.
+        let tiny = dec("0.1").pow(70000).unwrap().to_string();
+        assert_eq!((tiny.len(), &tiny[..4], &tiny[tiny.len() - 2..]), (70002, "0.00", "01"));
+        assert!(dec("0.00").pow(3_000_000_000).is_none());
+    }
+}
@@ -0,0 +1,573 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use serde_json::{json, Value};
+
+use crate::debugger::{Breakpoint, Mode, Stepper, Stop};
+use crate::hook::{Hook, Pause};
+use crate::protocol::{invalid, read_message, write_message};
+use crate::{Lox, Program};
+
+/// One end of a Debug Adapter Protocol session: JSON messages, each after
+/// a `Content-Length` header. Requests are read on a thread of their own,
+/// so that they come in while the program runs.
+struct Connection {
+    requests: Receiver<io::Result<Request>>,
+    // Requests about a paused program that came while it ran, answered
+    // at its next pause.
+    pending: VecDeque<Request>,
+    output: Box<dyn Write>,
+    // The number of the last message sent.
+    seq: u64,
+    // What the front end numbers the first line and column: 0 or 1.
+    first_line: usize,
+    first_column: usize,
+}
+
+/// A front end's request, with its arguments.
+struct Request {
+    seq: u64,
+    command: String,
+    arguments: Value,
+}
+
+/// Sends the requests read from `input` until it ends, or until one
+/// can't be read.
+fn read_requests(mut input: impl BufRead, requests: Sender<io::Result<Request>>) {
+    loop {
+        let request = match read_message(&mut input) {
+            Ok(Some(message)) => match message["command"].as_str() {
+                Some(command) => Ok(Request {
+                    seq: message["seq"].as_u64().unwrap_or(0),
+                    command: command.to_string(),
+                    arguments: message["arguments"].clone(),
+                }),
+                None => Err(invalid("Expect a request.")),
+            },
+            Ok(None) => return,
+            Err(e) => Err(e),
+        };
+        let failed = request.is_err();
+        if requests.send(request).is_err() || failed {
+            return;
+        }
+    }
+}
+
+impl Connection {
+    /// Waits for the next request, the oldest put off first. Returns `None`
+    /// at the end of the input.
+    fn read(&mut self) -> io::Result<Option<Request>> {
+        if let Some(request) = self.pending.pop_front() {
+            return Ok(Some(request));
+        }
+        self.requests.recv().ok().transpose()
+    }
+
+    /// The next request if one came, without waiting for it.
+    fn poll(&mut self) -> io::Result<Option<Request>> {
+        self.requests.try_recv().ok().transpose()
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.output, &message)
+    }
+
+    /// Answers `request` with a body, or with the message it failed with.
+    fn respond(&mut self, request: &Request, result: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request.seq,
+            "command": request.command,
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    fn output(&mut self, category: &str, output: &str) -> io::Result<()> {
+        self.event("output", json!({ "category": category, "output": output }))
+    }
+}
+
+/// What a program prints, sent on as output events a line at a time.
+struct Output {
+    connection: Rc<RefCell<Connection>>,
+    line: Vec<u8>,
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        if buf.contains(&b'\n') {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.line.is_empty() {
+            let line = String::from_utf8_lossy(&self.line).into_owned();
+            self.connection.borrow_mut().output("stdout", &line)?;
+            self.line.clear();
+        }
+        Ok(())
+    }
+}
+
+/// Pauses a program for the front end, and answers its requests about the
+/// paused program until one resumes it. While the program runs, it answers
+/// those that can't wait, such as `pause`, before each statement.
+struct DapHook {
+    connection: Rc<RefCell<Connection>>,
+    stepper: Stepper,
+    disconnected: Rc<Cell<bool>>,
+}
+
+// The only thread a program has.
+const THREAD_ID: u64 = 1;
+
+/// Runs a debugging session over `input` and `output`: it configures
+/// breakpoints, launches the program, then pauses it as the front end
+/// asks. While the program runs, requests to pause it, to set breakpoints,
+/// to list threads and to disconnect are answered at once, others at its
+/// next pause.
+pub fn serve_dap(input: impl BufRead + Send + 'static, output: impl Write + 'static) -> io::Result<()> {
+    let (sender, requests) = mpsc::channel();
+    thread::spawn(move || read_requests(input, sender));
+    let connection = Rc::new(RefCell::new(Connection {
+        requests,
+        pending: VecDeque::new(),
+        output: Box::new(output),
+        seq: 0,
+        first_line: 1,
+        first_column: 1,
+    }));
+    let mut stepper = Stepper::new();
+    let mut program = None;
+    let mut stop_on_entry = false;
+
+    // Set-up goes on until the front end says it is done.
+    loop {
+        let Some(request) = connection.borrow_mut().read()? else {
+            return Ok(());
+        };
+        let result = match request.command.as_str() {
+            "initialize" => {
+                let mut connection = connection.borrow_mut();
+                let first = |key: &str| if request.arguments[key].as_bool() == Some(false) { 0 } else { 1 };
+                connection.first_line = first("linesStartAt1");
+                connection.first_column = first("columnsStartAt1");
+                Ok(json!({ "supportsConfigurationDoneRequest": true }))
+            }
+            "launch" => match request.arguments["program"].as_str() {
+                Some(path) => {
+                    program = Some(PathBuf::from(path));
+                    stop_on_entry = request.arguments["stopOnEntry"].as_bool().unwrap_or(false);
+                    Ok(json!({}))
+                }
+                None => Err("Expect the path of the program to launch.".to_string()),
+            },
+            "setBreakpoints" => Ok(set_breakpoints(&mut stepper, &request.arguments, connection.borrow().first_line)),
+            "setExceptionBreakpoints" => Ok(json!({})),
+            "threads" => Ok(threads()),
+            "configurationDone" if program.is_some() => Ok(json!({})),
+            "configurationDone" => Err("Launch a program first.".to_string()),
+            "disconnect" => {
+                return connection.borrow_mut().respond(&request, Ok(json!({})));
+            }
+            command => Err(format!("Unsupported request '{}'.", command)),
+        };
+        let done = request.command == "configurationDone" && result.is_ok();
+        connection.borrow_mut().respond(&request, result)?;
+        if request.command == "initialize" {
+            connection.borrow_mut().event("initialized", json!({}))?;
+        }
+        if done {
+            break;
+        }
+    }
+
+    if !stop_on_entry {
+        stepper.resume(Mode::Run);
+    }
+    let disconnected = Rc::new(Cell::new(false));
+    let exit_code = run(program.unwrap(), &connection, stepper, &disconnected)?;
+    if disconnected.get() {
+        return Ok(());
+    }
+
+    let mut connection = connection.borrow_mut();
+    connection.event("exited", json!({ "exitCode": exit_code }))?;
+    connection.event("terminated", json!({}))?;
+    while let Some(request) = connection.read()? {
+        match request.command.as_str() {
+            "disconnect" => return connection.respond(&request, Ok(json!({}))),
+            "threads" => connection.respond(&request, Ok(json!({ "threads": [] })))?,
+            _ => connection.respond(&request, Err("The program has ended.".to_string()))?,
+        }
+    }
+    Ok(())
+}
+
+/// Runs the program at `path` under the hook. Returns its exit code, as
+/// `Lox::run_file` would exit with.
+fn run(
+    path: PathBuf,
+    connection: &Rc<RefCell<Connection>>,
+    stepper: Stepper,
+    disconnected: &Rc<Cell<bool>>,
+) -> io::Result<i32> {
+    let source = match fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(e) => {
+            let message = format!("Can't read '{}': {}.\n", path.display(), e);
+            connection.borrow_mut().output("stderr", &message)?;
+            return Ok(66);
+        }
+    };
+
+    let mut lox = Lox::new();
+    let ast = match Lox::compile(&source, &lox.interner, Some(path)) {
+        Ok(ast) => ast,
+        Err(errors) => {
+            connection.borrow_mut().output("stderr", &(errors.join("\n") + "\n"))?;
+            return Ok(65);
+        }
+    };
+
+    lox.set_output(Output {
+        connection: connection.clone(),
+        line: vec![],
+    });
+    lox.set_hook(DapHook {
+        connection: connection.clone(),
+        stepper,
+        disconnected: disconnected.clone(),
+    });
+    match lox.try_execute(&Program { ast: Rc::new(ast) }) {
+        Ok(()) => Ok(0),
+        Err(_) if disconnected.get() => Ok(0),
+        Err(e) => {
+            connection.borrow_mut().output("stderr", &format!("{}\n", e))?;
+            Ok(70)
+        }
+    }
+}
+
+/// Replaces the breakpoints of one file, answering with where they are.
+/// The front end numbers lines from `first_line`.
+fn set_breakpoints(stepper: &mut Stepper, arguments: &Value, first_line: usize) -> Value {
+    let file = arguments["source"]["path"].as_str().map(PathBuf::from);
+    let lines: Vec<usize> = match arguments["breakpoints"].as_array() {
+        Some(breakpoints) => breakpoints.iter().filter_map(|b| b["line"].as_u64()).map(|line| line as usize).collect(),
+        None => vec![],
+    };
+
+    let cleared = Breakpoint::new(file.clone(), 0).file;
+    stepper.breakpoints.retain(|breakpoint| breakpoint.file != cleared);
+    let breakpoints: Vec<_> = lines
+        .iter()
+        .map(|&line| match (line + 1).checked_sub(first_line).filter(|&line| line > 0) {
+            Some(line) => {
+                stepper.breakpoints.push(Breakpoint::new(file.clone(), line));
+                json!({ "verified": true, "line": line + first_line - 1 })
+            }
+            None => json!({ "verified": false, "line": line, "message": "There is no such line." }),
+        })
+        .collect();
+    json!({ "breakpoints": breakpoints })
+}
+
+fn threads() -> Value {
+    json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })
+}
+
+impl DapHook {
+    /// Answers a request made while paused. Returns how to go on if the
+    /// request resumes the program.
+    fn handle(&mut self, request: &Request, pause: &mut Pause) -> io::Result<Option<ControlFlow<()>>> {
+        let mut resume = None;
+        let result = match request.command.as_str() {
+            "threads" => Ok(threads()),
+            "pause" => Ok(json!({})),
+            "stackTrace" => Ok(stack_trace(pause, &self.connection.borrow())),
+            "scopes" => {
+                // Each frame has two scopes, numbered from 1 up.
+                let frame = request.arguments["frameId"].as_u64().unwrap_or(0);
+                Ok(json!({ "scopes": [
+                    { "name": "Locals", "variablesReference": 2 * frame + 1, "expensive": false },
+                    { "name": "Globals", "variablesReference": 2 * frame + 2, "expensive": false },
+                ]}))
+            }
+            "variables" => variables(pause, request.arguments["variablesReference"].as_u64().unwrap_or(0)),
+            "evaluate" => match request.arguments["frameId"].as_u64().unwrap_or(0) {
+                0 => {
+                    let expression = request.arguments["expression"].as_str().unwrap_or_default();
+                    let source = if expression.ends_with([';', '}']) { expression.to_string() } else { format!("{};", expression) };
+                    pause.evaluate(&source).map(|value| json!({ "result": value, "variablesReference": 0 }))
+                }
+                _ => Err("Can only evaluate in the innermost frame.".to_string()),
+            },
+            "setBreakpoints" => {
+                let first_line = self.connection.borrow().first_line;
+                Ok(set_breakpoints(&mut self.stepper, &request.arguments, first_line))
+            }
+            "continue" | "next" | "stepIn" | "stepOut" => {
+                let mode = match request.command.as_str() {
+                    "continue" => Mode::Run,
+                    "next" => Mode::StepOver(pause.depth()),
+                    "stepIn" => Mode::StepIn,
+                    _ => Mode::StepOut(pause.depth()),
+                };
+                self.stepper.resume(mode);
+                resume = Some(ControlFlow::Continue(()));
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "disconnect" => {
+                self.disconnected.set(true);
+                resume = Some(ControlFlow::Break(()));
+                Ok(json!({}))
+            }
+            command => Err(format!("Unsupported request '{}'.", command)),
+        };
+        self.connection.borrow_mut().respond(request, result)?;
+        Ok(resume)
+    }
+
+    /// Answers the requests that came while the program ran, putting off
+    /// those about a paused program. Returns whether the front end asked to
+    /// pause it, or breaks if it disconnected.
+    fn poll(&mut self) -> io::Result<ControlFlow<(), bool>> {
+        let mut paused = false;
+        loop {
+            let Some(request) = self.connection.borrow_mut().poll()? else {
+                return Ok(ControlFlow::Continue(paused));
+            };
+            let result = match request.command.as_str() {
+                "pause" => {
+                    paused = true;
+                    Ok(json!({}))
+                }
+                "continue" => Ok(json!({ "allThreadsContinued": true })),
+                "setBreakpoints" => {
+                    let first_line = self.connection.borrow().first_line;
+                    Ok(set_breakpoints(&mut self.stepper, &request.arguments, first_line))
+                }
+                "threads" => Ok(threads()),
+                "disconnect" => {
+                    self.disconnected.set(true);
+                    self.connection.borrow_mut().respond(&request, Ok(json!({})))?;
+                    return Ok(ControlFlow::Break(()));
+                }
+                _ => {
+                    self.connection.borrow_mut().pending.push_back(request);
+                    continue;
+                }
+            };
+            self.connection.borrow_mut().respond(&request, result)?;
+        }
+    }
+
+    fn pause(&mut self, stop: Stop, pause: &mut Pause) -> io::Result<ControlFlow<()>> {
+        let reason = match stop {
+            Stop::Entry => "entry",
+            Stop::Step => "step",
+            Stop::Breakpoint => "breakpoint",
+            Stop::Pause => "pause",
+        };
+        let body = json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true });
+        self.connection.borrow_mut().event("stopped", body)?;
+
+        loop {
+            let request = self.connection.borrow_mut().read()?;
+            let Some(request) = request else {
+                self.disconnected.set(true);
+                return Ok(ControlFlow::Break(()));
+            };
+            if let Some(flow) = self.handle(&request, pause)? {
+                return Ok(flow);
+            }
+        }
+    }
+}
+
+/// The calls running, numbering lines and columns as `connection` does.
+fn stack_trace(pause: &Pause, connection: &Connection) -> Value {
+    let frames: Vec<_> = pause
+        .stack()
+        .into_iter()
+        .enumerate()
+        .map(|(id, frame)| {
+            let line = frame.line + connection.first_line - 1;
+            let mut json = json!({ "id": id, "name": frame.function, "line": line, "column": connection.first_column });
+            if let Some(path) = &frame.path {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                json["source"] = json!({ "name": name, "path": path.display().to_string() });
+            }
+            json
+        })
+        .collect();
+    json!({ "stackFrames": frames, "totalFrames": frames.len() })
+}
+
+/// The variables of the scope numbered `reference` by a `scopes` answer.
+fn variables(pause: &Pause, reference: u64) -> Result<Value, String> {
+    let frame = reference.checked_sub(1).ok_or("Unknown variables reference.")? / 2;
+    let mut scopes = pause.scopes(frame as usize).ok_or("Unknown variables reference.")?;
+    let globals = scopes.pop().unwrap_or_default();
+    let variables = if reference.is_multiple_of(2) { globals } else { scopes.concat() };
+
+    let variables: Vec<_> = variables
+        .into_iter()
+        .map(|(name, value)| json!({ "name": name, "value": value, "variablesReference": 0 }))
+        .collect();
+    Ok(json!({ "variables": variables }))
+}
+
+impl Hook for DapHook {
+    fn before_statement(&mut self, pause: &mut Pause) -> ControlFlow<()> {
+        // A broken connection leaves no one to debug for.
+        let paused = match self.poll() {
+            Ok(ControlFlow::Continue(paused)) => paused,
+            Ok(ControlFlow::Break(())) => return ControlFlow::Break(()),
+            Err(_) => {
+                self.disconnected.set(true);
+                return ControlFlow::Break(());
+            }
+        };
+        let Some(stop) = self.stepper.stop(pause).or(paused.then_some(Stop::Pause)) else {
+            return ControlFlow::Continue(());
+        };
+        self.pause(stop, pause).unwrap_or_else(|_| {
+            self.disconnected.set(true);
+            ControlFlow::Break(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{pipe, BufReader, PipeWriter};
+    use std::thread::JoinHandle;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::test_support::{read_fixture, Transcript};
+
+    /// A front end driving a server on another thread. Like an editor, it
+    /// waits for the answer to each request before sending the next.
+    struct Client {
+        input: PipeWriter,
+        transcript: Transcript,
+        server: JoinHandle<()>,
+        seq: u64,
+    }
+
+    fn is_stop(message: &Value) -> bool {
+        message["event"] == "stopped" || message["event"] == "terminated"
+    }
+
+    impl Client {
+        fn start() -> Self {
+            let (reader, input) = pipe().unwrap();
+            let transcript = Transcript::default();
+            let output = transcript.clone();
+            let server = thread::spawn(move || serve_dap(BufReader::new(reader), output).unwrap());
+            Self { input, transcript, server, seq: 0 }
+        }
+
+        fn count(&self, matches: impl Fn(&Value) -> bool) -> usize {
+            self.transcript.messages().iter().filter(|&message| matches(message)).count()
+        }
+
+        /// Waits for the `n`th message that `matches`, counting from 1.
+        fn wait_for(&self, n: usize, matches: impl Fn(&Value) -> bool) -> Value {
+            let start = Instant::now();
+            loop {
+                if let Some(message) = self.transcript.messages().into_iter().filter(&matches).nth(n - 1) {
+                    return message;
+                }
+                assert!(start.elapsed() < Duration::from_secs(10), "The server stopped answering.");
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+
+        /// Sends `request` and waits for its response.
+        fn send(&mut self, request: &Value) -> Value {
+            write_message(&mut self.input, request).unwrap();
+            let seq = request["seq"].clone();
+            self.wait_for(1, |message| message["type"] == "response" && message["request_seq"] == seq)
+        }
+
+        fn request(&mut self, command: &str, arguments: Value) -> Value {
+            self.seq += 1;
+            self.send(&json!({ "seq": self.seq, "type": "request", "command": command, "arguments": arguments }))
+        }
+
+        /// Ends the input, and returns all the server sent once it is done.
+        fn finish(self) -> Vec<Value> {
+            drop(self.input);
+            self.server.join().unwrap();
+            self.transcript.messages()
+        }
+    }
+
+    #[test]
+    fn test_dap() {
+        let mut client = Client::start();
+        for request in read_fixture("fixtures/dap/session.requests.json") {
+            let stops = client.count(is_stop);
+            let response = client.send(&request);
+            // Ask about a resumed program once it stopped again.
+            let resumed = ["configurationDone", "continue", "next", "stepIn", "stepOut"].contains(&request["command"].as_str().unwrap());
+            if resumed && response["success"] == true {
+                client.wait_for(stops + 1, is_stop);
+            }
+        }
+        assert_eq!(client.finish(), read_fixture("fixtures/dap/session.expected.json"));
+    }
+
+    #[test]
+    fn test_requests_while_running() {
+        let mut client = Client::start();
+        let zero_based = json!({ "adapterID": "primox", "linesStartAt1": false, "columnsStartAt1": false });
+        client.request("initialize", zero_based);
+        client.request("launch", json!({ "program": "fixtures/dap/loop.lox" }));
+        client.request("configurationDone", json!({}));
+
+        // The loop runs until a breakpoint set while it runs, on the third
+        // line, named from zero and through another path to the file.
+        let source = json!({ "path": "fixtures/../fixtures/dap/loop.lox" });
+        let response = client.request("setBreakpoints", json!({ "source": source, "breakpoints": [{ "line": 2 }] }));
+        assert_eq!(response["body"]["breakpoints"], json!([{ "verified": true, "line": 2 }]));
+        assert_eq!(client.wait_for(1, is_stop)["body"]["reason"], "breakpoint");
+        let response = client.request("stackTrace", json!({ "threadId": THREAD_ID }));
+        let frame = &response["body"]["stackFrames"][0];
+        assert_eq!((&frame["line"], &frame["column"]), (&json!(2), &json!(0)));
+
+        // Without breakpoints, it runs until paused.
+        client.request("setBreakpoints", json!({ "source": source, "breakpoints": [] }));
+        client.request("continue", json!({ "threadId": THREAD_ID }));
+        assert_eq!(client.request("pause", json!({ "threadId": THREAD_ID }))["success"], true);
+        assert_eq!(client.wait_for(2, is_stop)["body"]["reason"], "pause");
+        client.request("disconnect", json!({}));
+        client.finish();
+    }
+}
@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+
+use crate::hook::{Hook, Pause};
+
+const HELP: &str = "\
+Commands, with their short forms:
+  step (s)            run to the next line, entering calls
+  next (n)            run to the next line in this call or its callers
+  out (o)             run until this call returns
+  continue (c)        run to the next breakpoint
+  break (b) [FILE:]N  pause at line N, of FILE if given
+  delete (d) [FILE:]N remove a breakpoint
+  backtrace (bt)      show the calls running
+  locals (l)          show the variables of this call
+  globals (g)         show the global variables
+  print (p) EXPR      evaluate EXPR here
+  quit (q)            stop the program";
+
+/// A command-line debugger. It pauses before the first line of a program,
+/// then wherever a breakpoint or a step command says, and reads commands
+/// from `input` while paused.
+pub struct Debugger<R, W> {
+    input: R,
+    output: W,
+    stepper: Stepper,
+    // The lines of the files paused in, to show where.
+    sources: HashMap<PathBuf, Vec<String>>,
+}
+
+/// Decides where a program pauses: before its first line, at breakpoints,
+/// and wherever the last step command says. Debuggers share it.
+pub(crate) struct Stepper {
+    pub(crate) breakpoints: Vec<Breakpoint>,
+    mode: Mode,
+    // The statement seen last. A line only pauses at its first statement,
+    // or when a loop comes back to it.
+    last: Option<Location>,
+    // The canonical form of each path paused in, to match breakpoints.
+    canonical: HashMap<PathBuf, PathBuf>,
+}
+
+/// Where to pause: a line, of the given file or of any. The file is kept
+/// canonical when it exists, so that other ways to name it match.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Breakpoint {
+    pub(crate) file: Option<PathBuf>,
+    pub(crate) line: usize,
+}
+
+#[derive(Debug, PartialEq)]
+struct Location {
+    depth: usize,
+    path: Option<PathBuf>,
+    line: usize,
+    offset: usize,
+}
+
+/// How far to run before pausing again, besides at breakpoints. Steps
+/// over and out of calls record the depth they started at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Mode {
+    Run,
+    StepIn,
+    StepOver(usize),
+    StepOut(usize),
+}
+
+/// Why a program paused.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Stop {
+    Entry,
+    Step,
+    Breakpoint,
+    /// The front end asked for it.
+    Pause,
+}
+
+impl Breakpoint {
+    pub(crate) fn new(file: Option<PathBuf>, line: usize) -> Self {
+        let file = file.map(|file| fs::canonicalize(&file).unwrap_or(file));
+        Self { file, line }
+    }
+}
+
+impl Stepper {
+    pub(crate) fn new() -> Self {
+        Self {
+            breakpoints: vec![],
+            mode: Mode::StepIn,
+            last: None,
+            canonical: HashMap::new(),
+        }
+    }
+
+    /// Runs on until the next pause that `mode` asks for.
+    pub(crate) fn resume(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    /// Whether the program pauses before the statement at `pause`, and why.
+    pub(crate) fn stop(&mut self, pause: &Pause) -> Option<Stop> {
+        let location = Location {
+            depth: pause.depth(),
+            path: pause.path().map(Path::to_path_buf),
+            line: pause.line(),
+            offset: pause.offset(),
+        };
+        let new_line = match &self.last {
+            Some(last) => {
+                (last.depth, &last.path, last.line) != (location.depth, &location.path, location.line)
+                    || location.offset <= last.offset
+            }
+            None => true,
+        };
+        let stepped = match self.mode {
+            Mode::Run => false,
+            Mode::StepIn => true,
+            Mode::StepOver(depth) => location.depth <= depth,
+            Mode::StepOut(depth) => location.depth < depth,
+        };
+
+        let stop = if !new_line {
+            None
+        } else if stepped {
+            Some(if self.last.is_none() { Stop::Entry } else { Stop::Step })
+        } else if self.at_breakpoint(&location) {
+            Some(Stop::Breakpoint)
+        } else {
+            None
+        };
+        self.last = Some(location);
+        stop
+    }
+
+    fn at_breakpoint(&mut self, location: &Location) -> bool {
+        let canonical = &mut self.canonical;
+        self.breakpoints.iter().any(|breakpoint| {
+            if breakpoint.line != location.line {
+                return false;
+            }
+            match (&breakpoint.file, &location.path) {
+                (None, _) => true,
+                // A file that can't be found, such as one named without
+                // its directory, matches the end of the path.
+                (Some(file), Some(path)) => {
+                    let path = canonical
+                        .entry(path.clone())
+                        .or_insert_with(|| fs::canonicalize(path).unwrap_or_else(|_| path.clone()));
+                    path == file || path.ends_with(file)
+                }
+                (Some(_), None) => false,
+            }
+        })
+    }
+}
+
+impl<R: BufRead, W: Write> Debugger<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self {
+            input,
+            output,
+            stepper: Stepper::new(),
+            sources: HashMap::new(),
+        }
+    }
+
+    fn show_location(&mut self, pause: &Pause) -> io::Result<()> {
+        let line = pause.line();
+        let Some(path) = pause.path() else {
+            return writeln!(self.output, "Paused at line {}", line);
+        };
+
+        writeln!(self.output, "Paused at {}:{}", path.display(), line)?;
+        let lines = self.sources.entry(path.to_path_buf()).or_insert_with(|| {
+            let source = fs::read_to_string(path).unwrap_or_default();
+            source.lines().map(str::to_string).collect()
+        });
+        match lines.get(line - 1) {
+            Some(text) => writeln!(self.output, "{:>4} | {}", line, text),
+            None => Ok(()),
+        }
+    }
+
+    /// Reads and runs commands until one resumes or stops the program.
+    fn prompt(&mut self, pause: &mut Pause) -> io::Result<ControlFlow<()>> {
+        loop {
+            write!(self.output, "(debug) ")?;
+            self.output.flush()?;
+            let mut command = String::new();
+            if self.input.read_line(&mut command)? == 0 {
+                return Ok(ControlFlow::Break(()));
+            }
+            let command = command.trim();
+            let (name, argument) = command.split_once(' ').unwrap_or((command, ""));
+            let argument = argument.trim();
+
+            match name {
+                "" => {}
+                "s" | "step" => {
+                    self.stepper.resume(Mode::StepIn);
+                    return Ok(ControlFlow::Continue(()));
+                }
+                "n" | "next" => {
+                    self.stepper.resume(Mode::StepOver(pause.depth()));
+                    return Ok(ControlFlow::Continue(()));
+                }
+                "o" | "out" => {
+                    self.stepper.resume(Mode::StepOut(pause.depth()));
+                    return Ok(ControlFlow::Continue(()));
+                }
+                "c" | "continue" => {
+                    self.stepper.resume(Mode::Run);
+                    return Ok(ControlFlow::Continue(()));
+                }
+                "b" | "break" | "d" | "delete" => match parse_breakpoint(argument) {
+                    Some(breakpoint) if name.starts_with('b') => {
+                        if !self.stepper.breakpoints.contains(&breakpoint) {
+                            self.stepper.breakpoints.push(breakpoint);
+                        }
+                    }
+                    Some(breakpoint) => self.stepper.breakpoints.retain(|other| *other != breakpoint),
+                    None => writeln!(self.output, "Expect a line number, as in '{} 12' or '{} util.lox:12'.", name, name)?,
+                },
+                "bt" | "backtrace" => {
+                    for frame in pause.stack() {
+                        writeln!(self.output, "{}", frame)?;
+                    }
+                }
+                "l" | "locals" | "g" | "globals" => {
+                    let mut scopes = pause.scopes(0).unwrap_or_default();
+                    let globals = scopes.pop().unwrap_or_default();
+                    let variables = if name.starts_with('g') { globals } else { scopes.concat() };
+                    for (name, value) in variables {
+                        writeln!(self.output, "{} = {}", name, value)?;
+                    }
+                }
+                "p" | "print" => {
+                    let source = if argument.ends_with([';', '}']) { argument.to_string() } else { format!("{};", argument) };
+                    match pause.evaluate(&source) {
+                        Ok(value) => writeln!(self.output, "{}", value)?,
+                        Err(message) => writeln!(self.output, "{}", message)?,
+                    }
+                }
+                "q" | "quit" => return Ok(ControlFlow::Break(())),
+                "h" | "help" => writeln!(self.output, "{}", HELP)?,
+                _ => writeln!(self.output, "Unknown command '{}'. Type 'help' for a list.", name)?,
+            }
+        }
+    }
+}
+
+/// Reads `[FILE:]LINE`.
+fn parse_breakpoint(argument: &str) -> Option<Breakpoint> {
+    let (file, line) = match argument.rsplit_once(':') {
+        Some((file, line)) => (Some(Path::new(file).to_path_buf()), line),
+        None => (None, argument),
+    };
+    let line = line.parse().ok().filter(|&line| line > 0)?;
+    Some(Breakpoint::new(file, line))
+}
+
+impl<R: BufRead, W: Write> Hook for Debugger<R, W> {
+    fn before_statement(&mut self, pause: &mut Pause) -> ControlFlow<()> {
+        if self.stepper.stop(pause).is_none() {
+            return ControlFlow::Continue(());
+        }
+        // Without a working terminal, there is no one to debug for.
+        let result = self.show_location(pause).and_then(|()| self.prompt(pause));
+        result.unwrap_or(ControlFlow::Break(()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::test_support::Transcript;
+    use crate::Lox;
+
+    #[test]
+    fn test_debugger() {
+        let source = "fun add(a, b) {\n  var sum = a + b;\n  { return sum; }\n}\nvar x = 1;\nvar y = add(x, 2);\nvar z = y;\n";
+        let commands = "b 3\nc\nl\nbt\np sum * 10\np nope\no\ng\nn\n";
+        let transcript = Transcript::default();
+
+        let mut lox = Lox::new();
+        lox.set_hook(Debugger::new(Cursor::new(commands), transcript.clone()));
+        let program = lox.parse(source).unwrap();
+        assert!(lox.try_execute(&program).is_ok());
+
+        let expected = "\
+Paused at line 1
+(debug) (debug) Paused at line 3
+(debug) a = 1
+b = 2
+sum = 3
+(debug) [line 3] in add()
+[line 6] in script
+(debug) 30
+(debug) Undefined variable 'nope'.
+(debug) Paused at line 7
+(debug) add = <fn add>
+x = 1
+y = 3
+(debug) ";
+        assert_eq!(String::from_utf8(transcript.contents()).unwrap(), expected);
+    }
+}
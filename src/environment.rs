@@ -0,0 +1,110 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::interner::Symbol;
+use crate::value::Value;
+
+/// Variable bindings of one scope, keyed on interned names. Lookups that
+/// miss fall through to the enclosing scope, ending at the globals.
+#[derive(Default)]
+pub(crate) struct Environment {
+    values: HashMap<Symbol, Value>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            values: HashMap::new(),
+            enclosing: Some(enclosing),
+        }
+    }
+
+    pub(crate) fn define(&mut self, name: Symbol, value: Value) {
+        self.values.insert(name, value);
+    }
+
+    pub(crate) fn get(&self, name: Symbol) -> Option<Value> {
+        match self.values.get(&name) {
+            Some(value) => Some(value.clone()),
+            None => self.enclosing.as_ref()?.borrow().get(name),
+        }
+    }
+
+    /// Looks a variable up in the scope `distance` out from this one, as
+    /// the resolver found it.
+    pub(crate) fn get_at(&self, distance: usize, name: Symbol) -> Option<Value> {
+        match distance {
+            0 => self.get_local(name),
+            _ => self.enclosing.as_ref()?.borrow().get_at(distance - 1, name),
+        }
+    }
+
+    /// Looks a variable up in the globals, the outermost scope but for the
+    /// builtins, and then in the builtins.
+    pub(crate) fn get_global(&self, name: Symbol) -> Option<Value> {
+        match &self.enclosing {
+            Some(enclosing) if enclosing.borrow().enclosing.is_some() => enclosing.borrow().get_global(name),
+            _ => self.get(name),
+        }
+    }
+
+    /// Looks a variable up in this scope only.
+    pub(crate) fn get_local(&self, name: Symbol) -> Option<Value> {
+        self.values.get(&name).cloned()
+    }
+
+    /// The variables of this scope only, in no particular order.
+    pub(crate) fn bindings(&self) -> impl Iterator<Item = (Symbol, &Value)> {
+        self.values.iter().map(|(&name, value)| (name, value))
+    }
+
+    pub(crate) fn enclosing(&self) -> Option<Rc<RefCell<Environment>>> {
+        self.enclosing.clone()
+    }
+
+    /// Rebinds an existing variable in the scope `distance` out from this
+    /// one. Returns `false` if that scope does not define it.
+    pub(crate) fn assign_at(&mut self, distance: usize, name: Symbol, value: Value) -> bool {
+        match (distance, &self.enclosing) {
+            (0, _) => match self.values.get_mut(&name) {
+                Some(slot) => {
+                    *slot = value;
+                    true
+                }
+                None => false,
+            },
+            (_, Some(enclosing)) => enclosing.borrow_mut().assign_at(distance - 1, name, value),
+            (_, None) => false,
+        }
+    }
+
+    /// Rebinds an existing global, or builtin. Returns `false` if there is
+    /// none.
+    pub(crate) fn assign_global(&mut self, name: Symbol, value: Value) -> bool {
+        match &self.enclosing {
+            Some(enclosing) if enclosing.borrow().enclosing.is_some() => enclosing.borrow_mut().assign_global(name, value),
+            _ => self.assign(name, value),
+        }
+    }
+
+    /// Rebinds an existing variable in the innermost scope that defines it.
+    /// Returns `false` if no scope does.
+    pub(crate) fn assign(&mut self, name: Symbol, value: Value) -> bool {
+        match self.values.get_mut(&name) {
+            Some(slot) => {
+                *slot = value;
+                true
+            }
+            None => match &self.enclosing {
+                Some(enclosing) => enclosing.borrow_mut().assign(name, value),
+                None => false,
+            },
+        }
+    }
+}
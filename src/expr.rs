@@ -1,25 +1,105 @@
+use crate::ast::{Ast, ExprId, Span, StmtId};
+use crate::interner::Symbol;
 use crate::token::{LiteralToken, Token};
+use crate::token_type::TokenType;
 use derive_new::new;
 
 #[derive(Debug)]
 pub(crate) enum Expr {
-    Binary(Box<Binary>),
-    Grouping(Box<Grouping>),
-    Literal(Box<Literal>),
-    Ternary(Box<Ternary>),
-    Unary(Box<Unary>),
+    Assign(Assign),
+    Binary(Binary),
+    Call(Call),
+    Get(Get),
+    Grouping(Grouping),
+    Lambda(Lambda),
+    List(List),
+    Literal(Literal),
+    Logical(Logical),
+    Map(Map),
+    Set(Set),
+    SetSubscript(SetSubscript),
+    Subscript(Subscript),
+    Template(Template),
+    Ternary(Ternary),
+    Unary(Unary),
+    Update(Update),
+    Variable(Variable),
+}
+
+/// An operator as kept in the tree. Unlike a `Token`, it does not borrow
+/// from the source, so the tree can outlive the text it was parsed from.
+#[derive(new, Debug, Clone, Copy)]
+pub(crate) struct Operator {
+    pub(crate) ty: TokenType,
+    pub(crate) span: Span,
+}
+
+impl From<Token<'_>> for Operator {
+    fn from(token: Token<'_>) -> Self {
+        Self::new(token.ty, token.span())
+    }
+}
+
+/// A name as kept in the tree: its interned symbol and where it appeared.
+#[derive(new, Debug, Clone, Copy)]
+pub(crate) struct Identifier {
+    pub(crate) symbol: Symbol,
+    pub(crate) span: Span,
+}
+
+impl From<Token<'_>> for Identifier {
+    /// The token must be an `IDENTIFIER`.
+    fn from(token: Token<'_>) -> Self {
+        Self::new(token.symbol.unwrap(), token.span())
+    }
+}
+
+/// An assignment to a variable: `name = value`. A compound assignment such
+/// as `name += value` keeps the binary operator it applies, `+` here.
+#[derive(new, Debug)]
+pub(crate) struct Assign {
+    pub(crate) name: Identifier,
+    pub(crate) operator: Option<Operator>,
+    pub(crate) value: ExprId,
 }
 
 #[derive(new, Debug)]
 pub(crate) struct Binary {
-    pub(crate) left: Expr,
-    pub(crate) operator: Token,
-    pub(crate) right: Expr,
+    pub(crate) left: ExprId,
+    pub(crate) operator: Operator,
+    pub(crate) right: ExprId,
+}
+
+#[derive(new, Debug)]
+pub(crate) struct Call {
+    pub(crate) callee: ExprId,
+    pub(crate) arguments: Vec<ExprId>,
+}
+
+/// A property access: `object.name`. Only the methods of built-in values
+/// are properties so far.
+#[derive(new, Debug)]
+pub(crate) struct Get {
+    pub(crate) object: ExprId,
+    pub(crate) name: Identifier,
 }
 
 #[derive(new, Debug)]
 pub(crate) struct Grouping {
-    pub(crate) expression: Expr,
+    pub(crate) expression: ExprId,
+}
+
+/// A function without a name. Both `fun (a) { ... }` and the arrow form
+/// `(a) => expr` parse to one; the arrow form's body is a single `return`.
+#[derive(new, Debug)]
+pub(crate) struct Lambda {
+    pub(crate) params: Vec<Identifier>,
+    pub(crate) body: Vec<StmtId>,
+}
+
+#[derive(new, Debug)]
+pub(crate) struct List {
+    pub(crate) elements: Vec<ExprId>,
 }
 
 #[derive(new, Debug)]
@@ -27,45 +107,389 @@ pub(crate) struct Literal {
     pub(crate) value: LiteralToken,
 }
 
+/// An `and` or `or`, which only evaluates its right operand when the left
+/// one does not decide the result.
+#[derive(new, Debug)]
+pub(crate) struct Logical {
+    pub(crate) left: ExprId,
+    pub(crate) operator: Operator,
+    pub(crate) right: ExprId,
+}
+
+/// A map literal. Its keys are evaluated like any other expression.
+#[derive(new, Debug)]
+pub(crate) struct Map {
+    pub(crate) entries: Vec<(ExprId, ExprId)>,
+}
+
+/// An assignment to a property: `object.name = value`, or a compound one
+/// like `Assign`. Only the globals of a module can be set so far.
+#[derive(new, Debug)]
+pub(crate) struct Set {
+    pub(crate) object: ExprId,
+    pub(crate) name: Identifier,
+    pub(crate) operator: Option<Operator>,
+    pub(crate) value: ExprId,
+}
+
+/// An assignment to an element: `object[index] = value`, or a compound
+/// one like `Assign`.
+#[derive(new, Debug)]
+pub(crate) struct SetSubscript {
+    pub(crate) object: ExprId,
+    pub(crate) index: ExprId,
+    pub(crate) operator: Option<Operator>,
+    pub(crate) value: ExprId,
+}
+
+/// An element access: `object[index]`.
+#[derive(new, Debug)]
+pub(crate) struct Subscript {
+    pub(crate) object: ExprId,
+    pub(crate) index: ExprId,
+}
+
+/// A string with interpolated expressions, `"a ${b} c"`. Its parts are the
+/// string literals between the expressions and the expressions themselves,
+/// joined in order.
+#[derive(new, Debug)]
+pub(crate) struct Template {
+    pub(crate) parts: Vec<ExprId>,
+}
+
 #[derive(new, Debug)]
 pub(crate) struct Ternary {
-    pub(crate) left: Expr,
-    pub(crate) mid: Expr,
-    pub(crate) right: Expr,
+    pub(crate) left: ExprId,
+    pub(crate) mid: ExprId,
+    pub(crate) right: ExprId,
 }
 
 #[derive(new, Debug)]
 pub(crate) struct Unary {
-    pub(crate) operator: Token,
-    pub(crate) right: Expr,
+    pub(crate) operator: Operator,
+    pub(crate) right: ExprId,
+}
+
+/// An increment or decrement of a variable or an element: `++target` or
+/// `target--`. The prefix form evaluates to the new value, the postfix form
+/// to the old one.
+#[derive(new, Debug)]
+pub(crate) struct Update {
+    pub(crate) target: ExprId,
+    pub(crate) operator: Operator,
+    pub(crate) prefix: bool,
+}
+
+#[derive(new, Debug)]
+pub(crate) struct Variable {
+    pub(crate) name: Identifier,
+}
+
+impl From<Assign> for Expr {
+    fn from(e: Assign) -> Self {
+        Self::Assign(e)
+    }
 }
 
 impl From<Binary> for Expr {
     fn from(e: Binary) -> Self {
-        Self::Binary(Box::new(e))
+        Self::Binary(e)
+    }
+}
+
+impl From<Call> for Expr {
+    fn from(e: Call) -> Self {
+        Self::Call(e)
+    }
+}
+
+impl From<Get> for Expr {
+    fn from(e: Get) -> Self {
+        Self::Get(e)
     }
 }
 
 impl From<Grouping> for Expr {
     fn from(e: Grouping) -> Self {
-        Self::Grouping(Box::new(e))
+        Self::Grouping(e)
+    }
+}
+
+impl From<Lambda> for Expr {
+    fn from(e: Lambda) -> Self {
+        Self::Lambda(e)
+    }
+}
+
+impl From<List> for Expr {
+    fn from(e: List) -> Self {
+        Self::List(e)
     }
 }
 
 impl From<Literal> for Expr {
     fn from(e: Literal) -> Self {
-        Self::Literal(Box::new(e))
+        Self::Literal(e)
+    }
+}
+
+impl From<Logical> for Expr {
+    fn from(e: Logical) -> Self {
+        Self::Logical(e)
+    }
+}
+
+impl From<Map> for Expr {
+    fn from(e: Map) -> Self {
+        Self::Map(e)
+    }
+}
+
+impl From<Set> for Expr {
+    fn from(e: Set) -> Self {
+        Self::Set(e)
+    }
+}
+
+impl From<SetSubscript> for Expr {
+    fn from(e: SetSubscript) -> Self {
+        Self::SetSubscript(e)
+    }
+}
+
+impl From<Subscript> for Expr {
+    fn from(e: Subscript) -> Self {
+        Self::Subscript(e)
+    }
+}
+
+impl From<Template> for Expr {
+    fn from(e: Template) -> Self {
+        Self::Template(e)
     }
 }
 
 impl From<Ternary> for Expr {
     fn from(e: Ternary) -> Self {
-        Self::Ternary(Box::new(e))
+        Self::Ternary(e)
     }
 }
 
 impl From<Unary> for Expr {
     fn from(e: Unary) -> Self {
-        Self::Unary(Box::new(e))
+        Self::Unary(e)
+    }
+}
+
+impl From<Update> for Expr {
+    fn from(e: Update) -> Self {
+        Self::Update(e)
+    }
+}
+
+impl From<Variable> for Expr {
+    fn from(e: Variable) -> Self {
+        Self::Variable(e)
+    }
+}
+
+/// Walks expressions by shared reference, producing an `R` for each node.
+/// Each method receives the tree, the node's id, and the node itself.
+pub(crate) trait ExprVisitor<R> {
+    fn visit_assign_expr(&mut self, ast: &Ast, id: ExprId, expr: &Assign) -> R;
+    fn visit_binary_expr(&mut self, ast: &Ast, id: ExprId, expr: &Binary) -> R;
+    fn visit_call_expr(&mut self, ast: &Ast, id: ExprId, expr: &Call) -> R;
+    fn visit_get_expr(&mut self, ast: &Ast, id: ExprId, expr: &Get) -> R;
+    fn visit_grouping_expr(&mut self, ast: &Ast, id: ExprId, expr: &Grouping) -> R;
+    fn visit_lambda_expr(&mut self, ast: &Ast, id: ExprId, expr: &Lambda) -> R;
+    fn visit_list_expr(&mut self, ast: &Ast, id: ExprId, expr: &List) -> R;
+    fn visit_literal_expr(&mut self, ast: &Ast, id: ExprId, expr: &Literal) -> R;
+    fn visit_logical_expr(&mut self, ast: &Ast, id: ExprId, expr: &Logical) -> R;
+    fn visit_map_expr(&mut self, ast: &Ast, id: ExprId, expr: &Map) -> R;
+    fn visit_set_expr(&mut self, ast: &Ast, id: ExprId, expr: &Set) -> R;
+    fn visit_set_subscript_expr(&mut self, ast: &Ast, id: ExprId, expr: &SetSubscript) -> R;
+    fn visit_subscript_expr(&mut self, ast: &Ast, id: ExprId, expr: &Subscript) -> R;
+    fn visit_template_expr(&mut self, ast: &Ast, id: ExprId, expr: &Template) -> R;
+    fn visit_ternary_expr(&mut self, ast: &Ast, id: ExprId, expr: &Ternary) -> R;
+    fn visit_unary_expr(&mut self, ast: &Ast, id: ExprId, expr: &Unary) -> R;
+    fn visit_update_expr(&mut self, ast: &Ast, id: ExprId, expr: &Update) -> R;
+    fn visit_variable_expr(&mut self, ast: &Ast, id: ExprId, expr: &Variable) -> R;
+}
+
+/// Walks expressions with mutable access to the tree, for passes that
+/// rewrite it in place (e.g. constant folding). Each method receives the id
+/// of a node of the matching variant.
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) trait ExprVisitorMut<R> {
+    fn visit_assign_expr(&mut self, ast: &mut Ast, id: ExprId) -> R;
+    fn visit_binary_expr(&mut self, ast: &mut Ast, id: ExprId) -> R;
+    fn visit_call_expr(&mut self, ast: &mut Ast, id: ExprId) -> R;
+    fn visit_get_expr(&mut self, ast: &mut Ast, id: ExprId) -> R;
+    fn visit_grouping_expr(&mut self, ast: &mut Ast, id: ExprId) -> R;
+    fn visit_lambda_expr(&mut self, ast: &mut Ast, id: ExprId) -> R;
+    fn visit_list_expr(&mut self, ast: &mut Ast, id: ExprId) -> R;
+    fn visit_literal_expr(&mut self, ast: &mut Ast, id: ExprId) -> R;
+    fn visit_logical_expr(&mut self, ast: &mut Ast, id: ExprId) -> R;
+    fn visit_map_expr(&mut self, ast: &mut Ast, id: ExprId) -> R;
+    fn visit_set_expr(&mut self, ast: &mut Ast, id: ExprId) -> R;
+    fn visit_set_subscript_expr(&mut self, ast: &mut Ast, id: ExprId) -> R;
+    fn visit_subscript_expr(&mut self, ast: &mut Ast, id: ExprId) -> R;
+    fn visit_template_expr(&mut self, ast: &mut Ast, id: ExprId) -> R;
+    fn visit_ternary_expr(&mut self, ast: &mut Ast, id: ExprId) -> R;
+    fn visit_unary_expr(&mut self, ast: &mut Ast, id: ExprId) -> R;
+    fn visit_update_expr(&mut self, ast: &mut Ast, id: ExprId) -> R;
+    fn visit_variable_expr(&mut self, ast: &mut Ast, id: ExprId) -> R;
+}
+
+impl ExprId {
+    pub(crate) fn accept<R>(self, ast: &Ast, visitor: &mut impl ExprVisitor<R>) -> R {
+        match &ast[self] {
+            Expr::Assign(e) => visitor.visit_assign_expr(ast, self, e),
+            Expr::Binary(e) => visitor.visit_binary_expr(ast, self, e),
+            Expr::Call(e) => visitor.visit_call_expr(ast, self, e),
+            Expr::Get(e) => visitor.visit_get_expr(ast, self, e),
+            Expr::Grouping(e) => visitor.visit_grouping_expr(ast, self, e),
+            Expr::Lambda(e) => visitor.visit_lambda_expr(ast, self, e),
+            Expr::List(e) => visitor.visit_list_expr(ast, self, e),
+            Expr::Literal(e) => visitor.visit_literal_expr(ast, self, e),
+            Expr::Logical(e) => visitor.visit_logical_expr(ast, self, e),
+            Expr::Map(e) => visitor.visit_map_expr(ast, self, e),
+            Expr::Set(e) => visitor.visit_set_expr(ast, self, e),
+            Expr::SetSubscript(e) => visitor.visit_set_subscript_expr(ast, self, e),
+            Expr::Subscript(e) => visitor.visit_subscript_expr(ast, self, e),
+            Expr::Template(e) => visitor.visit_template_expr(ast, self, e),
+            Expr::Ternary(e) => visitor.visit_ternary_expr(ast, self, e),
+            Expr::Unary(e) => visitor.visit_unary_expr(ast, self, e),
+            Expr::Update(e) => visitor.visit_update_expr(ast, self, e),
+            Expr::Variable(e) => visitor.visit_variable_expr(ast, self, e),
+        }
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub(crate) fn accept_mut<R>(self, ast: &mut Ast, visitor: &mut impl ExprVisitorMut<R>) -> R {
+        match &ast[self] {
+            Expr::Assign(_) => visitor.visit_assign_expr(ast, self),
+            Expr::Binary(_) => visitor.visit_binary_expr(ast, self),
+            Expr::Call(_) => visitor.visit_call_expr(ast, self),
+            Expr::Get(_) => visitor.visit_get_expr(ast, self),
+            Expr::Grouping(_) => visitor.visit_grouping_expr(ast, self),
+            Expr::Lambda(_) => visitor.visit_lambda_expr(ast, self),
+            Expr::List(_) => visitor.visit_list_expr(ast, self),
+            Expr::Literal(_) => visitor.visit_literal_expr(ast, self),
+            Expr::Logical(_) => visitor.visit_logical_expr(ast, self),
+            Expr::Map(_) => visitor.visit_map_expr(ast, self),
+            Expr::Set(_) => visitor.visit_set_expr(ast, self),
+            Expr::SetSubscript(_) => visitor.visit_set_subscript_expr(ast, self),
+            Expr::Subscript(_) => visitor.visit_subscript_expr(ast, self),
+            Expr::Template(_) => visitor.visit_template_expr(ast, self),
+            Expr::Ternary(_) => visitor.visit_ternary_expr(ast, self),
+            Expr::Unary(_) => visitor.visit_unary_expr(ast, self),
+            Expr::Update(_) => visitor.visit_update_expr(ast, self),
+            Expr::Variable(_) => visitor.visit_variable_expr(ast, self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use crate::ast_printer::AstPrinter;
+    use crate::interner::Interner;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::stmt::{Stmt, StmtVisitorMut};
+    use crate::token_type::TokenType::*;
+
+    use super::*;
+
+    /// Folds sums and products of integer literals in place, looking
+    /// only inside the statements and expressions the test uses.
+    struct Folder;
+
+    impl StmtVisitorMut<()> for Folder {
+        fn visit_block_stmt(&mut self, ast: &mut Ast, id: StmtId) {
+            let Stmt::Block(s) = &ast[id] else { unreachable!() };
+            for statement in s.statements.clone() {
+                statement.accept_mut(ast, self);
+            }
+        }
+        fn visit_print_stmt(&mut self, ast: &mut Ast, id: StmtId) {
+            let Stmt::Print(s) = &ast[id] else { unreachable!() };
+            s.expression.accept_mut(ast, self);
+        }
+        fn visit_break_stmt(&mut self, _: &mut Ast, _: StmtId) {}
+        fn visit_continue_stmt(&mut self, _: &mut Ast, _: StmtId) {}
+        fn visit_expression_stmt(&mut self, _: &mut Ast, _: StmtId) {}
+        fn visit_for_stmt(&mut self, _: &mut Ast, _: StmtId) {}
+        fn visit_for_in_stmt(&mut self, _: &mut Ast, _: StmtId) {}
+        fn visit_function_stmt(&mut self, _: &mut Ast, _: StmtId) {}
+        fn visit_if_stmt(&mut self, _: &mut Ast, _: StmtId) {}
+        fn visit_import_stmt(&mut self, _: &mut Ast, _: StmtId) {}
+        fn visit_return_stmt(&mut self, _: &mut Ast, _: StmtId) {}
+        fn visit_throw_stmt(&mut self, _: &mut Ast, _: StmtId) {}
+        fn visit_try_stmt(&mut self, _: &mut Ast, _: StmtId) {}
+        fn visit_var_stmt(&mut self, _: &mut Ast, _: StmtId) {}
+        fn visit_while_stmt(&mut self, _: &mut Ast, _: StmtId) {}
+    }
+
+    impl ExprVisitorMut<()> for Folder {
+        fn visit_binary_expr(&mut self, ast: &mut Ast, id: ExprId) {
+            let Expr::Binary(e) = &ast[id] else { unreachable!() };
+            let (left, operator, right) = (e.left, e.operator.ty, e.right);
+            left.accept_mut(ast, self);
+            right.accept_mut(ast, self);
+            let int = |id| match &ast[id] {
+                Expr::Literal(Literal { value: LiteralToken::Int(n) }) => Some(*n),
+                _ => None,
+            };
+            let (Some(a), Some(b)) = (int(left), int(right)) else { return };
+            let value = match operator {
+                PLUS => a + b,
+                STAR => a * b,
+                _ => return,
+            };
+            ast[id] = Literal::new(LiteralToken::Int(value)).into();
+        }
+        fn visit_grouping_expr(&mut self, ast: &mut Ast, id: ExprId) {
+            let Expr::Grouping(e) = &ast[id] else { unreachable!() };
+            let expression = e.expression;
+            expression.accept_mut(ast, self);
+            if let Expr::Literal(literal) = &ast[expression] {
+                ast[id] = Literal::new(literal.value.clone()).into();
+            }
+        }
+        fn visit_assign_expr(&mut self, _: &mut Ast, _: ExprId) {}
+        fn visit_call_expr(&mut self, _: &mut Ast, _: ExprId) {}
+        fn visit_get_expr(&mut self, _: &mut Ast, _: ExprId) {}
+        fn visit_lambda_expr(&mut self, _: &mut Ast, _: ExprId) {}
+        fn visit_list_expr(&mut self, _: &mut Ast, _: ExprId) {}
+        fn visit_literal_expr(&mut self, _: &mut Ast, _: ExprId) {}
+        fn visit_logical_expr(&mut self, _: &mut Ast, _: ExprId) {}
+        fn visit_map_expr(&mut self, _: &mut Ast, _: ExprId) {}
+        fn visit_set_expr(&mut self, _: &mut Ast, _: ExprId) {}
+        fn visit_set_subscript_expr(&mut self, _: &mut Ast, _: ExprId) {}
+        fn visit_subscript_expr(&mut self, _: &mut Ast, _: ExprId) {}
+        fn visit_template_expr(&mut self, _: &mut Ast, _: ExprId) {}
+        fn visit_ternary_expr(&mut self, _: &mut Ast, _: ExprId) {}
+        fn visit_unary_expr(&mut self, _: &mut Ast, _: ExprId) {}
+        fn visit_update_expr(&mut self, _: &mut Ast, _: ExprId) {}
+        fn visit_variable_expr(&mut self, _: &mut Ast, _: ExprId) {}
+    }
+
+    #[test]
+    fn test_visit_mut() {
+        let source = "print (1 + 2) * 3 + x; { print 2 * (3 + 4) - 1; }";
+        let interner = Rc::new(Interner::new());
+        let mut ast = Parser::new(Scanner::new(source, interner.clone()), interner.clone()).parse().unwrap();
+        for statement in ast.statements.clone() {
+            statement.accept_mut(&mut ast, &mut Folder);
+        }
+
+        let Stmt::Print(first) = &ast[ast.statements[0]] else { panic!() };
+        let Stmt::Block(block) = &ast[ast.statements[1]] else { panic!() };
+        let Stmt::Print(second) = &ast[block.statements[0]] else { panic!() };
+        assert_eq!(AstPrinter::print(&ast, &interner, first.expression), "(+ 9 x)");
+        assert_eq!(AstPrinter::print(&ast, &interner, second.expression), "(- 14 1)");
     }
 }
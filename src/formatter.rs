@@ -0,0 +1,340 @@
+use std::rc::Rc;
+
+use crate::interner::Interner;
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+use crate::token::Token;
+use crate::token_type::TokenType::*;
+
+const INDENT: &str = "  ";
+
+/// Re-emits `source` in the one layout `primox fmt` allows: a statement per
+/// line, blocks indented by two spaces with their opening brace on the line
+/// of what they belong to, and single spaces around binary operators.
+/// Comments stay where they were, and runs of blank lines shrink to one.
+/// Fails with the errors of a source that does not parse, which is left
+/// alone rather than guessed at.
+pub fn format_source(source: &str) -> Result<String, Vec<String>> {
+    let interner = Rc::new(Interner::new());
+    let parser = Parser::new(Scanner::new(source, interner.clone()), interner.clone());
+    if let Err(errors) = parser.parse() {
+        return Err(errors.iter().map(|e| e.to_string()).collect());
+    }
+
+    // The source parsed, so it scans without errors.
+    let scanner = Scanner::new(source, interner).with_comments();
+    let tokens: Vec<Token> = scanner.map_while(Result::ok).take_while(|token| token.ty != EOF).collect();
+    let mut formatter = Formatter {
+        source,
+        output: String::new(),
+        open: vec![],
+        questions: 0,
+        previous: None,
+        last_end: 0,
+        pending: Break::None,
+    };
+    for (i, &token) in tokens.iter().enumerate() {
+        let mut code = tokens[i + 1..].iter().filter(|token| token.ty != COMMENT);
+        let lookahead = [code.next().copied(), code.next().copied()];
+        formatter.token(token, lookahead);
+    }
+
+    let mut output = formatter.output;
+    output.truncate(output.trim_end().len());
+    if !output.is_empty() {
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+/// What goes between two tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Break {
+    None,
+    Space,
+    Line,
+}
+
+/// A bracket the formatter is inside of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Open {
+    Block,
+    Map,
+    Paren { for_header: bool },
+    Bracket,
+}
+
+/// The last token other than a comment, with what it turned out to be.
+#[derive(Debug, Clone, Copy)]
+struct Previous<'a> {
+    token: Token<'a>,
+    // Whether it can end an operand, after which `(` is a call, `[` a
+    // subscript, `-` a binary operator, and `++` or `--` postfix ones.
+    operand: bool,
+    // Whether it is a unary operator before its operand.
+    prefix: bool,
+    // Whether it closed a block.
+    block_end: bool,
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    output: String,
+    // The brackets around the current token, innermost last, each with the
+    // `questions` of the bracket around it.
+    open: Vec<(Open, usize)>,
+    // Ternaries whose `:` is still to come, in the innermost bracket. It
+    // tells them apart from the `:` of a map entry.
+    questions: usize,
+    previous: Option<Previous<'a>>,
+    // Where the last token, comments included, ends in the source.
+    last_end: usize,
+    // What a comment must be followed by, such as the end of its line.
+    pending: Break,
+}
+
+impl<'a> Formatter<'a> {
+    /// Writes `token`, knowing the two code tokens after it.
+    fn token(&mut self, token: Token<'a>, lookahead: [Option<Token>; 2]) {
+        let newlines = self.source[self.last_end..token.offset].matches('\n').count();
+        self.last_end = token.offset + token.lexeme.len();
+
+        if token.ty == COMMENT {
+            self.comment(token, newlines);
+            return;
+        }
+
+        let separator = match std::mem::replace(&mut self.pending, Break::None) {
+            Break::Space if matches!(token.ty, RIGHT_PAREN | RIGHT_BRACKET | COMMA | SEMICOLON) => self.separator(token),
+            pending => self.separator(token).max(pending),
+        };
+        let closed = self.close(token);
+        // Only lines ending a block go back to the depth of the block;
+        // lines a comment broke within brackets are indented by them too.
+        let depth = match closed {
+            Some(Open::Block) => self.blocks(),
+            _ => self.depth(),
+        };
+        self.write(separator, newlines, depth, token.lexeme);
+
+        let prefix = matches!(token.ty, BANG | TILDE)
+            || matches!(token.ty, MINUS | PLUS_PLUS | MINUS_MINUS) && !self.previous.is_some_and(|previous| previous.operand);
+        let operand = match token.ty {
+            IDENTIFIER | STRING | INTEGER | BIGINT | DECIMAL | NUMBER | TRUE | FALSE | NIL | THIS | SUPER => true,
+            RIGHT_PAREN | RIGHT_BRACKET => true,
+            RIGHT_BRACE => closed == Some(Open::Map),
+            PLUS_PLUS | MINUS_MINUS => !prefix,
+            _ => false,
+        };
+        self.open(token, lookahead);
+        self.previous = Some(Previous {
+            token,
+            operand,
+            prefix,
+            block_end: closed == Some(Open::Block),
+        });
+    }
+
+    /// Writes a comment at the end of the line it was on, or on a line of
+    /// its own if it had one.
+    fn comment(&mut self, token: Token, newlines: usize) {
+        let separator = if newlines > 0 {
+            Break::Line
+        } else if self.output.ends_with(['(', '[']) {
+            Break::None
+        } else {
+            Break::Space
+        };
+        self.write(separator, newlines, self.depth(), token.lexeme);
+
+        let rest = self.source[self.last_end..].trim_start_matches([' ', '\t', '\r']);
+        self.pending = if token.lexeme.starts_with("//") || rest.starts_with('\n') {
+            Break::Line
+        } else {
+            Break::Space
+        };
+    }
+
+    /// Writes `text` after `separator`. A new line starts `depth` levels
+    /// in, after one blank line if there were any in the source.
+    fn write(&mut self, separator: Break, newlines: usize, depth: usize, text: &str) {
+        match separator {
+            _ if self.output.is_empty() => {}
+            Break::None => {}
+            Break::Space => self.output.push(' '),
+            Break::Line => {
+                self.output.push('\n');
+                if newlines > 1 && !self.output.ends_with("{\n") && !text.starts_with('}') {
+                    self.output.push('\n');
+                }
+                self.output.push_str(&INDENT.repeat(depth));
+            }
+        }
+        self.output.push_str(text);
+    }
+
+    fn blocks(&self) -> usize {
+        self.open.iter().filter(|&&(open, _)| open == Open::Block).count()
+    }
+
+    fn depth(&self) -> usize {
+        let brackets = self.open.iter().rev().take_while(|&&(open, _)| open != Open::Block).count();
+        self.blocks() + brackets
+    }
+
+    fn innermost(&self) -> Option<Open> {
+        self.open.last().map(|&(open, _)| open)
+    }
+
+    /// What goes between the previous token and `token`.
+    fn separator(&self, token: Token) -> Break {
+        let Some(previous) = self.previous else {
+            return Break::None;
+        };
+
+        // Statements go on lines of their own.
+        match previous.token.ty {
+            SEMICOLON if self.innermost() != Some(Open::Paren { for_header: true }) => return Break::Line,
+            LEFT_BRACE if self.innermost() == Some(Open::Block) => {
+                return if token.ty == RIGHT_BRACE { Break::None } else { Break::Line };
+            }
+            RIGHT_BRACE if previous.block_end => {
+                return match token.ty {
+                    ELSE | CATCH | FINALLY => Break::Space,
+                    SEMICOLON | COMMA | DOT | LEFT_PAREN | RIGHT_PAREN | RIGHT_BRACKET => Break::None,
+                    // A function passed along in an expression.
+                    _ if self.innermost().is_some_and(|open| open != Open::Block) => Break::Space,
+                    _ => Break::Line,
+                };
+            }
+            _ => {}
+        }
+        if token.ty == RIGHT_BRACE && self.innermost() == Some(Open::Block) {
+            return Break::Line;
+        }
+
+        let tight_before = match token.ty {
+            RIGHT_PAREN | RIGHT_BRACKET | COMMA | SEMICOLON | DOT => true,
+            RIGHT_BRACE => self.innermost() == Some(Open::Map),
+            LEFT_PAREN | LEFT_BRACKET | PLUS_PLUS | MINUS_MINUS => previous.operand,
+            COLON => self.questions == 0,
+            // The rest of a string after an interpolation.
+            STRING | INTERPOLATION => token.lexeme.starts_with('}'),
+            _ => false,
+        };
+        let tight_after = match previous.token.ty {
+            LEFT_PAREN | LEFT_BRACKET | DOT | INTERPOLATION => true,
+            LEFT_BRACE => self.innermost() == Some(Open::Map),
+            // Unless the operator and the token would scan as one, as
+            // `- -x` would as `--x`.
+            _ if previous.prefix => {
+                let merges = |c: char| previous.token.lexeme.ends_with(c) && token.lexeme.starts_with(c);
+                !merges('-') && !merges('+')
+            }
+            _ => false,
+        };
+        if tight_before || tight_after {
+            Break::None
+        } else {
+            Break::Space
+        }
+    }
+
+    /// Leaves the bracket `token` closes, if any, returning it.
+    fn close(&mut self, token: Token) -> Option<Open> {
+        if !matches!(token.ty, RIGHT_PAREN | RIGHT_BRACKET | RIGHT_BRACE) {
+            return None;
+        }
+        let (open, questions) = self.open.pop()?;
+        self.questions = questions;
+        Some(open)
+    }
+
+    /// Enters the bracket `token` opens, if any, and counts ternaries.
+    fn open(&mut self, token: Token, lookahead: [Option<Token>; 2]) {
+        let open = match token.ty {
+            LEFT_PAREN => Open::Paren {
+                for_header: self.previous.is_some_and(|previous| previous.token.ty == FOR),
+            },
+            LEFT_BRACKET => Open::Bracket,
+            LEFT_BRACE if self.opens_block(lookahead) => Open::Block,
+            LEFT_BRACE => Open::Map,
+            QUESTION => {
+                self.questions += 1;
+                return;
+            }
+            COLON if self.questions > 0 => {
+                self.questions -= 1;
+                return;
+            }
+            _ => return,
+        };
+        self.open.push((open, std::mem::take(&mut self.questions)));
+    }
+
+    /// Whether the `{` just written opens a block, as the parser decides:
+    /// it does where a statement or a function body can start, unless a
+    /// simple key and a colon follow, as only in a map.
+    fn opens_block(&self, lookahead: [Option<Token>; 2]) -> bool {
+        let starts_map = matches!(
+            lookahead,
+            [Some(Token { ty: STRING | INTEGER | BIGINT | DECIMAL | NUMBER | IDENTIFIER | TRUE | FALSE | NIL, .. }), Some(Token { ty: COLON, .. })]
+        );
+        let starts_statement = match self.previous {
+            None => true,
+            Some(previous) => match previous.token.ty {
+                SEMICOLON | RIGHT_PAREN | ELSE | TRY | FINALLY | ARROW => true,
+                LEFT_BRACE => self.innermost() == Some(Open::Block),
+                RIGHT_BRACE => previous.block_end,
+                _ => false,
+            },
+        };
+        starts_statement && !starts_map
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+
+    fn fixtures(dir: &Path, files: &mut Vec<String>) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                fixtures(&path, files);
+            } else if path.extension().is_some_and(|extension| extension == "lox") {
+                files.push(path.to_string_lossy().into_owned());
+            }
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        let mut files = vec![];
+        fixtures(Path::new("fixtures"), &mut files);
+        assert!(!files.is_empty());
+
+        // Formatted fixtures come back unchanged, and unformatted ones come
+        // out as the formatted fixture of the same name.
+        for file in files {
+            let source = fs::read_to_string(&file).unwrap();
+            let formatted = format_source(&source).unwrap();
+            let expected = match file.strip_suffix(".unformatted.lox") {
+                Some(stem) => fs::read_to_string(format!("{}.lox", stem)).unwrap(),
+                None => source,
+            };
+            assert_eq!(formatted, expected, "{}", file);
+        }
+
+        assert_eq!(format_source("print  1 ;// note\n\n\n\nprint 2;").unwrap(), "print 1; // note\n\nprint 2;\n");
+        assert_eq!(format_source("").unwrap(), "");
+        // Prefix operators stay apart from a token they would merge with.
+        assert_eq!(format_source("print -  -x;").unwrap(), "print - -x;\n");
+        assert_eq!(format_source("print - --x;").unwrap(), "print - --x;\n");
+        assert_eq!(format_source("print - ++x + -(--x) + !!x;").unwrap(), "print -++x + -(--x) + !!x;\n");
+        assert_eq!(format_source("print (").unwrap_err(), vec!["[line 1] Error at end: Expect expression."]);
+    }
+}
@@ -0,0 +1,27 @@
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::rc::Rc;
+
+use crate::ast::{Ast, ExprId};
+use crate::environment::Environment;
+
+/// A function defined in Lox, along with the variables it closed over.
+/// It refers to its `Lambda` node by id, and keeps the tree holding that
+/// node alive, so it can be called after its program has finished running.
+pub(crate) struct LoxFunction {
+    pub(crate) name: Option<Rc<str>>,
+    pub(crate) arity: usize,
+    pub(crate) lambda: ExprId,
+    pub(crate) ast: Rc<Ast>,
+    pub(crate) closure: Rc<RefCell<Environment>>,
+}
+
+// The closure may well contain the function itself.
+impl Debug for LoxFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoxFunction")
+            .field("name", &self.name)
+            .field("lambda", &self.lambda)
+            .finish()
+    }
+}
@@ -0,0 +1,62 @@
+use std::ops::ControlFlow;
+use std::path::Path;
+
+use crate::interpreter::{Interpreter, StackFrame};
+
+/// Watches a program run, one statement at a time. The interpreter calls
+/// it before each statement other than a block, which is how the debugger
+/// pauses, and how a coverage tool or a profiler could count lines.
+pub trait Hook {
+    /// Called before the statement at `pause`. Breaking stops the program
+    /// with an "Interrupted." error.
+    fn before_statement(&mut self, pause: &mut Pause) -> ControlFlow<()>;
+}
+
+/// A program stopped before one of its statements, for a `Hook` to look
+/// at. Frames are counted from the innermost call, which is frame 0.
+pub struct Pause<'a> {
+    pub(crate) interpreter: &'a mut Interpreter,
+    pub(crate) line: usize,
+    pub(crate) offset: usize,
+}
+
+impl Pause<'_> {
+    /// The line of the statement about to run.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Where the statement starts in its source, in bytes. Together with
+    /// the line, it tells apart statements that share a line.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The file the statement comes from, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.interpreter.current_path()
+    }
+
+    /// How many calls are running.
+    pub fn depth(&self) -> usize {
+        self.interpreter.call_depth()
+    }
+
+    /// The calls running, innermost first, ending with the top-level code.
+    pub fn stack(&self) -> Vec<StackFrame> {
+        self.interpreter.stack(self.line)
+    }
+
+    /// The variables visible in `frame`, scope by scope from the innermost
+    /// one to the globals, as names and values sorted by name. Returns
+    /// `None` if there is no such frame.
+    pub fn scopes(&self, frame: usize) -> Option<Vec<Vec<(String, String)>>> {
+        self.interpreter.scopes(frame)
+    }
+
+    /// Runs `source` in the innermost frame, returning the value of its
+    /// last statement if that is an expression, or the error it ran into.
+    pub fn evaluate(&mut self, source: &str) -> Result<String, String> {
+        self.interpreter.evaluate_source(source)
+    }
+}
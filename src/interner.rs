@@ -0,0 +1,58 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// A cheap handle to an interned string. Two symbols from the same
+/// `Interner` are equal exactly when their strings are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) struct Symbol(u32);
+
+/// Deduplicates identifiers and string constants. It is shared (behind an
+/// `Rc`) by the scanner, the parser and the interpreter, so a name gets the
+/// same `Symbol` no matter which source it was read from.
+#[derive(Debug, Default)]
+pub(crate) struct Interner {
+    symbols: RefCell<HashMap<Rc<str>, Symbol>>,
+    strings: RefCell<Vec<Rc<str>>>,
+}
+
+impl Interner {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn intern(&self, string: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.borrow().get(string) {
+            return symbol;
+        }
+
+        let mut strings = self.strings.borrow_mut();
+        let symbol = Symbol(strings.len() as u32);
+        let string: Rc<str> = Rc::from(string);
+        strings.push(string.clone());
+        self.symbols.borrow_mut().insert(string, symbol);
+        symbol
+    }
+
+    /// The string behind `symbol`. Every call returns the same allocation,
+    /// so resolved strings can be compared by pointer.
+    pub(crate) fn resolve(&self, symbol: Symbol) -> Rc<str> {
+        self.strings.borrow()[symbol.0 as usize].clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_intern() {
+        let interner = Interner::new();
+        let a = interner.intern("foo");
+        let b = interner.intern("bar");
+        assert_ne!(a, b);
+        assert_eq!(interner.intern("foo"), a);
+        assert_eq!(&*interner.resolve(b), "bar");
+        assert!(Rc::ptr_eq(&interner.resolve(a), &interner.resolve(a)));
+    }
+}
@@ -1,154 +1,758 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::Display;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Instant;
 
+use indexmap::IndexMap;
+
+use crate::ast::{Ast, ExprId, Span, StmtId};
+use crate::bignum::{self, BigInt, Decimal};
+use crate::environment::Environment;
 use crate::expr::*;
+use crate::function::LoxFunction;
+use crate::hook::{Hook, Pause};
+use crate::interner::Interner;
+use crate::iterator::{self, ValueIterator};
+use crate::limits::{self, Interrupt, LimitExceeded, Limits};
+use crate::module::Module;
+use crate::natives::{self, IO_NATIVES, NATIVES};
 use crate::stmt::*;
-use crate::token::*;
-use crate::token_type::TokenType::*;
-
-// In Java, all generic values of Lox is fitted into Object, using 
-// `istanceOf` for type-checking and finding runtime errors. This is
-// not feasible in Rust, so for now we just wrap every possible Lox
-// values in an enum instead of a Trait. In effect, we have implemented
-// a type system for Lox in Rust.
-#[derive(PartialEq)]
-pub(crate) enum Value {
-    Null,
-    Bool(bool),
-    Number(f64),
-    String(String)
+use crate::token_type::TokenType::{self, *};
+use crate::value::{ErrorObject, MapKey, NativeMethod, Value};
+use crate::Lox;
+
+pub(crate) struct Interpreter {
+    interner: Rc<Interner>,
+    environment: Rc<RefCell<Environment>>,
+    // The scope of the natives, enclosing the globals of every module.
+    builtins: Rc<RefCell<Environment>>,
+    // The tree being run, which functions created from it keep alive.
+    ast: Rc<Ast>,
+    // Modules already loaded, by canonical path.
+    modules: HashMap<PathBuf, Rc<Module>>,
+    // The canonical paths of the files being run, each importing the next.
+    importing: Vec<PathBuf>,
+    // The Lox functions being called, outermost first.
+    calls: Vec<CallFrame>,
+    limits: Limits,
+    // Statements and expressions evaluated, and when to stop, for the
+    // current call to `interprete`.
+    steps: u64,
+    deadline: Option<Instant>,
+    interrupt: Interrupt,
+    hook: Option<Box<dyn Hook>>,
+    // Where `print` writes.
+    output: Box<dyn Write>,
 }
 
-impl From<LiteralToken> for Value {
-    fn from(token: LiteralToken) -> Self {
-        match token {
-            LiteralToken::Null => Value::Null,
-            LiteralToken::Bool(b) => Value::Bool(b),
-            LiteralToken::Number(n) => Value::Number(n),
-            LiteralToken::String(s) => Value::String(s),
-        }
-    }
+/// A Lox function being called, and where its caller was at.
+struct CallFrame {
+    function: String,
+    caller_path: Option<PathBuf>,
+    call_line: usize,
+    caller_environment: Rc<RefCell<Environment>>,
 }
 
-impl Display for Value {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Value::Null => write!(f, "nil"),
-            Value::Bool(v) => write!(f, "{}", v),
-            Value::Number(v) => write!(f, "{}", v),
-            Value::String(v) => write!(f, "{}", v),
+// How many steps go by between two looks at the clock and the interrupt.
+const STEPS_PER_CHECK: u64 = 1024;
+
+impl Interpreter {
+    #[allow(dead_code)]
+    pub(crate) fn new(interner: Rc<Interner>) -> Self {
+        Self::with_limits(interner, Limits::default())
+    }
+
+    pub(crate) fn with_limits(interner: Rc<Interner>, limits: Limits) -> Self {
+        let mut builtins = Environment::new();
+        let io_natives = if limits.allow_io { IO_NATIVES } else { &[] };
+        for native in NATIVES.iter().chain(io_natives) {
+            builtins.define(interner.intern(native.name), Value::NativeFunction(native));
+        }
+        let builtins = Rc::new(RefCell::new(builtins));
+
+        Self {
+            interner,
+            environment: Rc::new(RefCell::new(Environment::with_enclosing(builtins.clone()))),
+            builtins,
+            ast: Rc::default(),
+            modules: HashMap::new(),
+            importing: vec![],
+            calls: vec![],
+            limits,
+            steps: 0,
+            deadline: None,
+            interrupt: Interrupt::default(),
+            hook: None,
+            output: Box::new(io::stdout()),
         }
     }
-}
 
-pub(crate) struct Interpreter {}
+    /// Installs a hook to call before each statement, replacing any other.
+    pub(crate) fn set_hook(&mut self, hook: Option<Box<dyn Hook>>) {
+        self.hook = hook;
+    }
 
-impl Interpreter {
-    pub(crate) fn new() -> Self {
-        Self {}
+    /// Sends what programs print to `output` rather than to stdout.
+    pub(crate) fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    /// A handle that stops whatever program the interpreter is running.
+    pub(crate) fn interrupt_handle(&self) -> Interrupt {
+        self.interrupt.clone()
+    }
+
+    pub(crate) fn interprete(&mut self, ast: &Rc<Ast>) -> Result<(), RuntimeError> {
+        self.ast = ast.clone();
+        self.steps = 0;
+        self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+        // A module importing the file being run is part of a cycle.
+        let path = ast.path.as_ref().and_then(|path| fs::canonicalize(path).ok());
+        self.importing.extend(path.clone());
+        let result = self.run_statements(ast);
+        if path.is_some() {
+            self.importing.pop();
+        }
+        result.map_err(|mut e| {
+            e.unwind("script".to_string(), ast.path.clone(), 0);
+            e
+        })
     }
 
-    pub(crate) fn interprete(&mut self, statements: Vec<Stmt>) -> Result<(), RuntimeError> {
-        for statement in statements {
-            self.execute(statement)?;
+    /// Runs the top-level statements of a program in the current scope.
+    fn run_statements(&mut self, ast: &Ast) -> Result<(), RuntimeError> {
+        for &statement in &ast.statements {
+            match self.execute(ast, statement) {
+                Ok(()) => {}
+                Err(Unwind::Error(e)) => return Err(e),
+                // The resolver rejects `break`, `continue` and `return` out
+                // of place.
+                Err(Unwind::Break | Unwind::Continue | Unwind::Return(_)) => unreachable!(),
+            }
         }
         Ok(())
     }
 
-    fn evaluate(expr: Expr) -> Result<Value, RuntimeError> {
-        match expr {
-            Expr::Binary(e) => Self::binary_expr(*e),
-            Expr::Literal(e) => Self::literal_expr(*e),
-            Expr::Grouping(e) => Self::grouping_expr(*e),
-            Expr::Ternary(e) => Self::ternary_expr(*e),
-            Expr::Unary(e) => Self::unary_expr(*e),
+    /// Loads the module at `path`, relative to the directory of the file
+    /// being run, or returns it as loaded before.
+    fn import(&mut self, path: &str, span: Span) -> Result<Rc<Module>, RuntimeError> {
+        if !self.limits.allow_io {
+            return Err(RuntimeError::new(span, "Imports are disabled."));
+        }
+
+        let error = |message: String| RuntimeError::new(span, &message);
+        let directory = self.ast.path.as_deref().and_then(Path::parent).unwrap_or(Path::new(""));
+        let file = directory.join(path);
+        let canonical =
+            fs::canonicalize(&file).map_err(|e| error(format!("Can't open module '{}': {}.", file.display(), e)))?;
+
+        if let Some(module) = self.modules.get(&canonical) {
+            return Ok(module.clone());
+        }
+        if let Some(start) = self.importing.iter().position(|importing| *importing == canonical) {
+            let cycle: Vec<_> = self.importing[start..]
+                .iter()
+                .chain([&canonical])
+                .map(|path| path.file_name().unwrap_or_default().to_string_lossy())
+                .collect();
+            return Err(error(format!("Import cycle: {}.", cycle.join(" -> "))));
+        }
+
+        let source =
+            fs::read_to_string(&canonical).map_err(|e| error(format!("Can't read module '{}': {}.", file.display(), e)))?;
+        let ast = Lox::compile(&source, &self.interner, Some(file.clone()))
+            .map_err(|errors| error(format!("Errors in module '{}':\n{}", file.display(), errors.join("\n"))))?;
+
+        // The module runs in its own global scope, which becomes its
+        // namespace.
+        let ast = Rc::new(ast);
+        let globals = Rc::new(RefCell::new(Environment::with_enclosing(self.builtins.clone())));
+        self.importing.push(canonical.clone());
+        let previous_ast = std::mem::replace(&mut self.ast, ast.clone());
+        let previous_environment = std::mem::replace(&mut self.environment, globals.clone());
+        let result = self.run_statements(&ast);
+        self.environment = previous_environment;
+        self.ast = previous_ast;
+        self.importing.pop();
+        result.map_err(|mut e| {
+            e.unwind("script".to_string(), Some(file.clone()), span.line);
+            e
+        })?;
+
+        let name = file.file_stem().unwrap_or_default().to_string_lossy().into();
+        let module = Rc::new(Module { name, globals });
+        self.modules.insert(canonical, module.clone());
+        Ok(module)
+    }
+
+    fn evaluate(&mut self, ast: &Ast, expr: ExprId) -> Result<Value, RuntimeError> {
+        let limit_error = |limit| RuntimeError::limit(ast.exprs.span(expr), limit);
+        self.step().map_err(limit_error)?;
+        // Nested nodes and calls recurse through the tree walker, so the
+        // host stack grows onto the heap as it runs low, rather than
+        // overflowing.
+        let value = limits::with_stack(|| expr.accept(ast, self))?;
+        self.check_size(&value).map_err(limit_error)?;
+        Ok(value)
+    }
+
+    fn execute(&mut self, ast: &Ast, statement: StmtId) -> Result<(), Unwind> {
+        let span = ast.stmts.span(statement);
+        self.step().map_err(|limit| RuntimeError::limit(span, limit))?;
+
+        // The hook is out while it runs, so evaluating code from it does
+        // not call it again.
+        if !matches!(ast[statement], Stmt::Block(_)) {
+            if let Some(mut hook) = self.hook.take() {
+                let flow = hook.before_statement(&mut Pause { interpreter: self, line: span.line, offset: span.start });
+                self.hook = Some(hook);
+                if flow.is_break() {
+                    return Err(RuntimeError::limit(span, LimitExceeded::Interrupted).into());
+                }
+            }
         }
+        limits::with_stack(|| statement.accept(ast, self))
     }
 
-    fn execute(&mut self, statement: Stmt) -> Result<(), RuntimeError> {
-        match statement {
-            Stmt::Expression(s) => Self::expression_stmt(s),
-            Stmt::Print(s) => Self::print_stmt(s),
+    pub(crate) fn current_path(&self) -> Option<&Path> {
+        self.ast.path.as_deref()
+    }
+
+    pub(crate) fn call_depth(&self) -> usize {
+        self.calls.len()
+    }
+
+    /// The calls running, innermost first, when the innermost has reached
+    /// `line`.
+    pub(crate) fn stack(&self, line: usize) -> Vec<StackFrame> {
+        let mut frames = vec![];
+        let mut frame = StackFrame { function: "script".to_string(), path: self.ast.path.clone(), line };
+        for call in self.calls.iter().rev() {
+            frame.function = call.function.clone();
+            let caller = StackFrame { function: "script".to_string(), path: call.caller_path.clone(), line: call.call_line };
+            frames.push(std::mem::replace(&mut frame, caller));
         }
+        frames.push(frame);
+        frames
     }
 
-    fn expression_stmt(stmt: ExpressionStmt) -> Result<(), RuntimeError> {
-        Self::evaluate(stmt.expression)?;
-        Ok(())
+    /// The variables visible in the `frame`th call from the innermost, by
+    /// scope from the innermost to the globals.
+    pub(crate) fn scopes(&self, frame: usize) -> Option<Vec<Vec<(String, String)>>> {
+        let mut environment = match frame.checked_sub(1) {
+            None => self.environment.clone(),
+            Some(caller) => self.calls.iter().rev().nth(caller)?.caller_environment.clone(),
+        };
+
+        let mut scopes = vec![];
+        while !Rc::ptr_eq(&environment, &self.builtins) {
+            let mut scope: Vec<_> = environment
+                .borrow()
+                .bindings()
+                .map(|(name, value)| (self.interner.resolve(name).to_string(), value.repr()))
+                .collect();
+            scope.sort();
+            scopes.push(scope);
+
+            let Some(enclosing) = environment.borrow().enclosing() else { break };
+            environment = enclosing;
+        }
+        Some(scopes)
     }
 
-    fn print_stmt(stmt: PrintStmt) -> Result<(), RuntimeError> {
-        let value = Self::evaluate(stmt.expression)?;
-        println!("{}", value);
+    /// Runs `source` in the current scope. Returns the value of its last
+    /// statement if that is an expression, or `nil`.
+    pub(crate) fn evaluate_source(&mut self, source: &str) -> Result<String, String> {
+        // The local scopes, outermost first, so that the code can use
+        // their variables. The globals are left to the interpreter.
+        let mut scopes = vec![];
+        let mut environment = self.environment.clone();
+        loop {
+            let Some(enclosing) = environment.borrow().enclosing() else { break };
+            if Rc::ptr_eq(&enclosing, &self.builtins) {
+                break;
+            }
+            scopes.push(environment.borrow().bindings().map(|(name, _)| name).collect());
+            environment = enclosing;
+        }
+        scopes.reverse();
+
+        let ast = Lox::compile_within(source, &self.interner, scopes).map_err(|errors| errors.join("\n"))?;
+        let ast = Rc::new(ast);
+        let previous_ast = std::mem::replace(&mut self.ast, ast.clone());
+        let mut run = || {
+            let mut value = Value::Null;
+            for &statement in &ast.statements {
+                value = match &ast[statement] {
+                    Stmt::Expression(s) => self.evaluate(&ast, s.expression)?,
+                    _ => match self.execute(&ast, statement) {
+                        Err(Unwind::Error(e)) => return Err(e),
+                        _ => Value::Null,
+                    },
+                };
+            }
+            Ok(value)
+        };
+        let result = run();
+        self.ast = previous_ast;
+        result.map(|value| value.repr()).map_err(|e| e.message)
+    }
+
+    /// Counts one more step, failing once the program is out of steps or
+    /// time, or has been interrupted.
+    fn step(&mut self) -> Result<(), LimitExceeded> {
+        self.steps += 1;
+        if self.limits.max_steps.is_some_and(|max| self.steps > max) {
+            return Err(LimitExceeded::Steps);
+        }
+        if self.steps.is_multiple_of(STEPS_PER_CHECK) {
+            if self.interrupt.take() {
+                return Err(LimitExceeded::Interrupted);
+            }
+            if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(LimitExceeded::Timeout);
+            }
+        }
         Ok(())
     }
 
-    fn binary_expr(expr: Binary) -> Result<Value, RuntimeError> {
-        let left = Self::evaluate(expr.left)?;
-        let right = Self::evaluate(expr.right)?;
+    /// Fails if `value` is a string, list, map or exact number larger than
+    /// the limits allow. Growing a list or map in place is caught the next
+    /// time it is evaluated.
+    fn check_size(&self, value: &Value) -> Result<(), LimitExceeded> {
+        let Some(max) = self.limits.max_value_size else {
+            return Ok(());
+        };
+        let size = match value {
+            Value::String(string) => string.len(),
+            Value::List(list) => list.borrow().len(),
+            Value::Map(map) => map.borrow().len(),
+            Value::BigInt(n) => n.log10() as usize + 1,
+            Value::Decimal(n) => n.log10() as usize + 1,
+            _ => 0,
+        };
+        if size > max { Err(LimitExceeded::ValueSize) } else { Ok(()) }
+    }
 
-        match expr.operator.ty {
-            GREATER | GREATER_EQUAL | LESS | LESS_EQUAL | MINUS | SLASH | STAR => {
-                match (left, right) {
-                    (Value::Number(a), Value::Number(b)) => {
-                        match expr.operator.ty {
-                            GREATER => Ok(Value::Bool(a > b)),
-                            GREATER_EQUAL => Ok(Value::Bool(a >= b)),
-                            LESS => Ok(Value::Bool(a < b)),
-                            LESS_EQUAL => Ok(Value::Bool(a <= b)),
-                            MINUS => Ok(Value::Number(a - b)),
-                            SLASH => Ok(Value::Number(a / b)),
-                            STAR => Ok(Value::Number(a * b)),
-                            _ => unreachable!(),
-                        }
-                    }
-                    _ => Err(RuntimeError::new(&expr.operator, "Operands must be two numbers.")),
-                }
+    fn execute_block(&mut self, ast: &Ast, statements: &[StmtId], environment: Environment) -> Result<(), Unwind> {
+        self.in_scope(environment, |interpreter| {
+            statements.iter().try_for_each(|&statement| interpreter.execute(ast, statement))
+        })
+    }
+
+    /// Calls `f` with `environment` as the current environment, then
+    /// restores the previous one, even if `f` fails.
+    fn in_scope<T>(&mut self, environment: Environment, f: impl FnOnce(&mut Self) -> T) -> T {
+        let previous = std::mem::replace(&mut self.environment, Rc::new(RefCell::new(environment)));
+        let result = f(self);
+        self.environment = previous;
+        result
+    }
+
+    fn new_scope(&self) -> Environment {
+        Environment::with_enclosing(self.environment.clone())
+    }
+
+    /// Calls `callee`, a function or a native, from the expression at `span`.
+    fn call(&mut self, callee: Value, mut arguments: Vec<Value>, span: Span) -> Result<Value, RuntimeError> {
+        let arity = match &callee {
+            Value::NativeFunction(native) => native.arity,
+            Value::NativeMethod(native) => native.method.arity,
+            Value::Function(function) => function.arity,
+            _ => return Err(RuntimeError::new(span, "Can only call functions and classes.")),
+        };
+        if arguments.len() != arity {
+            let message = format!("Expected {} arguments but got {}.", arity, arguments.len());
+            return Err(RuntimeError::new(span, &message));
+        }
+
+        match callee {
+            Value::NativeFunction(native) => {
+                (native.function)(&arguments).map_err(|message| RuntimeError::new(span, &message))
             }
-            BANG_EQUAL => Ok(Value::Bool(!Self::is_equal(left, right))),
-            EQUAL_EQUAL => Ok(Value::Bool(Self::is_equal(left, right))),
-            PLUS => {
-                match (left, right) {
-                    (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a+b)),
-                    (Value::String(a), Value::String(b)) => Ok(Value::String(format!("{}{}", a, b))),
-                    _ => Err(RuntimeError::new(&expr.operator, "Operands must be two numbers or two strings.")),
-                }
+            Value::NativeMethod(native) => {
+                arguments.insert(0, native.receiver.clone());
+                (native.method.function)(&arguments).map_err(|message| RuntimeError::new(span, &message))
             }
+            Value::Function(function) => self.call_function(&function, arguments, span),
             _ => unreachable!(),
         }
     }
 
-    fn literal_expr(expr: Literal) -> Result<Value, RuntimeError> {
-        return Ok(expr.value.into());
+    /// Calls `function` from a call expression at `span`. A runtime error
+    /// coming out of the call gets the function's frame in its trace.
+    fn call_function(&mut self, function: &LoxFunction, arguments: Vec<Value>, span: Span) -> Result<Value, RuntimeError> {
+        if self.calls.len() == self.limits.max_call_depth {
+            return Err(RuntimeError::limit(span, LimitExceeded::CallDepth));
+        }
+
+        let ast = function.ast.clone();
+        let Expr::Lambda(lambda) = &ast[function.lambda] else {
+            unreachable!()
+        };
+
+        let mut environment = Environment::with_enclosing(function.closure.clone());
+        for (param, argument) in lambda.params.iter().zip(arguments) {
+            environment.define(param.symbol, argument);
+        }
+
+        let name = match &function.name {
+            Some(name) => format!("{}()", name),
+            None => "<fn>".to_string(),
+        };
+        self.calls.push(CallFrame {
+            function: name,
+            caller_path: self.ast.path.clone(),
+            call_line: span.line,
+            caller_environment: self.environment.clone(),
+        });
+        let caller_ast = std::mem::replace(&mut self.ast, ast.clone());
+        let result = self.execute_block(&ast, &lambda.body, environment);
+        self.ast = caller_ast;
+        let call = self.calls.pop().unwrap();
+
+        match result {
+            Ok(()) => Ok(Value::Null),
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Error(mut e)) => {
+                e.unwind(call.function, ast.path.clone(), span.line);
+                Err(e)
+            }
+            Err(Unwind::Break | Unwind::Continue) => unreachable!(),
+        }
     }
 
-    fn grouping_expr(expr: Grouping) -> Result<Value, RuntimeError> {
-        return Self::evaluate(expr.expression);
+    fn new_function(&self, name: Option<Rc<str>>, lambda: ExprId) -> Value {
+        let Expr::Lambda(e) = &self.ast[lambda] else {
+            unreachable!()
+        };
+        Value::Function(Rc::new(LoxFunction {
+            name,
+            arity: e.params.len(),
+            lambda,
+            ast: self.ast.clone(),
+            closure: self.environment.clone(),
+        }))
     }
 
-    fn ternary_expr(expr: Ternary) -> Result<Value, RuntimeError> {
-        let left = Self::evaluate(expr.left)?;
-        let mid = Self::evaluate(expr.mid)?;
-        let right = Self::evaluate(expr.right)?;
+    /// Applies `operator` like `binary_operation`, but first fails if the
+    /// result would be a number larger than the limits allow, which could
+    /// take very long to compute, and gives up if computing it runs past
+    /// the timeout.
+    fn operate(&self, operator: Operator, left: Value, right: Value) -> Result<Value, RuntimeError> {
+        if let Some(max) = self.limits.max_value_size {
+            if Self::result_digits(operator.ty, &left, &right).is_some_and(|digits| digits > max as f64) {
+                return Err(RuntimeError::limit(operator.span, LimitExceeded::ValueSize));
+            }
+        }
+        bignum::with_deadline(self.deadline, || Self::binary_operation(operator.ty, left, right))
+            .ok_or_else(|| RuntimeError::limit(operator.span, LimitExceeded::Timeout))?
+            .map_err(|message| RuntimeError::new(operator.span, &message))
+    }
 
-        if Self::is_truthy(left) { Ok(mid) } else { Ok(right) }
+    /// Roughly how many digits the result of `**` or `<<` on big integers
+    /// or decimals has, from the sizes of the operands. `None` for other
+    /// operations, whose results are never much larger than the operands.
+    fn result_digits(operator: TokenType, left: &Value, right: &Value) -> Option<f64> {
+        match (operator, left, right) {
+            // These overflow rather than grow.
+            (_, Value::Int(_), Value::Int(_)) => None,
+            (STAR_STAR, Value::Decimal(_), _) | (STAR_STAR, _, Value::Decimal(_)) => {
+                let exponent = right.to_decimal()?.to_bigint()?.to_f64().abs();
+                Some(exponent * left.to_decimal()?.log10() + 1.0)
+            }
+            (STAR_STAR, _, _) => Some(right.to_bigint()?.to_f64() * left.to_bigint()?.log10() + 1.0),
+            (LESS_LESS, _, _) => {
+                let left = left.to_bigint().filter(|n| !n.is_zero())?;
+                Some(left.log10() + right.to_bigint()?.to_f64() * std::f64::consts::LOG10_2 + 1.0)
+            }
+            _ => None,
+        }
     }
 
-    fn unary_expr(expr: Unary) -> Result<Value, RuntimeError> {
-        let right = Self::evaluate(expr.right)?;
+    /// Applies a binary operator, other than `and` and `or`, to operands
+    /// already evaluated.
+    fn binary_operation(operator: TokenType, left: Value, right: Value) -> Result<Value, String> {
+        match (operator, left, right) {
+            (COMMA, _, right) => Ok(right),
+            (BANG_EQUAL, left, right) => Ok(Value::Bool(!Self::is_equal(left, right))),
+            (EQUAL_EQUAL, left, right) => Ok(Value::Bool(Self::is_equal(left, right))),
+            (PLUS, Value::String(a), Value::String(b)) => Ok(Value::String(format!("{}{}", a, b).into())),
+            (AMPERSAND | CARET | GREATER_GREATER | LESS_LESS | PIPE, a, b)
+                if !matches!((&a, &b), (Value::Int(_) | Value::BigInt(_), Value::Int(_) | Value::BigInt(_))) =>
+            {
+                Err("Operands must be two integers.".to_string())
+            }
+            // Two integers give an integer. Otherwise exact numbers are
+            // converted to the wider of big integers and decimals. With a
+            // float on either side, both are converted to floats, except
+            // that decimals refuse to lose their exactness.
+            (_, Value::Int(a), Value::Int(b)) => Self::int_arithmetic(operator, a, b),
+            (_, a @ (Value::Int(_) | Value::BigInt(_)), b @ (Value::Int(_) | Value::BigInt(_))) => {
+                Self::bigint_arithmetic(operator, a.to_bigint().unwrap(), b.to_bigint().unwrap())
+            }
+            (_, Value::Decimal(_), Value::Number(_)) | (_, Value::Number(_), Value::Decimal(_)) => {
+                Err("Can't mix decimals and floats.".to_string())
+            }
+            (_, a @ (Value::Int(_) | Value::BigInt(_) | Value::Decimal(_)), b @ (Value::Int(_) | Value::BigInt(_) | Value::Decimal(_))) => {
+                Self::decimal_arithmetic(operator, a.to_decimal().unwrap(), b.to_decimal().unwrap())
+            }
+            (_, Value::Int(a), Value::Number(b)) => Ok(Self::float_arithmetic(operator, a as f64, b)),
+            (_, Value::Number(a), Value::Int(b)) => Ok(Self::float_arithmetic(operator, a, b as f64)),
+            (_, Value::BigInt(a), Value::Number(b)) => Ok(Self::float_arithmetic(operator, a.to_f64(), b)),
+            (_, Value::Number(a), Value::BigInt(b)) => Ok(Self::float_arithmetic(operator, a, b.to_f64())),
+            (_, Value::Number(a), Value::Number(b)) => Ok(Self::float_arithmetic(operator, a, b)),
+            (PLUS, _, _) => Err("Operands must be two numbers or two strings.".to_string()),
+            _ => Err("Operands must be two numbers.".to_string()),
+        }
+    }
 
-        match expr.operator.ty {
-            BANG => Ok(Value::Bool(!Self::is_truthy(right))),
-            MINUS => {
-                match right {
-                    Value::Number(a) => Ok(Value::Number(-a)),
-                    _ => Err(RuntimeError::new(&expr.operator, "Operand must be a number.")),
+    /// Applies an arithmetic, bitwise or comparison operator to two
+    /// integers. `/` always divides exactly, giving a float, as does `**`
+    /// with a negative exponent; `~/` and `%` round the quotient toward
+    /// negative infinity.
+    fn int_arithmetic(operator: TokenType, a: i64, b: i64) -> Result<Value, String> {
+        let result = match operator {
+            GREATER => return Ok(Value::Bool(a > b)),
+            GREATER_EQUAL => return Ok(Value::Bool(a >= b)),
+            LESS => return Ok(Value::Bool(a < b)),
+            LESS_EQUAL => return Ok(Value::Bool(a <= b)),
+            SLASH => return Ok(Value::Number(a as f64 / b as f64)),
+            STAR_STAR if b < 0 => return Ok(Value::Number((a as f64).powf(b as f64))),
+            TILDE_SLASH | PERCENT if b == 0 => return Err("Division by zero.".to_string()),
+            GREATER_GREATER | LESS_LESS if b < 0 => return Err("Shift amount must not be negative.".to_string()),
+            AMPERSAND => Some(a & b),
+            CARET => Some(a ^ b),
+            PIPE => Some(a | b),
+            // Shifting out a bit that differs from the sign overflows.
+            LESS_LESS if a == 0 => Some(0),
+            LESS_LESS => (b < 64).then(|| a << b).filter(|n| n >> b == a),
+            GREATER_GREATER => Some(a >> b.min(63)),
+            STAR_STAR => u32::try_from(b).ok().and_then(|b| a.checked_pow(b)),
+            MINUS => a.checked_sub(b),
+            PLUS => a.checked_add(b),
+            STAR => a.checked_mul(b),
+            TILDE_SLASH => a.checked_div(b).map(|q| if a % b != 0 && (a < 0) != (b < 0) { q - 1 } else { q }),
+            // Only the quotient of `i64::MIN` by -1 overflows, not the
+            // remainder, which is 0.
+            PERCENT => {
+                let r = a.wrapping_rem(b);
+                Some(if r != 0 && (r < 0) != (b < 0) { r + b } else { r })
+            }
+            _ => unreachable!(),
+        };
+        result.map(Value::Int).ok_or_else(|| "Integer overflow.".to_string())
+    }
+
+    /// Applies an arithmetic, bitwise or comparison operator to two big
+    /// integers, with the same rounding as `int_arithmetic`. `/` gives a float here
+    /// too; use decimals for exact quotients.
+    fn bigint_arithmetic(operator: TokenType, a: BigInt, b: BigInt) -> Result<Value, String> {
+        let result = match operator {
+            GREATER => return Ok(Value::Bool(a > b)),
+            GREATER_EQUAL => return Ok(Value::Bool(a >= b)),
+            LESS => return Ok(Value::Bool(a < b)),
+            LESS_EQUAL => return Ok(Value::Bool(a <= b)),
+            SLASH => return Ok(Value::Number(a.to_f64() / b.to_f64())),
+            STAR_STAR if b < BigInt::from(0) => return Ok(Value::Number(a.to_f64().powf(b.to_f64()))),
+            GREATER_GREATER | LESS_LESS if b < BigInt::from(0) => {
+                return Err("Shift amount must not be negative.".to_string())
+            }
+            AMPERSAND => a.bitwise(&b, |x, y| x & y),
+            CARET => a.bitwise(&b, |x, y| x ^ y),
+            PIPE => a.bitwise(&b, |x, y| x | y),
+            GREATER_GREATER => a.shr(b.to_i64().and_then(|n| u32::try_from(n).ok()).unwrap_or(u32::MAX)),
+            LESS_LESS => a.shl(Self::small_operand(&b)?),
+            STAR_STAR => a.pow(Self::small_operand(&b)?),
+            MINUS => &a - &b,
+            PLUS => &a + &b,
+            STAR => &a * &b,
+            TILDE_SLASH => a.div_floor(&b).ok_or("Division by zero.")?.0,
+            PERCENT => a.div_floor(&b).ok_or("Division by zero.")?.1,
+            _ => unreachable!(),
+        };
+        Ok(Value::bigint(result))
+    }
+
+    /// Applies an arithmetic or comparison operator to two decimals, with
+    /// the same rounding as `int_arithmetic`. Only `/` can be inexact, see
+    /// `Decimal::div`.
+    fn decimal_arithmetic(operator: TokenType, a: Decimal, b: Decimal) -> Result<Value, String> {
+        let result = match operator {
+            GREATER => return Ok(Value::Bool(a > b)),
+            GREATER_EQUAL => return Ok(Value::Bool(a >= b)),
+            LESS => return Ok(Value::Bool(a < b)),
+            LESS_EQUAL => return Ok(Value::Bool(a <= b)),
+            MINUS => &a - &b,
+            PLUS => &a + &b,
+            STAR => &a * &b,
+            SLASH => a.div(&b).ok_or("Division by zero.")?,
+            TILDE_SLASH => a.div_floor(&b).ok_or("Division by zero.")?.0,
+            PERCENT => a.div_floor(&b).ok_or("Division by zero.")?.1,
+            STAR_STAR => {
+                let exponent = b.to_bigint().ok_or("Exponent of a decimal must be an integer.")?;
+                let too_large = || "Right operand is too large.".to_string();
+                if exponent < BigInt::from(0) {
+                    let one = Decimal::from(BigInt::from(1));
+                    let power = a.pow(Self::small_operand(&-&exponent)?).ok_or_else(too_large)?;
+                    one.div(&power).ok_or("Division by zero.")?
+                } else {
+                    a.pow(Self::small_operand(&exponent)?).ok_or_else(too_large)?
                 }
             }
-            _ => unreachable!()
+            _ => unreachable!(),
+        };
+        Ok(Value::decimal(result))
+    }
+
+    /// An exponent or shift amount, which must fit in a `u32`.
+    fn small_operand(n: &BigInt) -> Result<u32, String> {
+        n.to_i64()
+            .and_then(|n| u32::try_from(n).ok())
+            .ok_or_else(|| "Right operand is too large.".to_string())
+    }
+
+    /// Applies an arithmetic or comparison operator to two floats, with
+    /// the same rounding as `int_arithmetic`.
+    fn float_arithmetic(operator: TokenType, a: f64, b: f64) -> Value {
+        match operator {
+            GREATER => Value::Bool(a > b),
+            GREATER_EQUAL => Value::Bool(a >= b),
+            LESS => Value::Bool(a < b),
+            LESS_EQUAL => Value::Bool(a <= b),
+            MINUS => Value::Number(a - b),
+            PLUS => Value::Number(a + b),
+            SLASH => Value::Number(a / b),
+            STAR => Value::Number(a * b),
+            STAR_STAR => Value::Number(a.powf(b)),
+            TILDE_SLASH => Value::Number((a / b).floor()),
+            PERCENT => Value::Number(a - b * (a / b).floor()),
+            _ => unreachable!(),
         }
     }
 
+    /// Decides, from how one pass through a loop body ended, whether the
+    /// loop goes on. Errors keep unwinding past the loop.
+    fn loop_continues(pass: Result<(), Unwind>) -> Result<bool, Unwind> {
+        match pass {
+            Ok(()) | Err(Unwind::Continue) => Ok(true),
+            Err(Unwind::Break) => Ok(false),
+            Err(unwind) => Err(unwind),
+        }
+    }
+
+    /// Reads the variable `expr` uses, in the scope the resolver found for
+    /// it.
+    fn look_up_variable(&self, ast: &Ast, expr: ExprId, name: &Identifier) -> Result<Value, RuntimeError> {
+        let environment = self.environment.borrow();
+        let value = match ast.locals.get(&expr) {
+            Some(&distance) => environment.get_at(distance, name.symbol),
+            None => environment.get_global(name.symbol),
+        };
+        value.ok_or_else(|| self.undefined_variable(name))
+    }
+
+    /// Rebinds the variable `expr` assigns, in the scope the resolver
+    /// found for it.
+    fn assign_variable(&self, ast: &Ast, expr: ExprId, name: &Identifier, value: Value) -> Result<(), RuntimeError> {
+        let mut environment = self.environment.borrow_mut();
+        let assigned = match ast.locals.get(&expr) {
+            Some(&distance) => environment.assign_at(distance, name.symbol, value),
+            None => environment.assign_global(name.symbol, value),
+        };
+        if assigned { Ok(()) } else { Err(self.undefined_variable(name)) }
+    }
+
+    /// Reads the element of a list at an index, the value of a map at a
+    /// key, or the character of a string at an index.
+    fn get_subscript(object: &Value, index: Value) -> Result<Value, String> {
+        match object {
+            Value::List(list) => {
+                let list = list.borrow();
+                let index = index.to_index(list.len())?;
+                Ok(list[index].clone())
+            }
+            Value::Map(map) => {
+                let key = MapKey::new(index)?;
+                let value = map.borrow().get(&key).cloned();
+                value.ok_or_else(|| format!("Undefined key {}.", key))
+            }
+            Value::String(string) => {
+                let index = index.to_index(string.chars().count())?;
+                Ok(Value::String(string.chars().nth(index).unwrap().to_string().into()))
+            }
+            _ => Err("Only lists, maps and strings can be indexed.".to_string()),
+        }
+    }
+
+    /// Replaces the element of a list at an index, or sets the value of a
+    /// map at a key.
+    fn set_subscript(object: &Value, index: Value, value: Value) -> Result<(), String> {
+        match object {
+            Value::List(list) => {
+                let mut list = list.borrow_mut();
+                let index = index.to_index(list.len())?;
+                list[index] = value;
+            }
+            Value::Map(map) => {
+                let key = MapKey::new(index)?;
+                map.borrow_mut().insert(key, value);
+            }
+            Value::String(_) => return Err("Strings are immutable.".to_string()),
+            _ => return Err("Only lists and maps can be indexed.".to_string()),
+        }
+        Ok(())
+    }
+
+    /// Reads a global of a module, a field of an error, or a method of a
+    /// string.
+    fn get_property(&self, object: Value, name: &Identifier) -> Result<Value, RuntimeError> {
+        let text = self.interner.resolve(name.symbol);
+        if let Value::Module(module) = &object {
+            let value = module.globals.borrow().get_local(name.symbol);
+            let message = || format!("Module '{}' has no '{}'.", module.name, text);
+            return value.ok_or_else(|| RuntimeError::new(name.span, &message()));
+        }
+        if let Value::Error(error) = &object {
+            return match &*text {
+                "message" => Ok(Value::String(error.message.clone())),
+                "line" => Ok(Value::Int(error.line as i64)),
+                _ => Err(RuntimeError::new(name.span, &format!("Errors have no field '{}'.", text))),
+            };
+        }
+
+        let methods = natives::methods(&object);
+        match methods.iter().find(|method| *method.name == *text) {
+            Some(method) => Ok(Value::NativeMethod(Rc::new(NativeMethod { receiver: object, method }))),
+            None if methods.is_empty() => Err(RuntimeError::new(name.span, "Only strings, modules and errors have properties.")),
+            None => Err(RuntimeError::new(name.span, &format!("Undefined method '{}'.", text))),
+        }
+    }
+
+    /// Rebinds an existing global of a module, the only properties that
+    /// can be set.
+    fn set_property(&self, object: &Value, name: &Identifier, value: Value) -> Result<(), RuntimeError> {
+        let Value::Module(module) = object else {
+            return Err(RuntimeError::new(name.span, "Only the globals of modules can be set."));
+        };
+        if module.globals.borrow_mut().assign_at(0, name.symbol, value) {
+            Ok(())
+        } else {
+            let message = format!("Module '{}' has no '{}'.", module.name, self.interner.resolve(name.symbol));
+            Err(RuntimeError::new(name.span, &message))
+        }
+    }
+
+    fn undefined_variable(&self, name: &Identifier) -> RuntimeError {
+        let message = format!("Undefined variable '{}'.", self.interner.resolve(name.symbol));
+        RuntimeError::new(name.span, &message)
+    }
+
     fn is_truthy(val: Value) -> bool {
         match val {
             Value::Null => false,
@@ -157,30 +761,901 @@ impl Interpreter {
         }
     }
 
+    // `MapKey` relies on this being `Value`'s own equality, so that map
+    // lookups agree with `==`.
     fn is_equal(a: Value, b: Value) -> bool {
         a == b
     }
 }
 
+impl StmtVisitor<Result<(), Unwind>> for Interpreter {
+    fn visit_block_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &BlockStmt) -> Result<(), Unwind> {
+        self.execute_block(ast, &stmt.statements, self.new_scope())
+    }
+
+    fn visit_break_stmt(&mut self, _: &Ast, _: StmtId, _: &BreakStmt) -> Result<(), Unwind> {
+        Err(Unwind::Break)
+    }
+
+    fn visit_continue_stmt(&mut self, _: &Ast, _: StmtId, _: &ContinueStmt) -> Result<(), Unwind> {
+        Err(Unwind::Continue)
+    }
+
+    fn visit_expression_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &ExpressionStmt) -> Result<(), Unwind> {
+        self.evaluate(ast, stmt.expression)?;
+        Ok(())
+    }
+
+    fn visit_for_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &ForStmt) -> Result<(), Unwind> {
+        // The initializer's variable lives in a scope of its own, around
+        // the whole loop.
+        self.in_scope(self.new_scope(), |interpreter| {
+            if let Some(initializer) = stmt.initializer {
+                interpreter.execute(ast, initializer)?;
+            }
+            loop {
+                if let Some(condition) = stmt.condition {
+                    if !Self::is_truthy(interpreter.evaluate(ast, condition)?) {
+                        return Ok(());
+                    }
+                }
+                if !Self::loop_continues(interpreter.execute(ast, stmt.body))? {
+                    return Ok(());
+                }
+                // A `continue` still runs the increment.
+                if let Some(increment) = stmt.increment {
+                    interpreter.evaluate(ast, increment)?;
+                }
+            }
+        })
+    }
+
+    fn visit_for_in_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &ForInStmt) -> Result<(), Unwind> {
+        let iterable = self.evaluate(ast, stmt.iterable)?;
+        let span = ast.exprs.span(stmt.iterable);
+        let mut iterator = match iterator::script_function(&iterable, "iterator") {
+            Some(function) => {
+                let object = self.call(function, vec![], span)?;
+                let next = iterator::script_function(&object, "next");
+                let next = next.ok_or_else(|| RuntimeError::new(span, "Iterators must have a 'next' function."))?;
+                ValueIterator::Script { next }
+            }
+            None => ValueIterator::new(iterable).map_err(|message| RuntimeError::new(span, &message))?,
+        };
+
+        // Each iteration binds the loop variable afresh.
+        while let Some(value) = iterator.next(|next| self.call(next.clone(), vec![], span))? {
+            let mut environment = self.new_scope();
+            environment.define(stmt.name.symbol, value);
+            let pass = self.execute_block(ast, std::slice::from_ref(&stmt.body), environment);
+            if !Self::loop_continues(pass)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_function_stmt(&mut self, _: &Ast, _: StmtId, stmt: &FunctionStmt) -> Result<(), Unwind> {
+        let name = self.interner.resolve(stmt.name.symbol);
+        let function = self.new_function(Some(name), stmt.function);
+        self.environment.borrow_mut().define(stmt.name.symbol, function);
+        Ok(())
+    }
+
+    fn visit_if_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &IfStmt) -> Result<(), Unwind> {
+        if Self::is_truthy(self.evaluate(ast, stmt.condition)?) {
+            self.execute(ast, stmt.then_branch)
+        } else if let Some(else_branch) = stmt.else_branch {
+            self.execute(ast, else_branch)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn visit_import_stmt(&mut self, _: &Ast, _: StmtId, stmt: &ImportStmt) -> Result<(), Unwind> {
+        let module = self.import(&stmt.path, stmt.keyword)?;
+        self.environment.borrow_mut().define(stmt.name.symbol, Value::Module(module));
+        Ok(())
+    }
+
+    fn visit_print_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &PrintStmt) -> Result<(), Unwind> {
+        let value = self.evaluate(ast, stmt.expression)?;
+        let message = |e: io::Error| format!("Can't print: {}.", e);
+        writeln!(self.output, "{}", value).map_err(|e| RuntimeError::new(ast.stmts.span(id), &message(e)))?;
+        Ok(())
+    }
+
+    fn visit_return_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &ReturnStmt) -> Result<(), Unwind> {
+        let value = match stmt.value {
+            Some(value) => self.evaluate(ast, value)?,
+            None => Value::Null,
+        };
+        Err(Unwind::Return(value))
+    }
+
+    fn visit_throw_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &ThrowStmt) -> Result<(), Unwind> {
+        let value = self.evaluate(ast, stmt.value)?;
+        Err(Unwind::Error(RuntimeError::thrown(stmt.keyword, value)))
+    }
+
+    fn visit_try_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &TryStmt) -> Result<(), Unwind> {
+        let result = match (self.execute(ast, stmt.body), &stmt.catch) {
+            (Err(Unwind::Error(e)), Some(catch)) if e.is_catchable() => {
+                let mut environment = self.new_scope();
+                environment.define(catch.name.symbol, e.into_value());
+                self.execute_block(ast, std::slice::from_ref(&catch.body), environment)
+            }
+            (result, _) => result,
+        };
+
+        // A limit or an interruption stops the program at once, without
+        // running any more of it, so `finally` can't keep it going.
+        if matches!(&result, Err(Unwind::Error(e)) if !e.is_catchable()) {
+            return result;
+        }
+        // Leaving the `finally` block early, by a jump or an error, takes
+        // over from however the rest of the statement ended.
+        if let Some(finally) = stmt.finally {
+            self.execute(ast, finally)?;
+        }
+        result
+    }
+
+    fn visit_var_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &VarStmt) -> Result<(), Unwind> {
+        let value = match stmt.initializer {
+            Some(initializer) => self.evaluate(ast, initializer)?,
+            None => Value::Null,
+        };
+        self.environment.borrow_mut().define(stmt.name.symbol, value);
+        Ok(())
+    }
+
+    fn visit_while_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &WhileStmt) -> Result<(), Unwind> {
+        while Self::is_truthy(self.evaluate(ast, stmt.condition)?) {
+            if !Self::loop_continues(self.execute(ast, stmt.body))? {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ExprVisitor<Result<Value, RuntimeError>> for Interpreter {
+    fn visit_assign_expr(&mut self, ast: &Ast, id: ExprId, expr: &Assign) -> Result<Value, RuntimeError> {
+        let value = match expr.operator {
+            Some(operator) => {
+                let current = self.look_up_variable(ast, id, &expr.name)?;
+                let right = self.evaluate(ast, expr.value)?;
+                self.operate(operator, current, right)?
+            }
+            None => self.evaluate(ast, expr.value)?,
+        };
+        self.assign_variable(ast, id, &expr.name, value.clone())?;
+        Ok(value)
+    }
+
+    fn visit_binary_expr(&mut self, ast: &Ast, _: ExprId, expr: &Binary) -> Result<Value, RuntimeError> {
+        let left = self.evaluate(ast, expr.left)?;
+        let right = self.evaluate(ast, expr.right)?;
+        self.operate(expr.operator, left, right)
+    }
+
+    fn visit_call_expr(&mut self, ast: &Ast, id: ExprId, expr: &Call) -> Result<Value, RuntimeError> {
+        let callee = self.evaluate(ast, expr.callee)?;
+
+        let mut arguments = vec![];
+        for &argument in &expr.arguments {
+            arguments.push(self.evaluate(ast, argument)?);
+        }
+
+        self.call(callee, arguments, ast.exprs.span(id))
+    }
+
+    fn visit_lambda_expr(&mut self, _: &Ast, id: ExprId, _: &Lambda) -> Result<Value, RuntimeError> {
+        Ok(self.new_function(None, id))
+    }
+
+    fn visit_list_expr(&mut self, ast: &Ast, _: ExprId, expr: &List) -> Result<Value, RuntimeError> {
+        let mut elements = vec![];
+        for &element in &expr.elements {
+            elements.push(self.evaluate(ast, element)?);
+        }
+        Ok(Value::list(elements))
+    }
+
+    fn visit_literal_expr(&mut self, _: &Ast, _: ExprId, expr: &Literal) -> Result<Value, RuntimeError> {
+        Ok(expr.value.clone().into())
+    }
+
+    fn visit_logical_expr(&mut self, ast: &Ast, _: ExprId, expr: &Logical) -> Result<Value, RuntimeError> {
+        let left = self.evaluate(ast, expr.left)?;
+
+        // The result is whichever operand decided it, not a boolean.
+        if expr.operator.ty == OR {
+            if Self::is_truthy(left.clone()) {
+                return Ok(left);
+            }
+        } else if !Self::is_truthy(left.clone()) {
+            return Ok(left);
+        }
+
+        self.evaluate(ast, expr.right)
+    }
+
+    fn visit_map_expr(&mut self, ast: &Ast, _: ExprId, expr: &Map) -> Result<Value, RuntimeError> {
+        let mut entries = IndexMap::new();
+        for &(key, value) in &expr.entries {
+            let span = ast.exprs.span(key);
+            let key = MapKey::new(self.evaluate(ast, key)?).map_err(|message| RuntimeError::new(span, &message))?;
+            let value = self.evaluate(ast, value)?;
+            entries.insert(key, value);
+        }
+        Ok(Value::map(entries))
+    }
+
+    fn visit_get_expr(&mut self, ast: &Ast, _: ExprId, expr: &Get) -> Result<Value, RuntimeError> {
+        let object = self.evaluate(ast, expr.object)?;
+        self.get_property(object, &expr.name)
+    }
+
+    fn visit_grouping_expr(&mut self, ast: &Ast, _: ExprId, expr: &Grouping) -> Result<Value, RuntimeError> {
+        self.evaluate(ast, expr.expression)
+    }
+
+    fn visit_set_expr(&mut self, ast: &Ast, _: ExprId, expr: &Set) -> Result<Value, RuntimeError> {
+        let object = self.evaluate(ast, expr.object)?;
+        let value = match expr.operator {
+            Some(operator) => {
+                let current = self.get_property(object.clone(), &expr.name)?;
+                let right = self.evaluate(ast, expr.value)?;
+                self.operate(operator, current, right)?
+            }
+            None => self.evaluate(ast, expr.value)?,
+        };
+        self.set_property(&object, &expr.name, value.clone())?;
+        Ok(value)
+    }
+
+    fn visit_set_subscript_expr(&mut self, ast: &Ast, id: ExprId, expr: &SetSubscript) -> Result<Value, RuntimeError> {
+        let object = self.evaluate(ast, expr.object)?;
+        let index = self.evaluate(ast, expr.index)?;
+
+        let span = ast.exprs.span(id);
+        let value = match expr.operator {
+            Some(operator) => {
+                let current = Self::get_subscript(&object, index.clone()).map_err(|message| RuntimeError::new(span, &message))?;
+                let right = self.evaluate(ast, expr.value)?;
+                self.operate(operator, current, right)?
+            }
+            None => self.evaluate(ast, expr.value)?,
+        };
+        Self::set_subscript(&object, index, value.clone()).map_err(|message| RuntimeError::new(span, &message))?;
+        Ok(value)
+    }
+
+    fn visit_subscript_expr(&mut self, ast: &Ast, id: ExprId, expr: &Subscript) -> Result<Value, RuntimeError> {
+        let object = self.evaluate(ast, expr.object)?;
+        let index = self.evaluate(ast, expr.index)?;
+        Self::get_subscript(&object, index).map_err(|message| RuntimeError::new(ast.exprs.span(id), &message))
+    }
+
+    fn visit_template_expr(&mut self, ast: &Ast, _: ExprId, expr: &Template) -> Result<Value, RuntimeError> {
+        let mut string = String::new();
+        for &part in &expr.parts {
+            string.push_str(&self.evaluate(ast, part)?.to_string());
+        }
+        Ok(Value::String(string.into()))
+    }
+
+    fn visit_ternary_expr(&mut self, ast: &Ast, _: ExprId, expr: &Ternary) -> Result<Value, RuntimeError> {
+        // Only the branch chosen runs, as either may have side effects.
+        let left = self.evaluate(ast, expr.left)?;
+        let branch = if Self::is_truthy(left) { expr.mid } else { expr.right };
+        self.evaluate(ast, branch)
+    }
+
+    fn visit_unary_expr(&mut self, ast: &Ast, _: ExprId, expr: &Unary) -> Result<Value, RuntimeError> {
+        let right = self.evaluate(ast, expr.right)?;
+
+        match expr.operator.ty {
+            BANG => Ok(Value::Bool(!Self::is_truthy(right))),
+            MINUS => {
+                match right {
+                    Value::Int(a) => a
+                        .checked_neg()
+                        .map(Value::Int)
+                        .ok_or_else(|| RuntimeError::new(expr.operator.span, "Integer overflow.")),
+                    Value::BigInt(a) => Ok(Value::bigint(-&*a)),
+                    Value::Decimal(a) => Ok(Value::decimal(-&*a)),
+                    Value::Number(a) => Ok(Value::Number(-a)),
+                    _ => Err(RuntimeError::new(expr.operator.span, "Operand must be a number.")),
+                }
+            }
+            TILDE => match right {
+                Value::Int(a) => Ok(Value::Int(!a)),
+                Value::BigInt(a) => Ok(Value::bigint(&-&*a - &BigInt::from(1))),
+                _ => Err(RuntimeError::new(expr.operator.span, "Operand must be an integer.")),
+            },
+            _ => unreachable!()
+        }
+    }
+
+    fn visit_update_expr(&mut self, ast: &Ast, _: ExprId, expr: &Update) -> Result<Value, RuntimeError> {
+        let operator = if expr.operator.ty == PLUS_PLUS { PLUS } else { MINUS };
+        let step = |old: Value| match old {
+            Value::Int(_) | Value::BigInt(_) | Value::Decimal(_) | Value::Number(_) => {
+                Self::binary_operation(operator, old, Value::Int(1))
+                    .map_err(|message| RuntimeError::new(expr.operator.span, &message))
+            }
+            _ => Err(RuntimeError::new(expr.operator.span, "Operand must be a number.")),
+        };
+
+        // The target's parts are evaluated once, for both reading and
+        // writing.
+        let (old, new) = match &ast[expr.target] {
+            Expr::Variable(target) => {
+                let old = self.look_up_variable(ast, expr.target, &target.name)?;
+                let new = step(old.clone())?;
+                self.assign_variable(ast, expr.target, &target.name, new.clone())?;
+                (old, new)
+            }
+            Expr::Get(target) => {
+                let object = self.evaluate(ast, target.object)?;
+                let old = self.get_property(object.clone(), &target.name)?;
+                let new = step(old.clone())?;
+                self.set_property(&object, &target.name, new.clone())?;
+                (old, new)
+            }
+            Expr::Subscript(target) => {
+                let object = self.evaluate(ast, target.object)?;
+                let index = self.evaluate(ast, target.index)?;
+                let span = ast.exprs.span(expr.target);
+                let old = Self::get_subscript(&object, index.clone()).map_err(|message| RuntimeError::new(span, &message))?;
+                let new = step(old.clone())?;
+                Self::set_subscript(&object, index, new.clone()).map_err(|message| RuntimeError::new(span, &message))?;
+                (old, new)
+            }
+            _ => unreachable!(),
+        };
+        Ok(if expr.prefix { new } else { old })
+    }
+
+    fn visit_variable_expr(&mut self, ast: &Ast, id: ExprId, expr: &Variable) -> Result<Value, RuntimeError> {
+        self.look_up_variable(ast, id, &expr.name)
+    }
+}
+
+/// Why a statement stopped before running to its end: a jump out of the
+/// innermost loop or function, or a runtime error on its way to the top
+/// level.
 #[derive(Debug)]
-pub(crate) struct RuntimeError {
-    token: Token,
+pub(crate) enum Unwind {
+    Break,
+    Continue,
+    Return(Value),
+    Error(RuntimeError),
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(e: RuntimeError) -> Self {
+        Unwind::Error(e)
+    }
+}
+
+/// An error that stopped a program. Its `Display` gives the message and
+/// the trace of the calls it unwound out of.
+#[derive(Debug)]
+pub struct RuntimeError {
+    span: Span,
     message: String,
+    // The limit the program ran into, if that is what went wrong.
+    limit: Option<LimitExceeded>,
+    // What a `throw` threw. Errors raised by the interpreter itself have
+    // none, and are caught as error objects instead.
+    value: Option<Value>,
+    // The calls the error has unwound out of, innermost first, ending with
+    // the top-level code once it reaches `interprete`.
+    trace: Vec<StackFrame>,
+    // The line reached in the innermost call not in `trace` yet.
+    line: usize,
+}
+
+/// A call running when a runtime error was raised or a program paused:
+/// the function, or "script" for top-level code, the file it comes from
+/// if any, and the line it had reached.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+    pub function: String,
+    pub path: Option<PathBuf>,
+    pub line: usize,
 }
 
 impl RuntimeError {
-    fn new(token: &Token, message: &str) -> Self {
+    fn new(span: Span, message: &str) -> Self {
         Self {
-            token: token.clone(),
+            span,
             message: message.to_string(),
+            limit: None,
+            value: None,
+            trace: vec![],
+            line: span.line,
+        }
+    }
+
+    fn limit(span: Span, limit: LimitExceeded) -> Self {
+        Self {
+            limit: Some(limit),
+            ..Self::new(span, &limit.to_string())
         }
     }
+
+    /// The error's message, without the trace.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The limit the program ran into, if the error is not the program's
+    /// own.
+    pub fn limit_exceeded(&self) -> Option<LimitExceeded> {
+        self.limit
+    }
+
+    /// Whether a `catch` clause can catch the error. Running out of steps,
+    /// time or memory, or being interrupted, ends the program whatever it
+    /// does.
+    fn is_catchable(&self) -> bool {
+        matches!(self.limit, None | Some(LimitExceeded::CallDepth))
+    }
+
+    /// The error unwinding from `throw value`. Rethrowing a caught error
+    /// object reports it where it was first raised.
+    fn thrown(span: Span, value: Value) -> Self {
+        let (span, message) = match &value {
+            Value::Error(error) => (Span { line: error.line, ..span }, error.message.to_string()),
+            _ => (span, format!("Uncaught exception: {}", value)),
+        };
+        Self {
+            value: Some(value),
+            ..Self::new(span, &message)
+        }
+    }
+
+    /// The value a `catch` clause binds for this error.
+    fn into_value(self) -> Value {
+        self.value.unwrap_or_else(|| {
+            Value::Error(Rc::new(ErrorObject {
+                message: self.message.into(),
+                line: self.span.line,
+            }))
+        })
+    }
+
+    /// Records the error unwinding out of `function`, from the file at
+    /// `path`, back to its caller at `call_line`.
+    fn unwind(&mut self, function: String, path: Option<PathBuf>, call_line: usize) {
+        let line = std::mem::replace(&mut self.line, call_line);
+        self.trace.push(StackFrame { function, path, line });
+    }
 }
 
 impl Display for RuntimeError {
+    /// Writes the message, then a line for each frame of the trace. A run
+    /// of identical frames, as in a stack overflow, is written once.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", &self.message)?;
+        if self.trace.is_empty() {
+            return write!(f, "\n[line {}]", self.line);
+        }
+
+        let mut frames = self.trace.iter().peekable();
+        while let Some(frame) = frames.next() {
+            write!(f, "\n{}", frame)?;
+            let mut repeated = 0;
+            while frames.next_if(|next| next == &frame).is_some() {
+                repeated += 1;
+            }
+            if repeated > 0 {
+                write!(f, "\n[previous line repeated {} more times]", repeated)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for StackFrame {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}\n[line {}]", &self.message, self.token.line)
+        write!(f, "[line {}] in {}", self.line, self.function)?;
+        match &self.path {
+            Some(path) => write!(f, " ({})", path.display()),
+            None => Ok(()),
+        }
     }
 }
 
 impl Error for RuntimeError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(source: &str, interner: &Rc<Interner>) -> Rc<Ast> {
+        Rc::new(Lox::compile(source, interner, None).unwrap())
+    }
+
+    /// Runs `source` and returns the global `result`, or the runtime error.
+    fn run(source: &str) -> Result<Value, String> {
+        let interner = Rc::new(Interner::new());
+        let ast = parse(source, &interner);
+        let mut interpreter = Interpreter::new(interner.clone());
+        interpreter.interprete(&ast).map_err(|e| e.message)?;
+        let result = interpreter.environment.borrow().get(interner.intern("result"));
+        Ok(result.unwrap())
+    }
+
+    #[test]
+    fn test_interprete_repeatedly() {
+        let interner = Rc::new(Interner::new());
+        let ast = parse("1 + 2;\n\"a\" + \"b\";", &interner);
+
+        let mut interpreter = Interpreter::new(interner);
+        for _ in 0..3 {
+            assert!(interpreter.interprete(&ast).is_ok());
+        }
+
+        let mut values = vec![];
+        for &statement in &ast.statements {
+            if let Stmt::Expression(s) = &ast[statement] {
+                values.push(interpreter.evaluate(&ast, s.expression).unwrap());
+            }
+        }
+        assert_eq!(values, vec![Value::Int(3), Value::String("ab".into())]);
+    }
+
+    #[test]
+    fn test_lists() {
+        let result = run("var a = [1, 2, 3]; a[0] = a[1] + a[2]; var result = a[0];");
+        assert_eq!(result, Ok(Value::Int(5)));
+
+        let source = "var a = [1, 2,]; var b = a; push(b, 3); insert(a, 0, 0); \
+                      var result = [len(a), pop(a), remove(a, 1), a];";
+        assert_eq!(run(source).unwrap().to_string(), "[4, 3, 1, [0, 2]]");
+
+        let result = run("var result = slice([\"a\", \"b\", \"c\"], 1, 3);");
+        assert_eq!(result.unwrap().to_string(), "[\"b\", \"c\"]");
+
+        // A collection inside itself shows as `[...]` or `{...}`, while one
+        // merely seen twice shows both times.
+        let source = "var l = [1]; push(l, l); var m = {}; m[\"m\"] = m; m[\"l\"] = [l, l]; \
+                      var result = [l, \"${m}\"];";
+        assert_eq!(run(source).unwrap().to_string(), "[[1, [...]], \"{\"m\": {...}, \"l\": [[1, [...]], [1, [...]]]}\"]");
+
+        assert_eq!(run("[1][-1];"), Err("Index -1 is negative.".to_string()));
+        assert_eq!(run("[1][1] = 2;"), Err("Index 1 is out of bounds.".to_string()));
+        assert_eq!(run("[1][0.5];"), Err("Index must be an integer.".to_string()));
+        assert_eq!(run("pop([]);"), Err("Can't pop from an empty list.".to_string()));
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let source = "var result = []; var i = 0; \
+                      while (i < 3) { if (i == 1 or false) push(result, \"one\"); else push(result, i); i = i + 1; } \
+                      for (var j = 0; j < 2 and true; j = j + 1) push(result, j * 10);";
+        assert_eq!(run(source).unwrap().to_string(), "[0, \"one\", 2, 0, 10]");
+
+        let source = "var result = []; \
+                      for (var i = 0; i < 5; i = i + 1) { { if (i == 1) continue; } if (i == 3) break; push(result, i); } \
+                      for (x in [1, 2, 3]) { while (true) break; if (x == 2) continue; push(result, x); }";
+        assert_eq!(run(source).unwrap().to_string(), "[0, 2, 1, 3]");
+    }
+
+    #[test]
+    fn test_numbers() {
+        let source = "var result = [7 / 2, 7 ~/ 2, -7 ~/ 2, -7 % 3, 7 % -3, 6 / 3, 1 + 0.5, 2 * 1.5, 7.5 ~/ 2, -7.5 % 2, \
+                      1 == 1.0, 3 > 2.5, int(-2.7), int(\" 42 \"), float(3), len([1, 2, 3]) + 0];";
+        let expected = "[3.5, 3, -4, 2, -2, 2.0, 1.5, 3.0, 3.0, 0.5, true, true, -2, 42, 3.0, 3]";
+        assert_eq!(run(source).unwrap().to_string(), expected);
+
+        // Beyond 2^53, floats can't tell neighbouring integers apart.
+        assert_eq!(run("var result = 9007199254740993 - 9007199254740992;"), Ok(Value::Int(1)));
+        assert_eq!(run("var result = 9007199254740993 == 9007199254740992.0;"), Ok(Value::Bool(false)));
+
+        assert_eq!(run("9223372036854775807 + 1;"), Err("Integer overflow.".to_string()));
+        assert_eq!(run("(-9223372036854775807 - 1) ~/ -1;"), Err("Integer overflow.".to_string()));
+        assert_eq!(run("var result = (-9223372036854775807 - 1) % -1;"), Ok(Value::Int(0)));
+        assert_eq!(run("1 % 0;"), Err("Division by zero.".to_string()));
+        assert_eq!(run("int(\"1.5\");"), Err("Can't convert \"1.5\" to an integer.".to_string()));
+    }
+
+    #[test]
+    fn test_exact_numbers() {
+        let source = "var result = [9223372036854775807n + 1, -2n % 3, 7n ~/ -2, 1.10d + 2.205d, 1.10d * 3, \
+                      1d / 3, 1.10d == 1.1d, 2n == 2.0, 2.5d > 2n, {1.0d: 1}[1], decimal(0.1) + 0.2d, int(2.9d)];";
+        let expected = "[9223372036854775808, 1, -4, 3.305, 3.30, 0.3333333333333333333333333333, true, true, true, 1, 0.3, 2]";
+        assert_eq!(run(source).unwrap().to_string(), expected);
+
+        assert_eq!(run("1.5d + 0.5;"), Err("Can't mix decimals and floats.".to_string()));
+        assert_eq!(run("1d / 0;"), Err("Division by zero.".to_string()));
+    }
+
+    #[test]
+    fn test_operators() {
+        let source = "var result = [2 ** 3 ** 2, -2 ** 2, 2 ** -1, 2n ** 64, 1.5d ** 2, 6 & 3 | 8 ^ 1, \
+                      1 << 62, -16 >> 2, ~5, -6n & 255, (-1n << 70) | 1];";
+        let expected = "[512, -4, 0.5, 18446744073709551616, 2.25, 11, 4611686018427387904, -4, -6, 250, \
+                        -1180591620717411303423]";
+        assert_eq!(run(source).unwrap().to_string(), expected);
+
+        // Each target is evaluated once, even when read and written.
+        let source = "var calls = 0; fun i() { calls += 1; return 0; } \
+                      var l = [1]; l[i()] += 10; l[i()]++; var x = 5; x *= 2; \
+                      var result = [l, calls, x++, x, --x];";
+        assert_eq!(run(source).unwrap().to_string(), "[[12], 2, 10, 11, 10]");
+
+        assert_eq!(run("1 << 63;"), Err("Integer overflow.".to_string()));
+        assert_eq!(run("1.5 | 1;"), Err("Operands must be two integers.".to_string()));
+        assert_eq!(run("0.00d ** 3000000000;"), Err("Right operand is too large.".to_string()));
+        assert_eq!(run("0.01d ** -3000000000;"), Err("Right operand is too large.".to_string()));
+        assert_eq!(run("var s = \"a\"; s++;"), Err("Operand must be a number.".to_string()));
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let source = "var name = \"Ann\"; var n = 2; \
+                      var result = \"Hi ${name}, ${n + 1} items: ${[n, \"${n}\"]} ${ {1: nil} }${1.0}\";";
+        assert_eq!(run(source), Ok(Value::String("Hi Ann, 3 items: [2, \"2\"] {1: nil}1.0".into())));
+    }
+
+    #[test]
+    fn test_string_methods() {
+        let source = "var s = \" héllo, wörld \".trim(); var upper = s.upper; \
+                      var result = [s.len(), len(s), upper(), s.split(\", \"), s.contains(\"wö\"), s.find(\"l\"), \
+                      s.find(\"z\"), s.replace(\"l\", \"L\"), s.startsWith(\"hé\"), \"hé\".chars(), s[1]];";
+        let expected = "[12, 12, \"HÉLLO, WÖRLD\", [\"héllo\", \"wörld\"], true, 2, nil, \"héLLo, wörLd\", true, \
+                        [\"h\", \"é\"], \"é\"]";
+        assert_eq!(run(source).unwrap().to_string(), expected);
+
+        assert_eq!(run("\"a\".size();"), Err("Undefined method 'size'.".to_string()));
+        assert_eq!(run("\"a\".split(1);"), Err("split() expects a string.".to_string()));
+    }
+
+    #[test]
+    fn test_exceptions() {
+        let source = "var result = []; fun risky(n) { if (n > 1) throw \"too big\"; return n; } \
+                      try { push(result, risky(1)); risky(2); push(result, 0); } \
+                      catch (e) { push(result, e); } finally { push(result, \"finally\"); } \
+                      try {\n nope; } catch (e) { push(result, [e.message, e.line]); } \
+                      try { try { 1 + nil; } finally { push(result, \"inner\"); } } catch (e) { push(result, e.message); } \
+                      try { try { [][0]; } catch (e) { throw e; } } catch (e) { push(result, e.line); } \
+                      fun f() { try { return 1; } finally { push(result, \"cleanup\"); } } push(result, f());";
+        let expected = "[1, \"too big\", \"finally\", [\"Undefined variable 'nope'.\", 2], \"inner\", \
+                        \"Operands must be two numbers or two strings.\", 2, \"cleanup\", 1]";
+        assert_eq!(run(source).unwrap().to_string(), expected);
+
+        assert_eq!(run("throw [1];"), Err("Uncaught exception: [1]".to_string()));
+        assert_eq!(run("try { throw 1; } finally {}"), Err("Uncaught exception: 1".to_string()));
+    }
+
+    #[test]
+    fn test_stack_traces() {
+        let source = "fun check(n) {\n if (n > 2) nope; }\nvar walk = (n) =>\n check(n);\n\
+                      fun loop(n) { for (i in range(0, n, 1)) { walk(i); } }\nloop(5);";
+        let interner = Rc::new(Interner::new());
+        let mut interpreter = Interpreter::new(interner.clone());
+        let error = interpreter.interprete(&parse(source, &interner)).unwrap_err();
+        let expected = "Undefined variable 'nope'.\n[line 2] in check()\n[line 4] in <fn>\n\
+                        [line 5] in loop()\n[line 6] in script";
+        assert_eq!(error.to_string(), expected);
+
+        let source = "fun down(n) { if (n > 0) { return down(n - 1) + 1; } return 0; }\nvar result = [down(100)];\n\
+                      try { down(2000); } catch (e) { push(result, e.message); }";
+        assert_eq!(run(source).unwrap().to_string(), "[100, \"Stack overflow.\"]");
+        let error = interpreter.interprete(&parse("fun down(n) {\n return down(n + 1); }\n\ndown(0);", &interner)).unwrap_err();
+        let expected = format!("Stack overflow.\n[line 2] in down()\n[previous line repeated {} more times]\n[line 4] in script", Limits::default().max_call_depth - 1);
+        assert_eq!(error.to_string(), expected);
+
+        // Deep expressions between calls don't overflow the host stack
+        // before the call depth runs out.
+        let nested = format!("{}down(n - 1){}", "(".repeat(100), ")".repeat(100));
+        let source = format!("fun down(n) {{ if (n == 0) return 0; return {} + 1; }} var result = down(1000);", nested);
+        assert_eq!(run(&source), Ok(Value::Int(1000)));
+    }
+
+    #[test]
+    fn test_limits() {
+        use std::time::Duration;
+        use LimitExceeded::*;
+
+        let run_limited = |source: &str, limits: Limits| {
+            let interner = Rc::new(Interner::new());
+            let mut interpreter = Interpreter::with_limits(interner.clone(), limits);
+            let result = interpreter.interprete(&parse(source, &interner));
+            result.map_err(|e| (e.limit_exceeded(), e.message))
+        };
+        let error = |limit, message: &str| Err((limit, message.to_string()));
+
+        let steps = Limits { max_steps: Some(100), ..Limits::default() };
+        assert_eq!(run_limited("var i = 0; while (i < 10) i++;", steps.clone()), Ok(()));
+        let source = "while (true) try { nil; } catch (e) {}";
+        assert_eq!(run_limited(source, steps), error(Some(Steps), "Step limit exceeded."));
+
+        let timeout = Limits { timeout: Some(Duration::from_millis(10)), ..Limits::default() };
+        assert_eq!(run_limited("while (true) {}", timeout.clone()), error(Some(Timeout), "Time limit exceeded."));
+        let source = "try { while (true) {} } catch (e) { while (true) {} } finally { while (true) {} }";
+        assert_eq!(run_limited(source, timeout.clone()), error(Some(Timeout), "Time limit exceeded."));
+        // One operation on large numbers gives up at the deadline too.
+        let source = "try { print len(\"${3n ** 1500000}\"); } catch (e) {}";
+        assert_eq!(run_limited(source, timeout), error(Some(Timeout), "Time limit exceeded."));
+
+        let depth = Limits { max_call_depth: 10, ..Limits::default() };
+        let source = "fun f(n) { return n == 0 ? 0 : f(n - 1); } f(9); f(10);";
+        assert_eq!(run_limited(source, depth), error(Some(CallDepth), "Stack overflow."));
+
+        let size = Limits { max_value_size: Some(8), ..Limits::default() };
+        let source = "var s = \"abcd\"; s = s + s; try { s + s; } catch (e) {}";
+        assert_eq!(run_limited(source, size.clone()), error(Some(ValueSize), "Value size limit exceeded."));
+        let source = "var list = []; while (true) push(list, 1);";
+        assert_eq!(run_limited(source, size.clone()), error(Some(ValueSize), "Value size limit exceeded."));
+        // Numbers count their digits, and results too large are refused
+        // before they are computed.
+        assert_eq!(run_limited("9999999n * 10; 1n ** 4000000000; 0n << 4000000000;", size.clone()), Ok(()));
+        assert_eq!(run_limited("999999999n * 10;", size.clone()), error(Some(ValueSize), "Value size limit exceeded."));
+        for source in ["2n ** 4000000000;", "1n << 4000000000;", "var x = [2n]; x[0] = x[0] ** 4000000000;", "0.5d ** -4000000000;"] {
+            assert_eq!(run_limited(source, size.clone()), error(Some(ValueSize), "Value size limit exceeded."), "{}", source);
+        }
+
+        let sandbox = Limits { allow_io: false, ..Limits::default() };
+        assert_eq!(run_limited("clock();", sandbox.clone()), error(None, "Undefined variable 'clock'."));
+        assert_eq!(run_limited("import \"x.lox\";", sandbox), error(None, "Imports are disabled."));
+        assert!(run("var result = clock();").unwrap() != Value::Null);
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        // Source nested however deep is parsed, resolved and run without
+        // overflowing the host stack.
+        let n = 10000;
+        let source = format!(
+            "var a; var result = [{}1{}, {}1, {}1{}]; {}a = 1; {}{}",
+            "(".repeat(n),
+            ")".repeat(n),
+            "- ".repeat(n),
+            "true ? ".repeat(n),
+            " : 0".repeat(n),
+            "a = ".repeat(n),
+            "if (true) { ".repeat(n),
+            "}".repeat(n)
+        );
+        assert_eq!(run(&source).unwrap().to_string(), "[1, 1, 1]");
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let interner = Rc::new(Interner::new());
+        let mut interpreter = Interpreter::new(interner.clone());
+        let interrupt = interpreter.interrupt_handle();
+        let waiter = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            interrupt.interrupt();
+        });
+
+        let error = interpreter.interprete(&parse("var n = 0; try { while (true) n++; } finally { while (true) {} }", &interner));
+        waiter.join().unwrap();
+        let error = error.unwrap_err();
+        assert_eq!((error.limit_exceeded(), error.message()), (Some(LimitExceeded::Interrupted), "Interrupted."));
+
+        // The program's state outlives it, and the next one runs as usual.
+        assert!(interpreter.interprete(&parse("var result = n > 0;", &interner)).is_ok());
+        let result = interpreter.environment.borrow().get(interner.intern("result"));
+        assert_eq!(result, Some(Value::Bool(true)));
+    }
+
+    #[test]
+    fn test_for_in() {
+        let source = "var result = []; var list = [1, 2]; \
+                      for (x in list) { if (x < 3) push(list, x + 2); push(result, x); } \
+                      for (k in {\"a\": 1, \"b\": 2}) push(result, k); \
+                      for (c in \"hé\") push(result, c); \
+                      for (n in range(3, 0, -1.5)) push(result, n);";
+        let expected = "[1, 2, 3, 4, \"a\", \"b\", \"h\", \"é\", 3.0, 1.5]";
+        assert_eq!(run(source).unwrap().to_string(), expected);
+
+        // Each loop gets a fresh iterator from the map's `iterator`.
+        let source = "fun countdown(from) { return {\"iterator\": fun () { \
+                          var n = from; \
+                          return {\"next\": fun () { if (n == 0) return nil; n = n - 1; return n + 1; }}; \
+                      }}; } \
+                      var result = []; \
+                      for (x in countdown(2)) for (y in countdown(x)) push(result, [x, y]);";
+        assert_eq!(run(source).unwrap().to_string(), "[[2, 2], [2, 1], [1, 1]]");
+
+        assert_eq!(run("for (x in 1) {}"), Err("Can only iterate over lists, maps, strings and ranges.".to_string()));
+        let source = "for (x in {\"iterator\": fun () { return {}; }}) {}";
+        assert_eq!(run(source), Err("Iterators must have a 'next' function.".to_string()));
+        assert_eq!(run("range(0, 1, 0);"), Err("range() step must be a nonzero number.".to_string()));
+    }
+
+    #[test]
+    fn test_functions() {
+        let source = "fun counter() { var n = 0; return fun () { n = n + 1; return n; }; } \
+                      var next = counter(); next(); \
+                      var twice = (f, x) => f(f(x)); \
+                      fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); } \
+                      var result = [next(), twice((x) => x * 2, 5), fib(10), (() => nil)(), fib];";
+        assert_eq!(run(source).unwrap().to_string(), "[2, 20, 55, nil, <fn fib>]");
+
+        assert_eq!(run("((a) => a)();"), Err("Expected 1 arguments but got 0.".to_string()));
+
+        // A ternary only runs the branch it picks, so recursion through one
+        // ends.
+        let source = "fun fact(n) { return n <= 1 ? 1 : n * fact(n - 1); } var l = [1, 2]; \
+                      var result = [fact(5), len(l) > 0 ? pop(l) : pop(l), l];";
+        assert_eq!(run(source).unwrap().to_string(), "[120, 2, [1]]");
+
+        // A closure sees the variables in scope where it was declared, even
+        // when a later declaration shadows one of them.
+        let source = "var a = \"global\"; var result = []; \
+                      { fun show() { push(result, a); } show(); var a = \"block\"; show(); a = \"set\"; show(); }";
+        assert_eq!(run(source).unwrap().to_string(), "[\"global\", \"global\", \"global\"]");
+    }
+
+    #[test]
+    fn test_maps() {
+        let source = "var m = {\"b\": 1, \"a\": [2],}; m[\"c\"] = 3; m[\"b\"] = m[\"b\"] + 1; \
+                      { var m = {}; m[true] = 0; } remove(m, \"a\"); \
+                      var result = [m, keys(m), values(m), len(m), has(m, \"a\"), {1: nil}[1.0]];";
+        assert_eq!(run(source).unwrap().to_string(), "[{\"b\": 2, \"c\": 3}, [\"b\", \"c\"], [2, 3], 2, false, nil]");
+
+        // A map literal can also start a statement; this one is not a block.
+        assert_eq!(run("{\"a\": 1}[\"a\"]; var result = {-0: 1}[0];"), Ok(Value::Int(1)));
+
+        assert_eq!(run("var m = {}; m[\"a\"];"), Err("Undefined key \"a\".".to_string()));
+        assert_eq!(
+            run("var m = {}; m[[]] = 1;"),
+            Err("Map keys must be nil, booleans, numbers or strings.".to_string())
+        );
+    }
+
+    #[test]
+    fn test_modules() {
+        let directory = std::env::temp_dir().join(format!("primox-modules-{}", std::process::id()));
+        fs::create_dir_all(directory.join("lib")).unwrap();
+        let files = [
+            ("lib/util.lox", "import \"count.lox\"; var name = \"util\"; fun twice(x) { return 2 * count.add(x); }"),
+            ("lib/count.lox", "var calls = 0; fun add(x) { calls = calls + 1; return x; }"),
+            ("a.lox", "import \"b.lox\";"),
+            ("b.lox", "import \"a.lox\";"),
+            ("bad.lox", "var = 1;"),
+        ];
+        for (name, source) in files {
+            fs::write(directory.join(name), source).unwrap();
+        }
+
+        let run_file = |source: &str| {
+            let interner = Rc::new(Interner::new());
+            let ast = Lox::compile(source, &interner, Some(directory.join("main.lox"))).unwrap();
+            let mut interpreter = Interpreter::new(interner.clone());
+            interpreter.interprete(&Rc::new(ast)).map_err(|e| e.message)?;
+            let result = interpreter.environment.borrow().get(interner.intern("result"));
+            Ok::<_, String>(result.unwrap().to_string())
+        };
+
+        let source = "import \"lib/util.lox\"; import \"lib/count.lox\" as c; var name = \"main\"; \
+                      var result = [util.twice(3), util.name, name, c.calls, util.count == c, util];";
+        assert_eq!(run_file(source), Ok("[6, \"util\", \"main\", 1, true, <module util>]".to_string()));
+
+        assert_eq!(run_file("import \"a.lox\";"), Err("Import cycle: a.lox -> b.lox -> a.lox.".to_string()));
+        assert_eq!(run_file("import \"lib/util.lox\"; util.calls;"), Err("Module 'util' has no 'calls'.".to_string()));
+
+        // A module's globals can be set, compound assignments and updates
+        // included, but not created.
+        let source = "import \"lib/count.lox\" as c; c.calls += 10; c.calls++; \
+                      var result = [c.calls, ++c.calls, c.add(1), c.calls, c.calls = 0];";
+        assert_eq!(run_file(source), Ok("[11, 12, 1, 13, 0]".to_string()));
+        assert_eq!(run_file("import \"lib/count.lox\"; count.total = 1;"), Err("Module 'count' has no 'total'.".to_string()));
+        assert_eq!(run_file("\"abc\".len = 1;"), Err("Only the globals of modules can be set.".to_string()));
+        let bad = run_file("import \"bad.lox\";").unwrap_err();
+        assert!(bad.starts_with(&format!("Errors in module '{}':\n[line 1]", directory.join("bad.lox").display())));
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
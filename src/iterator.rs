@@ -0,0 +1,80 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::value::{MapKey, Range, Value};
+
+/// The runtime side of `for (x in ...)`: the values a loop walks through,
+/// produced one at a time. `ValueIterator::new` decides which values are
+/// iterable, and how.
+///
+/// Scripts make their own iterables with maps holding functions: a map
+/// with an `iterator` function is walked through the map that function
+/// returns, whose `next` function gives each value in turn, then nil.
+pub(crate) enum ValueIterator {
+    // Lists are read live, so elements pushed during the loop are visited.
+    List { list: Rc<RefCell<Vec<Value>>>, index: usize },
+    // Maps yield their keys, as they were when the loop started.
+    Keys(std::vec::IntoIter<Value>),
+    // Strings yield their characters, each as a one-character string.
+    Chars { string: Rc<str>, offset: usize },
+    Range { range: Range, index: usize },
+    // The `next` function of a script's iterator, which the interpreter
+    // calls.
+    Script { next: Value },
+}
+
+impl ValueIterator {
+    pub(crate) fn new(value: Value) -> Result<Self, String> {
+        match value {
+            Value::List(list) => Ok(ValueIterator::List { list, index: 0 }),
+            Value::Map(map) => {
+                let keys: Vec<Value> = map.borrow().keys().map(|key| key.value().clone()).collect();
+                Ok(ValueIterator::Keys(keys.into_iter()))
+            }
+            Value::String(string) => Ok(ValueIterator::Chars { string, offset: 0 }),
+            Value::Range(range) => Ok(ValueIterator::Range { range, index: 0 }),
+            _ => Err("Can only iterate over lists, maps, strings and ranges.".to_string()),
+        }
+    }
+
+    /// The next value, if any. `call` calls the `next` function of a
+    /// script's iterator.
+    pub(crate) fn next<E>(&mut self, call: impl FnOnce(&Value) -> Result<Value, E>) -> Result<Option<Value>, E> {
+        let value = match self {
+            ValueIterator::List { list, index } => {
+                let value = list.borrow().get(*index).cloned();
+                *index += value.is_some() as usize;
+                value
+            }
+            ValueIterator::Keys(keys) => keys.next(),
+            ValueIterator::Chars { string, offset } => {
+                let c = string[*offset..].chars().next();
+                *offset += c.map_or(0, char::len_utf8);
+                c.map(|c| Value::String(c.to_string().into()))
+            }
+            ValueIterator::Range { range, index } => {
+                let n = range.get(*index);
+                *index += n.is_some() as usize;
+                n
+            }
+            ValueIterator::Script { next } => match call(next)? {
+                Value::Null => None,
+                value => Some(value),
+            },
+        };
+        Ok(value)
+    }
+}
+
+/// The function a script's map holds under `name`, as iterables and
+/// iterators do.
+pub(crate) fn script_function(value: &Value, name: &str) -> Option<Value> {
+    let Value::Map(map) = value else {
+        return None;
+    };
+    let key = MapKey::new(Value::String(name.into())).ok()?;
+    match map.borrow().get(&key) {
+        Some(function @ Value::Function(_)) => Some(function.clone()),
+        _ => None,
+    }
+}
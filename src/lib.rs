@@ -1,39 +1,98 @@
+mod analysis;
+mod ast;
+// Debugging aid for inspecting parsed trees; not wired into `Lox::run`.
+#[allow(dead_code)]
 mod ast_printer;
+mod bignum;
+mod dap;
+mod debugger;
+mod environment;
 mod expr;
+mod formatter;
+mod function;
+mod hook;
+mod interner;
 mod interpreter;
+mod iterator;
+mod limits;
+mod lsp;
+mod module;
+mod natives;
 mod parser;
+mod protocol;
+mod resolver;
 mod scanner;
 mod stmt;
+#[cfg(test)]
+mod test_support;
 mod token;
 mod token_type;
+mod value;
 
 #[cfg(feature = "rpn-printer")]
+#[allow(dead_code)]
 mod rpn_printer;
 
+use std::fmt::Display;
 use std::fs;
 use std::io;
 use std::io::Write;
+use std::path::PathBuf;
 use std::process;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
+use interner::{Interner, Symbol};
 use interpreter::Interpreter;
+pub use dap::serve_dap;
+pub use debugger::Debugger;
+pub use formatter::format_source;
+pub use hook::{Hook, Pause};
+pub use interpreter::{RuntimeError, StackFrame};
+pub use limits::{Interrupt, LimitExceeded, Limits};
+pub use lsp::serve_lsp;
 use parser::Parser;
+use resolver::Resolver;
 use scanner::Scanner;
+use ast::Ast;
 
 pub struct Lox {
+    interner: Rc<Interner>,
     interpreter: Interpreter,
     had_error: bool,
     had_runtime_error: bool,
 }
 
+/// A parsed script. Running it does not consume it, so it can be parsed once
+/// and executed any number of times with `Lox::execute`.
+pub struct Program {
+    ast: Rc<Ast>,
+}
+
+impl Default for Lox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Lox {
     pub fn new() -> Self {
-        let interpreter = Interpreter::new();
-        Self { interpreter, had_error: false, had_runtime_error: false, }
+        Self::with_limits(Limits::default())
+    }
+
+    /// An interpreter whose programs run within `limits`.
+    pub fn with_limits(limits: Limits) -> Self {
+        let interner = Rc::new(Interner::new());
+        let interpreter = Interpreter::with_limits(interner.clone(), limits);
+        Self { interner, interpreter, had_error: false, had_runtime_error: false, }
     }
 
     pub fn run_file(&mut self, path: &str) {
         let content: String = fs::read_to_string(path).unwrap();
-        self.run(content);
+        if let Some(program) = self.parse_file(&content, Some(path.into())) {
+            self.execute(&program);
+        }
         if self.had_error {
             process::exit(65);
         }
@@ -43,6 +102,23 @@ impl Lox {
     }
 
     pub fn run_prompt(&mut self) {
+        // Ctrl-C stops the line being run, rather than the whole session.
+        // At the prompt it starts a fresh line, as shells do.
+        let interrupt = self.interrupt_handle();
+        let running = Arc::new(AtomicBool::new(false));
+        let handler_running = running.clone();
+        let handler = move || {
+            if handler_running.load(Ordering::SeqCst) {
+                interrupt.interrupt();
+            } else {
+                print!("\n> ");
+                let _ = io::stdout().flush();
+            }
+        };
+        if let Err(error) = ctrlc::set_handler(handler) {
+            println!("error: {error}");
+        }
+
         loop {
             print!("> ");
             // https://stackoverflow.com/a/34993992
@@ -54,7 +130,11 @@ impl Lox {
                     if n == 0 {
                         break;
                     } else {
-                        self.run(buffer);
+                        // Forget a Ctrl-C that came too late for the last line.
+                        self.interrupt_handle().take();
+                        running.store(true, Ordering::SeqCst);
+                        self.run(&buffer);
+                        running.store(false, Ordering::SeqCst);
                         self.had_error = false;
                     }
                 }
@@ -66,27 +146,83 @@ impl Lox {
         }
     }
 
-    fn run(&mut self, source: String) {
-        let mut scanner = Scanner::new(source);
-        let tokens = scanner.scan_tokens(self);
+    /// Calls `hook` before each statement run from now on.
+    pub fn set_hook(&mut self, hook: impl Hook + 'static) {
+        self.interpreter.set_hook(Some(Box::new(hook)));
+    }
+
+    /// Sends what programs print to `output` rather than to stdout.
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.interpreter.set_output(Box::new(output));
+    }
+
+    /// A handle that stops the program being run, from any thread.
+    pub fn interrupt_handle(&self) -> Interrupt {
+        self.interpreter.interrupt_handle()
+    }
 
-        let mut parser = Parser::new(&tokens);
-        let statements = match parser.parse() {
-            Ok(expr) => expr,
-            Err(e) => {
-                self.error(e);
-                return;
+    /// Scans, parses and resolves `source`, reporting any error. Returns
+    /// `None` if the source is not a valid program.
+    pub fn parse(&mut self, source: &str) -> Option<Program> {
+        self.parse_file(source, None)
+    }
+
+    // Like `parse`, for source read from the file at `path`.
+    fn parse_file(&mut self, source: &str, path: Option<PathBuf>) -> Option<Program> {
+        match Self::compile(source, &self.interner, path) {
+            Ok(ast) => Some(Program { ast: Rc::new(ast) }),
+            Err(errors) => {
+                for e in errors {
+                    self.error(e);
+                }
+                None
             }
-        };
+        }
+    }
+
+    /// Scans, parses and resolves `source`, read from the file at `path` if
+    /// any. Fails with the messages of every error found.
+    pub(crate) fn compile(source: &str, interner: &Rc<Interner>, path: Option<PathBuf>) -> Result<Ast, Vec<String>> {
+        let mut ast = Self::compile_within(source, interner, vec![])?;
+        ast.path = path;
+        Ok(ast)
+    }
 
-        self.interpreter.interprete(statements);
+    /// Like `compile`, for code that runs within local scopes declaring
+    /// the names in `scopes`, outermost first.
+    pub(crate) fn compile_within(source: &str, interner: &Rc<Interner>, scopes: Vec<Vec<Symbol>>) -> Result<Ast, Vec<String>> {
+        let scanner = Scanner::new(source, interner.clone());
+        let parser = Parser::new(scanner, interner.clone());
+        let mut ast = parser.parse().map_err(|errors| errors.iter().map(|e| e.to_string()).collect::<Vec<_>>())?;
+        let resolver = Resolver::new(source).within(scopes);
+        ast.locals = resolver.resolve(&ast).map_err(|errors| errors.iter().map(|e| e.to_string()).collect::<Vec<_>>())?;
+        Ok(ast)
+    }
+
+    /// Runs a parsed program, reporting a runtime error if one occurs.
+    pub fn execute(&mut self, program: &Program) {
+        if let Err(e) = self.try_execute(program) {
+            self.runtime_error(e);
+        }
+    }
+
+    /// Runs a parsed program, returning its runtime error, if any, to the
+    /// caller instead of reporting it.
+    pub fn try_execute(&mut self, program: &Program) -> Result<(), RuntimeError> {
+        self.interpreter.interprete(&program.ast)
+    }
+
+    fn run(&mut self, source: &str) {
+        if let Some(program) = self.parse(source) {
+            self.execute(&program);
+        }
     }
 
     fn error_message(line: usize, err: &str, message: &str) -> String {
         format!("[line {line}] Error{err}: {message}")
     }
 
-    fn error(&mut self, e: impl std::error::Error) {
+    fn error(&mut self, e: impl Display) {
         self.had_error = true;
         println!("{}", e);
     }
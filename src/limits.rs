@@ -0,0 +1,90 @@
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Bounds on what a program may do, for running scripts that can't be
+/// trusted. The defaults put no bound on anything but the call depth.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// The most statements and expressions one `execute` may evaluate.
+    pub max_steps: Option<u64>,
+    /// How long one `execute` may run for.
+    pub timeout: Option<Duration>,
+    /// How deep calls may nest.
+    pub max_call_depth: usize,
+    /// The longest string, in bytes, the most elements of a list or a map,
+    /// and the most digits of a big integer or a decimal, a program may
+    /// build.
+    pub max_value_size: Option<usize>,
+    /// Whether programs may reach outside the interpreter, reading the
+    /// clock with `clock()` and files with `import`.
+    pub allow_io: bool,
+}
+
+/// The limit a program ran into, or its interruption by the host. Apart
+/// from a stack overflow, which unwinds to a `catch` like any runtime
+/// error, these go straight to the host, skipping `finally` blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    Steps,
+    Timeout,
+    CallDepth,
+    ValueSize,
+    Interrupted,
+}
+
+/// Stops the program a `Lox` is running, from any thread. The program
+/// checks for it every so often and fails with an "Interrupted." error.
+/// Interrupting while no program runs stops the next one.
+#[derive(Debug, Clone, Default)]
+pub struct Interrupt(Arc<AtomicBool>);
+
+impl Interrupt {
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the program was interrupted, clearing the request.
+    pub(crate) fn take(&self) -> bool {
+        self.0.swap(false, Ordering::Relaxed)
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_steps: None,
+            timeout: None,
+            max_call_depth: 1024,
+            max_value_size: None,
+            allow_io: true,
+        }
+    }
+}
+
+// The host stack left when a recursive walk grows it, and how much each
+// growth adds.
+const STACK_RED_ZONE: usize = 128 * 1024;
+const STACK_SEGMENT: usize = 2 * 1024 * 1024;
+
+/// Runs `f`, first moving it onto a new stack segment on the heap if the
+/// host stack is running low. The parser and every pass over a tree call
+/// it for each nested node, so source nested however deep can't overflow
+/// the host stack.
+pub(crate) fn with_stack<R>(f: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, f)
+}
+
+impl Display for LimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            LimitExceeded::Steps => "Step limit exceeded.",
+            LimitExceeded::Timeout => "Time limit exceeded.",
+            LimitExceeded::CallDepth => "Stack overflow.",
+            LimitExceeded::ValueSize => "Value size limit exceeded.",
+            LimitExceeded::Interrupted => "Interrupted.",
+        };
+        write!(f, "{}", message)
+    }
+}
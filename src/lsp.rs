@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+use serde_json::{json, Value};
+
+use crate::analysis::{Analysis, Analyzer, Definition, DefinitionKind};
+use crate::ast::Span;
+use crate::interner::Interner;
+use crate::natives::{IO_NATIVES, NATIVES};
+use crate::parser::Parser;
+use crate::protocol::{read_message, write_message};
+use crate::resolver::Resolver;
+use crate::scanner::{Scanner, KEYWORDS};
+use crate::value::NativeFunction;
+
+// Error codes from JSON-RPC.
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// An open file, as last sent by the editor.
+struct Document {
+    text: String,
+    // Where each line starts, in bytes.
+    lines: Vec<usize>,
+    // What the text declares, if it parses.
+    analysis: Option<Analysis>,
+    // The globals of the last text that parsed, still offered as
+    // completions while the text is broken.
+    globals: Vec<Rc<str>>,
+}
+
+/// Serves the Language Server Protocol over `input` and `output`, until the
+/// editor sends `exit` or closes the input. Files are checked as they are
+/// opened and changed, with the same scanner, parser and resolver that
+/// `Lox` runs them with.
+pub fn serve_lsp(mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let mut documents: HashMap<String, Document> = HashMap::new();
+    while let Some(message) = read_message(&mut input)? {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_string();
+
+        // Notifications have no id, and get no response.
+        let Some(id) = message.get("id") else {
+            let text = match method {
+                "textDocument/didOpen" => params["textDocument"]["text"].as_str(),
+                // Changes come whole, as asked for in `initialize`.
+                "textDocument/didChange" => params["contentChanges"].as_array().and_then(|changes| changes.last()?["text"].as_str()),
+                "textDocument/didClose" => {
+                    documents.remove(&uri);
+                    publish_diagnostics(&mut output, &uri, vec![])?;
+                    continue;
+                }
+                "exit" => return Ok(()),
+                _ => continue,
+            };
+            if let Some(text) = text {
+                let globals = documents.remove(&uri).map(|document| document.globals).unwrap_or_default();
+                let (document, diagnostics) = Document::new(text.to_string(), globals);
+                documents.insert(uri.clone(), document);
+                publish_diagnostics(&mut output, &uri, diagnostics)?;
+            }
+            continue;
+        };
+
+        let result = match (method, documents.get(&uri)) {
+            ("initialize", _) => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "hoverProvider": true,
+                    "documentSymbolProvider": true,
+                    "completionProvider": {},
+                },
+                "serverInfo": { "name": "primox" },
+            })),
+            ("shutdown", _) => Ok(Value::Null),
+            ("textDocument/definition", Some(document)) => Ok(document.definition(&uri, params)),
+            ("textDocument/references", Some(document)) => Ok(document.references(&uri, params)),
+            ("textDocument/hover", Some(document)) => Ok(document.hover(params)),
+            ("textDocument/documentSymbol", Some(document)) => Ok(document.symbols()),
+            ("textDocument/completion", Some(document)) => Ok(document.completions(params)),
+            (method, None) if method.starts_with("textDocument/") => Err((INVALID_PARAMS, format!("Unknown document '{}'.", uri))),
+            (method, _) => Err((METHOD_NOT_FOUND, format!("Unsupported method '{}'.", method))),
+        };
+
+        let response = match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }),
+        };
+        write_message(&mut output, &response)?;
+    }
+    Ok(())
+}
+
+fn publish_diagnostics(output: &mut impl Write, uri: &str, diagnostics: Vec<Value>) -> io::Result<()> {
+    let params = json!({ "uri": uri, "diagnostics": diagnostics });
+    write_message(output, &json!({ "jsonrpc": "2.0", "method": "textDocument/publishDiagnostics", "params": params }))
+}
+
+fn native_signature(native: &NativeFunction) -> String {
+    format!("native fun {}({})", native.name, vec!["_"; native.arity].join(", "))
+}
+
+// Kinds of symbols and completions, as numbered by the protocol.
+fn symbol_kind(definition: &Definition) -> u64 {
+    match definition.kind {
+        DefinitionKind::Function => 12,
+        DefinitionKind::Import => 2,
+        DefinitionKind::Parameter | DefinitionKind::Variable => 13,
+    }
+}
+
+fn completion_kind(definition: &Definition) -> u64 {
+    match (definition.kind, definition.inferred) {
+        (DefinitionKind::Function, _) | (_, Some("function")) => 3,
+        (DefinitionKind::Import, _) => 9,
+        _ => 6,
+    }
+}
+
+impl Document {
+    /// Checks `text`, returning the document along with its diagnostics.
+    fn new(text: String, globals: Vec<Rc<str>>) -> (Self, Vec<Value>) {
+        let mut lines = vec![0];
+        lines.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+        let mut document = Self {
+            text,
+            lines,
+            analysis: None,
+            globals,
+        };
+
+        let interner = Rc::new(Interner::new());
+        let scanner = Scanner::new(&document.text, interner.clone());
+        let errors: Vec<(Span, String)> = match Parser::new(scanner, interner.clone()).parse() {
+            Ok(ast) => {
+                let errors = match Resolver::new(&document.text).resolve(&ast) {
+                    Ok(_) => vec![],
+                    Err(errors) => errors.iter().map(|e| (e.span(), e.message().to_string())).collect(),
+                };
+                let analysis = Analyzer::new(&interner).analyze(&ast);
+                let globals = analysis.definitions.iter().filter(|definition| definition.scope.is_none());
+                document.globals = globals.map(|definition| definition.name.clone()).collect();
+                document.analysis = Some(analysis);
+                errors
+            }
+            Err(errors) => errors.iter().map(|e| (e.span(), e.message().to_string())).collect(),
+        };
+
+        let diagnostics = errors
+            .into_iter()
+            .map(|(span, message)| json!({ "range": document.range(span), "severity": 1, "source": "primox", "message": message }))
+            .collect();
+        (document, diagnostics)
+    }
+
+    /// The position of the byte at `offset`, as a line and a column
+    /// counted in UTF-16 code units, both from 0.
+    fn position(&self, offset: usize) -> Value {
+        let line = self.lines.partition_point(|&start| start <= offset) - 1;
+        let character: usize = self.text[self.lines[line]..offset].chars().map(char::len_utf16).sum();
+        json!({ "line": line, "character": character })
+    }
+
+    fn range(&self, span: Span) -> Value {
+        json!({ "start": self.position(span.start), "end": self.position(span.end) })
+    }
+
+    /// The offset of the position in `params`, if it is in the text.
+    fn offset(&self, params: &Value) -> Option<usize> {
+        let line = params["position"]["line"].as_u64()? as usize;
+        let character = params["position"]["character"].as_u64()? as usize;
+        let start = *self.lines.get(line)?;
+        let end = self.lines.get(line + 1).copied().unwrap_or(self.text.len());
+
+        let mut units = 0;
+        for (i, c) in self.text[start..end].char_indices() {
+            if units >= character {
+                return Some(start + i);
+            }
+            units += c.len_utf16();
+        }
+        Some(end)
+    }
+
+    /// The analysis, and the definition at the position in `params`.
+    fn definition_at(&self, params: &Value) -> Option<(&Analysis, usize)> {
+        let analysis = self.analysis.as_ref()?;
+        Some((analysis, analysis.definition_at(self.offset(params)?)?))
+    }
+
+    fn definition(&self, uri: &str, params: &Value) -> Value {
+        match self.definition_at(params) {
+            Some((analysis, definition)) => json!({ "uri": uri, "range": self.range(analysis.definitions[definition].span) }),
+            None => Value::Null,
+        }
+    }
+
+    fn references(&self, uri: &str, params: &Value) -> Value {
+        let Some((analysis, definition)) = self.definition_at(params) else {
+            return json!([]);
+        };
+        let mut spans = analysis.references_to(definition);
+        if params["context"]["includeDeclaration"].as_bool().unwrap_or(true) {
+            spans.insert(0, analysis.definitions[definition].span);
+        }
+        let locations: Vec<_> = spans.into_iter().map(|span| json!({ "uri": uri, "range": self.range(span) })).collect();
+        json!(locations)
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        let (Some(analysis), Some(offset)) = (self.analysis.as_ref(), self.offset(params)) else {
+            return Value::Null;
+        };
+        let (signature, span) = match analysis.definition_at(offset) {
+            Some(definition) => {
+                let definition = &analysis.definitions[definition];
+                let span = analysis.reference_at(offset).map_or(definition.span, |reference| reference.span);
+                (definition.signature(), span)
+            }
+            None => {
+                let Some(reference) = analysis.reference_at(offset) else {
+                    return Value::Null;
+                };
+                let native = NATIVES.iter().chain(IO_NATIVES).find(|native| *native.name == *reference.name);
+                match native {
+                    Some(native) => (native_signature(native), reference.span),
+                    None => (format!("undefined {}", reference.name), reference.span),
+                }
+            }
+        };
+        json!({
+            "contents": { "kind": "markdown", "value": format!("```lox\n{}\n```", signature) },
+            "range": self.range(span),
+        })
+    }
+
+    /// The definitions outside of any function, with those inside each
+    /// function nested under it. Parameters are left out.
+    fn symbols(&self) -> Value {
+        let Some(analysis) = &self.analysis else {
+            return json!([]);
+        };
+
+        // Definitions come before the ones they contain, so children are
+        // all built by the time their container is, going backwards.
+        let mut children: Vec<Vec<Value>> = analysis.definitions.iter().map(|_| vec![]).collect();
+        let mut symbols = vec![];
+        for (i, definition) in analysis.definitions.iter().enumerate().rev() {
+            if definition.kind == DefinitionKind::Parameter {
+                continue;
+            }
+            let mut symbol = json!({
+                "name": &*definition.name,
+                "detail": definition.signature(),
+                "kind": symbol_kind(definition),
+                "range": self.range(definition.range),
+                "selectionRange": self.range(definition.span),
+            });
+            let nested = std::mem::take(&mut children[i]);
+            if !nested.is_empty() {
+                symbol["children"] = json!(nested.into_iter().rev().collect::<Vec<_>>());
+            }
+            match definition.container {
+                Some(container) => children[container].push(symbol),
+                None => symbols.push(symbol),
+            }
+        }
+        symbols.reverse();
+        json!(symbols)
+    }
+
+    /// The names that can be used at the position in `params`, then the
+    /// natives and the keywords.
+    fn completions(&self, params: &Value) -> Value {
+        let mut items = vec![];
+        match (&self.analysis, self.offset(params)) {
+            (Some(analysis), Some(offset)) => {
+                for definition in analysis.visible_at(offset) {
+                    items.push(json!({ "label": &*definition.name, "kind": completion_kind(definition), "detail": definition.signature() }));
+                }
+            }
+            _ => {
+                for name in &self.globals {
+                    items.push(json!({ "label": &**name, "kind": 6 }));
+                }
+            }
+        }
+        for native in NATIVES.iter().chain(IO_NATIVES) {
+            items.push(json!({ "label": native.name, "kind": 3, "detail": native_signature(native) }));
+        }
+        for (keyword, _) in KEYWORDS {
+            items.push(json!({ "label": keyword, "kind": 14 }));
+        }
+        json!(items)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::test_support::{read_fixture, read_messages};
+
+    #[test]
+    fn test_lsp() {
+        let mut input = vec![];
+        for message in read_fixture("fixtures/lsp/session.requests.json") {
+            write_message(&mut input, &message).unwrap();
+        }
+        let mut output = vec![];
+        serve_lsp(Cursor::new(input), &mut output).unwrap();
+
+        assert_eq!(read_messages(output), read_fixture("fixtures/lsp/session.expected.json"));
+    }
+}
@@ -1,17 +1,104 @@
 use std::env;
+use std::fs;
+use std::io::{self, BufReader, Read};
 use std::process;
 
-use primox::Lox;
+use primox::{format_source, serve_dap, serve_lsp, Debugger, Lox};
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "fmt") {
+        process::exit(fmt(&args[1..]));
+    }
+    let server = match args.first().map(String::as_str) {
+        Some("dap") => Some(serve_dap(BufReader::new(io::stdin()), io::stdout())),
+        Some("lsp") => Some(serve_lsp(io::stdin().lock(), io::stdout())),
+        _ => None,
+    };
+    if let Some(result) = server {
+        if let Err(e) = result {
+            eprintln!("Server failed: {}.", e);
+            process::exit(74);
+        }
+        return;
+    }
+    let debug = args.first().is_some_and(|arg| arg == "--debug");
+    if debug {
+        args.remove(0);
+    }
+
     let mut program = Lox::new();
-    if args.len() > 2 {
-        println!("Usage: primox [script]");
-        process::exit(1);
-    } else if args.len() == 2 {
-        program.run_file(&args[1]);
-    } else {
-        program.run_prompt();
+    match args.as_slice() {
+        [script] => {
+            if debug {
+                program.set_hook(Debugger::new(io::stdin().lock(), io::stdout()));
+            }
+            program.run_file(script);
+        }
+        [] if !debug => program.run_prompt(),
+        _ => {
+            println!("Usage: primox [--debug] [script] | primox fmt [--check] [files] | primox dap | primox lsp");
+            process::exit(1);
+        }
+    }
+}
+
+/// Formats each file in place, or only lists those not formatted with
+/// `--check`. Without files, formats stdin to stdout. Returns the exit code.
+fn fmt(args: &[String]) -> i32 {
+    let check = args.first().is_some_and(|arg| arg == "--check");
+    let paths = if check { &args[1..] } else { args };
+
+    if paths.is_empty() {
+        let mut source = String::new();
+        if let Err(e) = io::stdin().read_to_string(&mut source) {
+            eprintln!("Can't read stdin: {}.", e);
+            return 74;
+        }
+        return match format_source(&source) {
+            Ok(formatted) if check && formatted != source => {
+                println!("Not formatted: <stdin>");
+                1
+            }
+            Ok(_) if check => 0,
+            Ok(formatted) => {
+                print!("{}", formatted);
+                0
+            }
+            Err(errors) => {
+                eprintln!("{}", errors.join("\n"));
+                65
+            }
+        };
+    }
+
+    let mut code = 0;
+    for path in paths {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("Can't read '{}': {}.", path, e);
+                code = 74;
+                continue;
+            }
+        };
+        match format_source(&source) {
+            Ok(formatted) if formatted == source => {}
+            Ok(_) if check => {
+                println!("Not formatted: {}", path);
+                code = code.max(1);
+            }
+            Ok(formatted) => {
+                if let Err(e) = fs::write(path, formatted) {
+                    eprintln!("Can't write '{}': {}.", path, e);
+                    code = 74;
+                }
+            }
+            Err(errors) => {
+                eprintln!("Errors in '{}':\n{}", path, errors.join("\n"));
+                code = code.max(65);
+            }
+        }
     }
+    code
 }
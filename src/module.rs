@@ -0,0 +1,19 @@
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::rc::Rc;
+
+use crate::environment::Environment;
+
+/// A loaded module, the value an `import` binds. Its namespace is the
+/// module's own global scope, which lives on after the module has run.
+pub(crate) struct Module {
+    pub(crate) name: Rc<str>,
+    pub(crate) globals: Rc<RefCell<Environment>>,
+}
+
+// The globals may well contain functions closing over them.
+impl Debug for Module {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Module").field("name", &self.name).finish()
+    }
+}
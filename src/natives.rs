@@ -0,0 +1,297 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use indexmap::IndexMap;
+
+use crate::bignum::{BigInt, Decimal};
+use crate::value::{exact_int, MapKey, NativeFunction, Range, Value};
+
+/// Every native function, defined as a global by the interpreter.
+pub(crate) static NATIVES: &[NativeFunction] = &[
+    NativeFunction { name: "len", arity: 1, function: len },
+    NativeFunction { name: "push", arity: 2, function: push },
+    NativeFunction { name: "pop", arity: 1, function: pop },
+    NativeFunction { name: "slice", arity: 3, function: slice },
+    NativeFunction { name: "insert", arity: 3, function: insert },
+    NativeFunction { name: "remove", arity: 2, function: remove },
+    NativeFunction { name: "has", arity: 2, function: has },
+    NativeFunction { name: "keys", arity: 1, function: keys },
+    NativeFunction { name: "values", arity: 1, function: values },
+    NativeFunction { name: "range", arity: 3, function: range },
+    NativeFunction { name: "int", arity: 1, function: int },
+    NativeFunction { name: "float", arity: 1, function: float },
+    NativeFunction { name: "bigint", arity: 1, function: bigint },
+    NativeFunction { name: "decimal", arity: 1, function: decimal },
+];
+
+/// The natives that reach outside the interpreter, left out unless
+/// `Limits::allow_io` is set.
+pub(crate) static IO_NATIVES: &[NativeFunction] = &[NativeFunction { name: "clock", arity: 0, function: clock }];
+
+/// The methods of strings, looked up by name on a string value. Each is
+/// passed the string, then `arity` arguments. Lengths and indexes count
+/// Unicode scalar values, like iterating over a string does.
+pub(crate) static STRING_METHODS: &[NativeFunction] = &[
+    NativeFunction { name: "len", arity: 0, function: string_len },
+    NativeFunction { name: "upper", arity: 0, function: upper },
+    NativeFunction { name: "lower", arity: 0, function: lower },
+    NativeFunction { name: "trim", arity: 0, function: trim },
+    NativeFunction { name: "chars", arity: 0, function: chars },
+    NativeFunction { name: "split", arity: 1, function: split },
+    NativeFunction { name: "contains", arity: 1, function: contains },
+    NativeFunction { name: "startsWith", arity: 1, function: starts_with },
+    NativeFunction { name: "endsWith", arity: 1, function: ends_with },
+    NativeFunction { name: "find", arity: 1, function: find },
+    NativeFunction { name: "replace", arity: 2, function: replace },
+];
+
+/// The methods that can be looked up on `value`.
+pub(crate) fn methods(value: &Value) -> &'static [NativeFunction] {
+    match value {
+        Value::String(_) => STRING_METHODS,
+        _ => &[],
+    }
+}
+
+fn list_arg<'v>(native: &str, value: &'v Value) -> Result<&'v Rc<RefCell<Vec<Value>>>, String> {
+    match value {
+        Value::List(list) => Ok(list),
+        _ => Err(format!("{}() expects a list.", native)),
+    }
+}
+
+fn map_arg<'v>(native: &str, value: &'v Value) -> Result<&'v Rc<RefCell<IndexMap<MapKey, Value>>>, String> {
+    match value {
+        Value::Map(map) => Ok(map),
+        _ => Err(format!("{}() expects a map.", native)),
+    }
+}
+
+/// Seconds since the Unix epoch.
+fn clock(_: &[Value]) -> Result<Value, String> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|e| e.to_string())?;
+    Ok(Value::Number(now.as_secs_f64()))
+}
+
+fn len(args: &[Value]) -> Result<Value, String> {
+    match &args[0] {
+        Value::List(list) => Ok(Value::Int(list.borrow().len() as i64)),
+        Value::Map(map) => Ok(Value::Int(map.borrow().len() as i64)),
+        Value::String(_) => string_len(args),
+        _ => Err("len() expects a list, a map or a string.".to_string()),
+    }
+}
+
+fn push(args: &[Value]) -> Result<Value, String> {
+    let list = list_arg("push", &args[0])?;
+    list.borrow_mut().push(args[1].clone());
+    Ok(Value::Null)
+}
+
+fn pop(args: &[Value]) -> Result<Value, String> {
+    let list = list_arg("pop", &args[0])?;
+    let value = list.borrow_mut().pop();
+    value.ok_or_else(|| "Can't pop from an empty list.".to_string())
+}
+
+/// The elements from `start` up to, but not including, `end`.
+fn slice(args: &[Value]) -> Result<Value, String> {
+    let list = list_arg("slice", &args[0])?.borrow();
+    let end = args[2].to_index(list.len() + 1)?;
+    let start = args[1].to_index(end + 1)?;
+    Ok(Value::list(list[start..end].to_vec()))
+}
+
+fn insert(args: &[Value]) -> Result<Value, String> {
+    let mut list = list_arg("insert", &args[0])?.borrow_mut();
+    let index = args[1].to_index(list.len() + 1)?;
+    list.insert(index, args[2].clone());
+    Ok(Value::Null)
+}
+
+/// Removes the element at an index of a list, or the entry for a key of a
+/// map, and returns it.
+fn remove(args: &[Value]) -> Result<Value, String> {
+    match &args[0] {
+        Value::List(list) => {
+            let mut list = list.borrow_mut();
+            let index = args[1].to_index(list.len())?;
+            Ok(list.remove(index))
+        }
+        Value::Map(map) => {
+            let key = MapKey::new(args[1].clone())?;
+            // Shifting keeps the remaining keys in insertion order.
+            let value = map.borrow_mut().shift_remove(&key);
+            value.ok_or_else(|| format!("Undefined key {}.", key))
+        }
+        _ => Err("remove() expects a list or a map.".to_string()),
+    }
+}
+
+fn has(args: &[Value]) -> Result<Value, String> {
+    let map = map_arg("has", &args[0])?;
+    let key = MapKey::new(args[1].clone())?;
+    Ok(Value::Bool(map.borrow().contains_key(&key)))
+}
+
+/// The keys of a map, in insertion order.
+fn keys(args: &[Value]) -> Result<Value, String> {
+    let map = map_arg("keys", &args[0])?;
+    Ok(Value::list(map.borrow().keys().map(|key| key.value().clone()).collect()))
+}
+
+/// The values of a map, in the order of their keys.
+fn values(args: &[Value]) -> Result<Value, String> {
+    let map = map_arg("values", &args[0])?;
+    Ok(Value::list(map.borrow().values().cloned().collect()))
+}
+
+fn float_arg(native: &str, value: &Value) -> Result<f64, String> {
+    match value {
+        Value::Int(n) => Ok(*n as f64),
+        Value::Number(n) => Ok(*n),
+        _ => Err(format!("{}() expects numbers.", native)),
+    }
+}
+
+fn range(args: &[Value]) -> Result<Value, String> {
+    let zero_step = || "range() step must be a nonzero number.".to_string();
+    if let [Value::Int(start), Value::Int(end), Value::Int(step)] = args {
+        if *step == 0 {
+            return Err(zero_step());
+        }
+        return Ok(Value::Range(Range::Int { start: *start, end: *end, step: *step }));
+    }
+
+    let (start, end, step) = (float_arg("range", &args[0])?, float_arg("range", &args[1])?, float_arg("range", &args[2])?);
+    if step == 0.0 || step.is_nan() {
+        return Err(zero_step());
+    }
+    Ok(Value::Range(Range::Float { start, end, step }))
+}
+
+/// Converts a number, rounding toward zero, or parses a string.
+fn int(args: &[Value]) -> Result<Value, String> {
+    let n = match &args[0] {
+        Value::Int(n) => Some(*n),
+        Value::BigInt(n) => n.to_i64(),
+        Value::Decimal(n) => n.trunc().to_i64(),
+        Value::Number(n) => exact_int(n.trunc()),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => return Err("int() expects a number or a string.".to_string()),
+    };
+    n.map(Value::Int).ok_or_else(|| format!("Can't convert {} to an integer.", args[0].repr()))
+}
+
+/// Converts a number to the nearest float, or parses a string.
+fn float(args: &[Value]) -> Result<Value, String> {
+    let n = match &args[0] {
+        Value::Int(n) => Some(*n as f64),
+        Value::BigInt(n) => Some(n.to_f64()),
+        Value::Decimal(n) => Some(n.to_f64()),
+        Value::Number(n) => Some(*n),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => return Err("float() expects a number or a string.".to_string()),
+    };
+    n.map(Value::Number).ok_or_else(|| format!("Can't convert {} to a float.", args[0].repr()))
+}
+
+/// Converts a number, rounding toward zero, or parses a string.
+fn bigint(args: &[Value]) -> Result<Value, String> {
+    let n = match &args[0] {
+        Value::Int(n) => Some(BigInt::from(*n)),
+        Value::BigInt(n) => Some((**n).clone()),
+        Value::Decimal(n) => Some(n.trunc()),
+        Value::Number(n) => BigInt::from_f64(n.trunc()),
+        Value::String(s) => BigInt::parse(s.trim()),
+        _ => return Err("bigint() expects a number or a string.".to_string()),
+    };
+    n.map(Value::bigint).ok_or_else(|| format!("Can't convert {} to a big integer.", args[0].repr()))
+}
+
+/// Converts a number, or parses a string. A float becomes the decimal with
+/// the digits it prints as, so `decimal(0.1)` is exactly `0.1d`.
+fn decimal(args: &[Value]) -> Result<Value, String> {
+    let n = match &args[0] {
+        Value::Number(n) => Decimal::from_f64(*n),
+        Value::String(s) => Decimal::parse(s.trim()),
+        value => value.to_decimal(),
+    };
+    match n {
+        Some(n) => Ok(Value::decimal(n)),
+        None if matches!(args[0], Value::Number(_) | Value::String(_)) => {
+            Err(format!("Can't convert {} to a decimal.", args[0].repr()))
+        }
+        None => Err("decimal() expects a number or a string.".to_string()),
+    }
+}
+
+// The string a string method was looked up on.
+fn receiver(args: &[Value]) -> &str {
+    match &args[0] {
+        Value::String(s) => s,
+        _ => unreachable!(),
+    }
+}
+
+fn string_arg<'v>(method: &str, value: &'v Value) -> Result<&'v str, String> {
+    match value {
+        Value::String(s) => Ok(s),
+        _ => Err(format!("{}() expects a string.", method)),
+    }
+}
+
+fn string_len(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::Int(receiver(args).chars().count() as i64))
+}
+
+fn upper(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::String(receiver(args).to_uppercase().into()))
+}
+
+fn lower(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::String(receiver(args).to_lowercase().into()))
+}
+
+fn trim(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::String(receiver(args).trim().into()))
+}
+
+fn chars(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::list(receiver(args).chars().map(|c| Value::String(c.to_string().into())).collect()))
+}
+
+fn split(args: &[Value]) -> Result<Value, String> {
+    let separator = string_arg("split", &args[1])?;
+    if separator.is_empty() {
+        return Err("split() separator must not be empty.".to_string());
+    }
+    Ok(Value::list(receiver(args).split(separator).map(|part| Value::String(part.into())).collect()))
+}
+
+fn contains(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::Bool(receiver(args).contains(string_arg("contains", &args[1])?)))
+}
+
+fn starts_with(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::Bool(receiver(args).starts_with(string_arg("startsWith", &args[1])?)))
+}
+
+fn ends_with(args: &[Value]) -> Result<Value, String> {
+    Ok(Value::Bool(receiver(args).ends_with(string_arg("endsWith", &args[1])?)))
+}
+
+/// The index of the first occurrence of a substring, or nil if there is
+/// none.
+fn find(args: &[Value]) -> Result<Value, String> {
+    let string = receiver(args);
+    let offset = string.find(string_arg("find", &args[1])?);
+    Ok(offset.map_or(Value::Null, |offset| Value::Int(string[..offset].chars().count() as i64)))
+}
+
+/// Replaces every occurrence of a substring.
+fn replace(args: &[Value]) -> Result<Value, String> {
+    let (from, to) = (string_arg("replace", &args[1])?, string_arg("replace", &args[2])?);
+    Ok(Value::String(receiver(args).replace(from, to).into()))
+}
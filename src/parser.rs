@@ -1,170 +1,826 @@
+use std::collections::VecDeque;
 use std::error::Error;
 use std::fmt::Display;
+use std::path::Path;
+use std::rc::Rc;
 
+use crate::ast::{Ast, ExprId, Span, StmtId};
+use crate::bignum::{BigInt, Decimal};
 use crate::expr::*;
+use crate::interner::Interner;
+use crate::limits;
+use crate::scanner::{ScanError, Scanner};
 use crate::stmt::*;
 use crate::token::*;
 use crate::token_type::TokenType::{self, *};
 use crate::Lox;
 
-pub(crate) struct Parser {
-    tokens: Vec<Token>,
-    current: usize,
+/// Parses tokens pulled lazily from a `Scanner`. Tokens not yet consumed
+/// wait in `lookahead`, which always holds at least the current token.
+pub(crate) struct Parser<'a> {
+    scanner: Scanner<'a>,
+    interner: Rc<Interner>,
+    lookahead: VecDeque<Token<'a>>,
+    previous: Token<'a>,
+    scan_errors: Vec<ScanError>,
+    ast: Ast,
 }
 
 #[derive(Debug)]
-pub(crate) struct ParseError {
-    token: Token,
+pub(crate) struct ParseError<'a> {
+    token: Token<'a>,
     message: String,
 }
 
-impl Parser {
-    pub(crate) fn new(tokens: &[Token]) -> Self {
-        Self {
-            tokens: tokens.to_owned(),
-            current: 0,
-        }
+/// Any error found while turning source text into a tree.
+#[derive(Debug)]
+pub(crate) enum SyntaxError<'a> {
+    Scan(ScanError),
+    Parse(ParseError<'a>),
+}
+
+impl<'a> Parser<'a> {
+    pub(crate) fn new(scanner: Scanner<'a>, interner: Rc<Interner>) -> Self {
+        let mut parser = Self {
+            scanner,
+            interner,
+            lookahead: VecDeque::new(),
+            previous: Token::new(EOF, "", None, 1, 0),
+            scan_errors: vec![],
+            ast: Ast::default(),
+        };
+        parser.fill_lookahead(1);
+        parser
     }
 
-    pub(crate) fn parse(&mut self) -> Result<Vec<Stmt>, ParseError> {
-        let mut statements = vec![];
+    /// Parses the whole program. Fails with every scan error met along the
+    /// way, followed by the parse error that stopped the parser, if any.
+    pub(crate) fn parse(mut self) -> Result<Ast, Vec<SyntaxError<'a>>> {
+        let mut parse_error = None;
         while !self.is_at_end() {
-            statements.push(self.statement()?);
+            match self.declaration() {
+                Ok(statement) => self.ast.statements.push(statement),
+                Err(e) => {
+                    parse_error = Some(e);
+                    break;
+                }
+            }
         }
 
-        Ok(statements)
+        let mut errors: Vec<SyntaxError> =
+            self.scan_errors.into_iter().map(SyntaxError::Scan).collect();
+        errors.extend(parse_error.map(SyntaxError::Parse));
+        if errors.is_empty() {
+            Ok(self.ast)
+        } else {
+            Err(errors)
+        }
     }
 
-    fn expression(&mut self) -> Result<Expr, ParseError> {
+    fn expression(&mut self) -> Result<ExprId, ParseError<'a>> {
         self.comma()
     }
 
-    fn statement(&mut self) -> Result<Stmt, ParseError> {
-        if self.current_is(&[PRINT]) {
-            self.print_statement()
+    fn declaration(&mut self) -> Result<StmtId, ParseError<'a>> {
+        // Without a name, `fun` starts a lambda in an expression statement.
+        if self.check(&FUN) && self.lookahead_type(1) == Some(IDENTIFIER) {
+            self.advance();
+            self.function_declaration()
+        } else if self.current_is(&[IMPORT]) {
+            self.import_declaration()
+        } else if self.current_is(&[VAR]) {
+            self.var_declaration()
         } else {
-            self.expression_statement()
+            self.statement()
+        }
+    }
+
+    fn function_declaration(&mut self) -> Result<StmtId, ParseError<'a>> {
+        let start = self.previous().span();
+        let name = self.consume(&IDENTIFIER, "Expect function name.")?;
+        let function = self.function()?;
+        let span = start.to(self.ast.exprs.span(function));
+        Ok(self.ast.stmts.alloc(FunctionStmt::new(name.into(), function), span))
+    }
+
+    /// Parses a parameter list and a block body into a `Lambda`.
+    fn function(&mut self) -> Result<ExprId, ParseError<'a>> {
+        let start = self.consume(&LEFT_PAREN, "Expect '(' before parameters.")?.span();
+        let mut params = vec![];
+        if !self.check(&RIGHT_PAREN) {
+            loop {
+                if params.len() >= 255 {
+                    return Err(ParseError::new(self.peek(), "Can't have more than 255 parameters."));
+                }
+                params.push(self.consume(&IDENTIFIER, "Expect parameter name.")?.into());
+                if !self.current_is(&[COMMA]) {
+                    break;
+                }
+            }
+        }
+        self.consume(&RIGHT_PAREN, "Expect ')' after parameters.")?;
+
+        self.consume(&LEFT_BRACE, "Expect '{' before function body.")?;
+        let body = self.block()?;
+        let end = self.previous().span();
+        Ok(self.ast.exprs.alloc(Lambda::new(params, body), start.to(end)))
+    }
+
+    /// Parses an arrow function, `(a, b) => expr`. Its single expression
+    /// becomes the body's `return`. A block may stand for the expression,
+    /// told apart from a map literal as at the start of a statement.
+    fn arrow_function(&mut self) -> Result<ExprId, ParseError<'a>> {
+        let start = self.consume(&LEFT_PAREN, "Expect '(' before parameters.")?.span();
+        let mut params = vec![];
+        while !self.current_is(&[RIGHT_PAREN]) {
+            if params.len() >= 255 {
+                return Err(ParseError::new(self.peek(), "Can't have more than 255 parameters."));
+            }
+            params.push(self.advance().into());
+            self.current_is(&[COMMA]);
+        }
+
+        let arrow = self.consume(&ARROW, "Expect '=>' after parameters.")?.span();
+        if self.check(&LEFT_BRACE) && !self.starts_map() {
+            self.advance();
+            let body = self.block()?;
+            let end = self.previous().span();
+            return Ok(self.ast.exprs.alloc(Lambda::new(params, body), start.to(end)));
         }
+
+        let value = self.assignment()?;
+        let span = self.ast.exprs.span(value);
+        let body = self.ast.stmts.alloc(ReturnStmt::new(arrow, Some(value)), span);
+        Ok(self.ast.exprs.alloc(Lambda::new(params, vec![body]), start.to(span)))
+    }
+
+    /// Whether the `(` about to be parsed opens the parameters of an arrow
+    /// function rather than a grouping: a list of names, the closing
+    /// parenthesis, then `=>`.
+    fn starts_arrow_function(&mut self) -> bool {
+        let mut n = 1;
+        if self.lookahead_type(n) != Some(RIGHT_PAREN) {
+            loop {
+                if self.lookahead_type(n) != Some(IDENTIFIER) {
+                    return false;
+                }
+                match self.lookahead_type(n + 1) {
+                    Some(COMMA) => n += 2,
+                    Some(RIGHT_PAREN) => break,
+                    _ => return false,
+                }
+            }
+            n += 1;
+        }
+        self.lookahead_type(n + 1) == Some(ARROW)
+    }
+
+    fn import_declaration(&mut self) -> Result<StmtId, ParseError<'a>> {
+        let keyword = self.previous().span();
+        let path = self.consume(&STRING, "Expect module path after 'import'.")?;
+        let path_text = self.interner.resolve(path.symbol.unwrap());
+
+        let name = if self.current_is(&[AS]) {
+            self.consume(&IDENTIFIER, "Expect namespace name after 'as'.")?.into()
+        } else {
+            // Without `as`, the module is named after its file.
+            let stem = Path::new(&*path_text).file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+            if !Scanner::is_identifier(stem) {
+                return Err(ParseError::new(&path, "Module file name is not a valid name; name it with 'as'."));
+            }
+            Identifier::new(self.interner.intern(stem), path.span())
+        };
+
+        let end = self.consume(&SEMICOLON, "Expect ';' after import.")?.span();
+        Ok(self.ast.stmts.alloc(ImportStmt::new(keyword, path_text, name), keyword.to(end)))
+    }
+
+    fn var_declaration(&mut self) -> Result<StmtId, ParseError<'a>> {
+        let start = self.previous().span();
+        let name = self.consume(&IDENTIFIER, "Expect variable name.")?;
+
+        let initializer = if self.current_is(&[EQUAL]) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+
+        let end = self.consume(&SEMICOLON, "Expect ';' after variable declaration.")?.span();
+        Ok(self.ast.stmts.alloc(VarStmt::new(name.into(), initializer), start.to(end)))
+    }
+
+    fn statement(&mut self) -> Result<StmtId, ParseError<'a>> {
+        limits::with_stack(|| {
+            if self.current_is(&[BREAK]) {
+                let keyword = self.previous().span();
+                let end = self.consume(&SEMICOLON, "Expect ';' after 'break'.")?.span();
+                Ok(self.ast.stmts.alloc(BreakStmt::new(keyword), keyword.to(end)))
+            } else if self.current_is(&[CONTINUE]) {
+                let keyword = self.previous().span();
+                let end = self.consume(&SEMICOLON, "Expect ';' after 'continue'.")?.span();
+                Ok(self.ast.stmts.alloc(ContinueStmt::new(keyword), keyword.to(end)))
+            } else if self.current_is(&[FOR]) {
+                self.for_statement()
+            } else if self.current_is(&[IF]) {
+                self.if_statement()
+            } else if self.current_is(&[PRINT]) {
+                self.print_statement()
+            } else if self.current_is(&[RETURN]) {
+                self.return_statement()
+            } else if self.current_is(&[THROW]) {
+                self.throw_statement()
+            } else if self.current_is(&[TRY]) {
+                self.try_statement()
+            } else if self.current_is(&[WHILE]) {
+                self.while_statement()
+            } else if self.check(&LEFT_BRACE) && !self.starts_map() {
+                self.advance();
+                let start = self.previous().span();
+                let statements = self.block()?;
+                let end = self.previous().span();
+                Ok(self.ast.stmts.alloc(BlockStmt::new(statements), start.to(end)))
+            } else {
+                self.expression_statement()
+            }
+        })
+    }
+
+    fn for_statement(&mut self) -> Result<StmtId, ParseError<'a>> {
+        let start = self.previous().span();
+        self.consume(&LEFT_PAREN, "Expect '(' after 'for'.")?;
+        if self.check(&IDENTIFIER) && self.lookahead_type(1) == Some(IN) {
+            return self.for_in_statement(start);
+        }
+
+        let initializer = if self.current_is(&[SEMICOLON]) {
+            None
+        } else if self.current_is(&[VAR]) {
+            Some(self.var_declaration()?)
+        } else {
+            Some(self.expression_statement()?)
+        };
+
+        let condition = if self.check(&SEMICOLON) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(&SEMICOLON, "Expect ';' after loop condition.")?;
+
+        let increment = if self.check(&RIGHT_PAREN) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(&RIGHT_PAREN, "Expect ')' after for clauses.")?;
+
+        let body = self.statement()?;
+        let span = start.to(self.ast.stmts.span(body));
+        Ok(self.ast.stmts.alloc(ForStmt::new(initializer, condition, increment, body), span))
+    }
+
+    /// Parses the rest of a `for (name in iterable)` loop, after its
+    /// opening parenthesis.
+    fn for_in_statement(&mut self, start: Span) -> Result<StmtId, ParseError<'a>> {
+        let name = self.advance();
+        self.advance();
+        let iterable = self.expression()?;
+        self.consume(&RIGHT_PAREN, "Expect ')' after for clauses.")?;
+
+        let body = self.statement()?;
+        let span = start.to(self.ast.stmts.span(body));
+        Ok(self.ast.stmts.alloc(ForInStmt::new(name.into(), iterable, body), span))
+    }
+
+    fn if_statement(&mut self) -> Result<StmtId, ParseError<'a>> {
+        let start = self.previous().span();
+        self.consume(&LEFT_PAREN, "Expect '(' after 'if'.")?;
+        let condition = self.expression()?;
+        self.consume(&RIGHT_PAREN, "Expect ')' after if condition.")?;
+
+        let then_branch = self.statement()?;
+        let else_branch = if self.current_is(&[ELSE]) {
+            Some(self.statement()?)
+        } else {
+            None
+        };
+
+        let end = self.ast.stmts.span(else_branch.unwrap_or(then_branch));
+        Ok(self.ast.stmts.alloc(IfStmt::new(condition, then_branch, else_branch), start.to(end)))
     }
 
-    fn print_statement(&mut self) -> Result<Stmt, ParseError> {
+    fn print_statement(&mut self) -> Result<StmtId, ParseError<'a>> {
+        let start = self.previous().span();
         let value = self.expression()?;
-        self.consume(&SEMICOLON, "Expect ';' after value.")?;
-        Ok(Stmt::Print(PrintStmt::new(value)))
+        let end = self.consume(&SEMICOLON, "Expect ';' after value.")?.span();
+        Ok(self.ast.stmts.alloc(PrintStmt::new(value), start.to(end)))
+    }
+
+    fn return_statement(&mut self) -> Result<StmtId, ParseError<'a>> {
+        let keyword = self.previous().span();
+        let value = if self.check(&SEMICOLON) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+
+        let end = self.consume(&SEMICOLON, "Expect ';' after return value.")?.span();
+        Ok(self.ast.stmts.alloc(ReturnStmt::new(keyword, value), keyword.to(end)))
+    }
+
+    fn throw_statement(&mut self) -> Result<StmtId, ParseError<'a>> {
+        let keyword = self.previous().span();
+        let value = self.expression()?;
+        let end = self.consume(&SEMICOLON, "Expect ';' after thrown value.")?.span();
+        Ok(self.ast.stmts.alloc(ThrowStmt::new(keyword, value), keyword.to(end)))
+    }
+
+    fn try_statement(&mut self) -> Result<StmtId, ParseError<'a>> {
+        let start = self.previous().span();
+        let body = self.block_statement("Expect '{' after 'try'.")?;
+
+        let catch = if self.current_is(&[CATCH]) {
+            self.consume(&LEFT_PAREN, "Expect '(' after 'catch'.")?;
+            let name = self.consume(&IDENTIFIER, "Expect error variable name.")?;
+            self.consume(&RIGHT_PAREN, "Expect ')' after error variable.")?;
+            let body = self.block_statement("Expect '{' after catch clause.")?;
+            Some(CatchClause::new(name.into(), body))
+        } else {
+            None
+        };
+        let finally = if self.current_is(&[FINALLY]) {
+            Some(self.block_statement("Expect '{' after 'finally'.")?)
+        } else {
+            None
+        };
+        if catch.is_none() && finally.is_none() {
+            return Err(ParseError::new(self.peek(), "Expect 'catch' or 'finally' after try block."));
+        }
+
+        let end = self.previous().span();
+        Ok(self.ast.stmts.alloc(TryStmt::new(body, catch, finally), start.to(end)))
     }
 
-    fn expression_statement(&mut self) -> Result<Stmt, ParseError> {
+    fn while_statement(&mut self) -> Result<StmtId, ParseError<'a>> {
+        let start = self.previous().span();
+        self.consume(&LEFT_PAREN, "Expect '(' after 'while'.")?;
+        let condition = self.expression()?;
+        self.consume(&RIGHT_PAREN, "Expect ')' after condition.")?;
+
+        let body = self.statement()?;
+        let span = start.to(self.ast.stmts.span(body));
+        Ok(self.ast.stmts.alloc(WhileStmt::new(condition, body), span))
+    }
+
+    /// Parses a block that has to come next, such as the body of a `try`.
+    fn block_statement(&mut self, message: &str) -> Result<StmtId, ParseError<'a>> {
+        let start = self.consume(&LEFT_BRACE, message)?.span();
+        let statements = self.block()?;
+        let end = self.previous().span();
+        Ok(self.ast.stmts.alloc(BlockStmt::new(statements), start.to(end)))
+    }
+
+    /// Parses the rest of a block, after its opening brace.
+    fn block(&mut self) -> Result<Vec<StmtId>, ParseError<'a>> {
+        let mut statements = vec![];
+        while !self.check(&RIGHT_BRACE) && !self.is_at_end() {
+            statements.push(self.declaration()?);
+        }
+
+        self.consume(&RIGHT_BRACE, "Expect '}' after block.")?;
+        Ok(statements)
+    }
+
+    /// Whether the `{` about to be parsed opens a map literal rather than a
+    /// block. Both may start a statement or an arrow function's body, so a
+    /// brace followed by a simple key and a colon is taken as a map, which
+    /// no block can begin with. Anywhere else an expression is expected,
+    /// `{` always opens a map.
+    fn starts_map(&mut self) -> bool {
+        let key = self.lookahead_type(1);
+        matches!(key, Some(STRING | INTEGER | BIGINT | DECIMAL | NUMBER | IDENTIFIER | TRUE | FALSE | NIL)) && self.lookahead_type(2) == Some(COLON)
+    }
+
+    fn expression_statement(&mut self) -> Result<StmtId, ParseError<'a>> {
         let expr = self.expression()?;
-        self.consume(&SEMICOLON, "Expect ';' after expression.")?;
-        Ok(Stmt::Expression(ExpressionStmt::new(expr)))
+        let end = self.consume(&SEMICOLON, "Expect ';' after expression.")?.span();
+        let span = self.ast.exprs.span(expr).to(end);
+        Ok(self.ast.stmts.alloc(ExpressionStmt::new(expr), span))
     }
 
-    fn comma(&mut self) -> Result<Expr, ParseError> {
-        let mut expr = self.ternary()?;
+    fn comma(&mut self) -> Result<ExprId, ParseError<'a>> {
+        let mut expr = self.assignment()?;
 
         while self.current_is(&[COMMA]) {
             let comma = self.previous();
-            let right = self.ternary()?;
-            expr = Binary::new(expr, comma, right).into();
+            let right = self.assignment()?;
+            expr = self.binary(expr, comma, right);
         }
 
         Ok(expr)
     }
 
-    fn ternary(&mut self) -> Result<Expr, ParseError> {
+    fn assignment(&mut self) -> Result<ExprId, ParseError<'a>> {
+        limits::with_stack(|| {
+            let expr = self.ternary()?;
+
+            if self.current_is(&[EQUAL, MINUS_EQUAL, PLUS_EQUAL, SLASH_EQUAL, STAR_EQUAL]) {
+                let equals = self.previous();
+                let operator = Self::compound_operator(equals);
+                let value = self.assignment()?;
+                let span = self.span_between(expr, value);
+
+                return match &self.ast[expr] {
+                    Expr::Variable(e) => {
+                        let name = e.name;
+                        Ok(self.ast.exprs.alloc(Assign::new(name, operator, value), span))
+                    }
+                    Expr::Get(e) => {
+                        let (object, name) = (e.object, e.name);
+                        Ok(self.ast.exprs.alloc(Set::new(object, name, operator, value), span))
+                    }
+                    Expr::Subscript(e) => {
+                        let (object, index) = (e.object, e.index);
+                        Ok(self.ast.exprs.alloc(SetSubscript::new(object, index, operator, value), span))
+                    }
+                    _ => Err(ParseError::new(&equals, "Invalid assignment target.")),
+                };
+            }
+
+            Ok(expr)
+        })
+    }
+
+    /// The binary operator a compound assignment such as `+=` applies, or
+    /// `None` for a plain `=`.
+    fn compound_operator(equals: Token) -> Option<Operator> {
+        let ty = match equals.ty {
+            MINUS_EQUAL => MINUS,
+            PLUS_EQUAL => PLUS,
+            SLASH_EQUAL => SLASH,
+            STAR_EQUAL => STAR,
+            _ => return None,
+        };
+        Some(Operator::new(ty, equals.span()))
+    }
+
+    fn ternary(&mut self) -> Result<ExprId, ParseError<'a>> {
+        limits::with_stack(|| {
+            let mut expr = self.or()?;
+
+            while self.current_is(&[QUESTION]) {
+                let mid = self.expression()?;
+                self.consume(&COLON, "Expect ':' in ternary expression.")?;
+                let right = self.ternary()?;
+                let span = self.span_between(expr, right);
+                expr = self.ast.exprs.alloc(Ternary::new(expr, mid, right), span);
+            }
+
+            Ok(expr)
+        })
+    }
+
+    fn or(&mut self) -> Result<ExprId, ParseError<'a>> {
+        let mut expr = self.and()?;
+
+        while self.current_is(&[OR]) {
+            let operator = self.previous();
+            let right = self.and()?;
+            expr = self.logical(expr, operator, right);
+        }
+
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<ExprId, ParseError<'a>> {
         let mut expr = self.equality()?;
 
-        while self.current_is(&[QUESTION]) {
-            let mid = self.expression()?;
-            self.consume(&COLON, "Expect ':' in ternary expression.")?;
-            let right = self.ternary()?;
-            expr = Ternary::new(expr, mid, right).into();
+        while self.current_is(&[AND]) {
+            let operator = self.previous();
+            let right = self.equality()?;
+            expr = self.logical(expr, operator, right);
         }
 
         Ok(expr)
     }
 
-    fn equality(&mut self) -> Result<Expr, ParseError> {
+    fn equality(&mut self) -> Result<ExprId, ParseError<'a>> {
         let mut expr = self.comparison()?;
 
         while self.current_is(&[BANG_EQUAL, EQUAL_EQUAL]) {
             let operator = self.previous();
             let right = self.comparison()?;
-            expr = Binary::new(expr, operator, right).into();
+            expr = self.binary(expr, operator, right);
         }
 
         Ok(expr)
     }
 
-    fn comparison(&mut self) -> Result<Expr, ParseError> {
-        let mut term = self.term()?;
+    fn comparison(&mut self) -> Result<ExprId, ParseError<'a>> {
+        let mut expr = self.bitwise_or()?;
 
         while self.current_is(&[GREATER, GREATER_EQUAL, LESS, LESS_EQUAL]) {
+            let operator = self.previous();
+            let right = self.bitwise_or()?;
+            expr = self.binary(expr, operator, right);
+        }
+
+        Ok(expr)
+    }
+
+    // Bitwise operators bind tighter than comparisons, so that
+    // `flags & MASK == 0` tests the masked bits.
+    fn bitwise_or(&mut self) -> Result<ExprId, ParseError<'a>> {
+        let mut expr = self.bitwise_xor()?;
+
+        while self.current_is(&[PIPE]) {
+            let operator = self.previous();
+            let right = self.bitwise_xor()?;
+            expr = self.binary(expr, operator, right);
+        }
+
+        Ok(expr)
+    }
+
+    fn bitwise_xor(&mut self) -> Result<ExprId, ParseError<'a>> {
+        let mut expr = self.bitwise_and()?;
+
+        while self.current_is(&[CARET]) {
+            let operator = self.previous();
+            let right = self.bitwise_and()?;
+            expr = self.binary(expr, operator, right);
+        }
+
+        Ok(expr)
+    }
+
+    fn bitwise_and(&mut self) -> Result<ExprId, ParseError<'a>> {
+        let mut expr = self.shift()?;
+
+        while self.current_is(&[AMPERSAND]) {
+            let operator = self.previous();
+            let right = self.shift()?;
+            expr = self.binary(expr, operator, right);
+        }
+
+        Ok(expr)
+    }
+
+    fn shift(&mut self) -> Result<ExprId, ParseError<'a>> {
+        let mut term = self.term()?;
+
+        while self.current_is(&[LESS_LESS, GREATER_GREATER]) {
             let operator = self.previous();
             let right = self.term()?;
-            term = Binary::new(term, operator, right).into();
+            term = self.binary(term, operator, right);
         }
 
         Ok(term)
     }
 
-    fn term(&mut self) -> Result<Expr, ParseError> {
+    fn term(&mut self) -> Result<ExprId, ParseError<'a>> {
         let mut factor = self.factor()?;
 
         while self.current_is(&[MINUS, PLUS]) {
             let operator = self.previous();
             let right = self.factor()?;
-            factor = Binary::new(factor, operator, right).into();
+            factor = self.binary(factor, operator, right);
         }
 
         Ok(factor)
     }
 
-    fn factor(&mut self) -> Result<Expr, ParseError> {
+    fn factor(&mut self) -> Result<ExprId, ParseError<'a>> {
         let mut unary = self.unary()?;
 
-        while self.current_is(&[SLASH, STAR]) {
+        while self.current_is(&[SLASH, STAR, PERCENT, TILDE_SLASH]) {
             let operator = self.previous();
             let right = self.unary()?;
-            unary = Binary::new(unary, operator, right).into();
+            unary = self.binary(unary, operator, right);
         }
 
         Ok(unary)
     }
 
-    fn unary(&mut self) -> Result<Expr, ParseError> {
-        if self.current_is(&[BANG, MINUS]) {
+    fn unary(&mut self) -> Result<ExprId, ParseError<'a>> {
+        limits::with_stack(|| {
+            if self.current_is(&[BANG, MINUS, TILDE]) {
+                let operator = self.previous();
+                let right = self.unary()?;
+                let span = operator.span().to(self.ast.exprs.span(right));
+                Ok(self.ast.exprs.alloc(Unary::new(operator.into(), right), span))
+            } else if self.current_is(&[MINUS_MINUS, PLUS_PLUS]) {
+                let operator = self.previous();
+                let target = self.unary()?;
+                let span = operator.span().to(self.ast.exprs.span(target));
+                self.update(target, operator, true, span)
+            } else {
+                self.power()
+            }
+        })
+    }
+
+    /// `**` binds tighter than a unary operator on its left, but not on its
+    /// right: `-2 ** -1` is `-(2 ** (-1))`. It groups to the right.
+    fn power(&mut self) -> Result<ExprId, ParseError<'a>> {
+        let base = self.postfix()?;
+
+        if self.current_is(&[STAR_STAR]) {
             let operator = self.previous();
-            let right = self.unary()?;
-            Ok(Unary::new(operator, right).into())
-        } else {
-            self.primary()
+            let exponent = self.unary()?;
+            return Ok(self.binary(base, operator, exponent));
         }
+
+        Ok(base)
     }
 
-    fn primary(&mut self) -> Result<Expr, ParseError> {
-        if self.current_is(&[FALSE]) {
-            Ok(Literal::new(LiteralToken::Bool(false)).into())
+    fn postfix(&mut self) -> Result<ExprId, ParseError<'a>> {
+        let mut expr = self.call()?;
+
+        while self.current_is(&[MINUS_MINUS, PLUS_PLUS]) {
+            let operator = self.previous();
+            let span = self.ast.exprs.span(expr).to(operator.span());
+            expr = self.update(expr, operator, false, span)?;
+        }
+
+        Ok(expr)
+    }
+
+    fn update(&mut self, target: ExprId, operator: Token<'a>, prefix: bool, span: Span) -> Result<ExprId, ParseError<'a>> {
+        match &self.ast[target] {
+            Expr::Variable(_) | Expr::Get(_) | Expr::Subscript(_) => {
+                Ok(self.ast.exprs.alloc(Update::new(target, operator.into(), prefix), span))
+            }
+            _ => Err(ParseError::new(&operator, "Invalid increment or decrement target.")),
+        }
+    }
+
+    fn call(&mut self) -> Result<ExprId, ParseError<'a>> {
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.current_is(&[LEFT_PAREN]) {
+                expr = self.finish_call(expr)?;
+            } else if self.current_is(&[DOT]) {
+                let name = self.consume(&IDENTIFIER, "Expect property name after '.'.")?;
+                let span = self.ast.exprs.span(expr).to(name.span());
+                expr = self.ast.exprs.alloc(Get::new(expr, name.into()), span);
+            } else if self.current_is(&[LEFT_BRACKET]) {
+                let index = self.expression()?;
+                let end = self.consume(&RIGHT_BRACKET, "Expect ']' after index.")?.span();
+                let span = self.ast.exprs.span(expr).to(end);
+                expr = self.ast.exprs.alloc(Subscript::new(expr, index), span);
+            } else {
+                break;
+            }
+        }
+
+        Ok(expr)
+    }
+
+    /// Parses a string with interpolated expressions, starting at the
+    /// text before the first `${`.
+    fn template(&mut self) -> Result<ExprId, ParseError<'a>> {
+        let start = self.peek().span();
+        let mut parts = vec![];
+        while self.current_is(&[INTERPOLATION]) {
+            parts.push(self.string_part(self.previous()));
+            parts.push(self.expression()?);
+        }
+        let end = self.consume(&STRING, "Expect '}' after interpolated expression.")?;
+        parts.push(self.string_part(end));
+        Ok(self.ast.exprs.alloc(Template::new(parts), start.to(end.span())))
+    }
+
+    fn string_part(&mut self, token: Token) -> ExprId {
+        let value = LiteralToken::String(self.interner.resolve(token.symbol.unwrap()));
+        self.ast.exprs.alloc(Literal::new(value), token.span())
+    }
+
+    fn finish_call(&mut self, callee: ExprId) -> Result<ExprId, ParseError<'a>> {
+        let mut arguments = vec![];
+        if !self.check(&RIGHT_PAREN) {
+            loop {
+                if arguments.len() >= 255 {
+                    return Err(ParseError::new(self.peek(), "Can't have more than 255 arguments."));
+                }
+                arguments.push(self.assignment()?);
+                if !self.current_is(&[COMMA]) {
+                    break;
+                }
+            }
+        }
+
+        let end = self.consume(&RIGHT_PAREN, "Expect ')' after arguments.")?.span();
+        let span = self.ast.exprs.span(callee).to(end);
+        Ok(self.ast.exprs.alloc(Call::new(callee, arguments), span))
+    }
+
+    fn primary(&mut self) -> Result<ExprId, ParseError<'a>> {
+        let value = if self.current_is(&[FALSE]) {
+            LiteralToken::Bool(false)
         } else if self.current_is(&[TRUE]) {
-            Ok(Literal::new(LiteralToken::Bool(true)).into())
+            LiteralToken::Bool(true)
         } else if self.current_is(&[NIL]) {
-            Ok(Literal::new(LiteralToken::Null).into())
-        } else if self.current_is(&[NUMBER, STRING]) {
-            Ok(Literal::new(self.previous().literal).into())
+            LiteralToken::Null
+        } else if self.current_is(&[INTEGER]) {
+            // The scanner only produces digits, but they may not fit.
+            match self.previous().lexeme.parse() {
+                Ok(n) => LiteralToken::Int(n),
+                Err(_) => return Err(ParseError::new(&self.previous(), "Integer literal is too large.")),
+            }
+        } else if self.current_is(&[BIGINT]) {
+            let digits = self.previous().lexeme.trim_end_matches('n');
+            LiteralToken::BigInt(Rc::new(BigInt::parse(digits).unwrap()))
+        } else if self.current_is(&[DECIMAL]) {
+            let digits = self.previous().lexeme.trim_end_matches('d');
+            LiteralToken::Decimal(Rc::new(Decimal::parse(digits).unwrap()))
+        } else if self.current_is(&[NUMBER]) {
+            // The scanner only produces well-formed number lexemes.
+            LiteralToken::Number(self.previous().lexeme.parse().unwrap())
+        } else if self.current_is(&[STRING]) {
+            let symbol = self.previous().symbol.unwrap();
+            LiteralToken::String(self.interner.resolve(symbol))
+        } else if self.check(&INTERPOLATION) {
+            return self.template();
+        } else if self.current_is(&[IDENTIFIER]) {
+            let name = self.previous();
+            return Ok(self.ast.exprs.alloc(Variable::new(name.into()), name.span()));
+        } else if self.check(&LEFT_PAREN) && self.starts_arrow_function() {
+            return self.arrow_function();
+        } else if self.current_is(&[FUN]) {
+            return self.function();
         } else if self.current_is(&[LEFT_PAREN]) {
+            let start = self.previous().span();
             let expr = self.expression()?;
-            self.consume(&RIGHT_PAREN, "Expect ')' after expression.")?;
-            Ok(Grouping::new(expr).into())
+            let end = self.consume(&RIGHT_PAREN, "Expect ')' after expression.")?.span();
+            return Ok(self.ast.exprs.alloc(Grouping::new(expr), start.to(end)));
+        } else if self.current_is(&[LEFT_BRACKET]) {
+            return self.list();
+        } else if self.current_is(&[LEFT_BRACE]) {
+            return self.map();
         } else {
-            Err(ParseError::new(&self.peek(), "Expect expression."))
+            return Err(ParseError::new(self.peek(), "Expect expression."));
+        };
+
+        let span = self.previous().span();
+        Ok(self.ast.exprs.alloc(Literal::new(value), span))
+    }
+
+    /// Parses the rest of a list literal, after its opening bracket. A
+    /// trailing comma is allowed.
+    fn list(&mut self) -> Result<ExprId, ParseError<'a>> {
+        let start = self.previous().span();
+        let mut elements = vec![];
+        while !self.check(&RIGHT_BRACKET) {
+            elements.push(self.assignment()?);
+            if !self.current_is(&[COMMA]) {
+                break;
+            }
         }
+
+        let end = self.consume(&RIGHT_BRACKET, "Expect ']' after list elements.")?.span();
+        Ok(self.ast.exprs.alloc(List::new(elements), start.to(end)))
+    }
+
+    /// Parses the rest of a map literal, after its opening brace. A
+    /// trailing comma is allowed.
+    fn map(&mut self) -> Result<ExprId, ParseError<'a>> {
+        let start = self.previous().span();
+        let mut entries = vec![];
+        while !self.check(&RIGHT_BRACE) {
+            let key = self.assignment()?;
+            self.consume(&COLON, "Expect ':' after map key.")?;
+            let value = self.assignment()?;
+            entries.push((key, value));
+            if !self.current_is(&[COMMA]) {
+                break;
+            }
+        }
+
+        let end = self.consume(&RIGHT_BRACE, "Expect '}' after map entries.")?.span();
+        Ok(self.ast.exprs.alloc(Map::new(entries), start.to(end)))
     }
 
-    fn consume(&mut self, ty: &TokenType, message: &str) -> Result<Token, ParseError> {
+    fn binary(&mut self, left: ExprId, operator: Token, right: ExprId) -> ExprId {
+        let span = self.span_between(left, right);
+        self.ast.exprs.alloc(Binary::new(left, operator.into(), right), span)
+    }
+
+    fn logical(&mut self, left: ExprId, operator: Token, right: ExprId) -> ExprId {
+        let span = self.span_between(left, right);
+        self.ast.exprs.alloc(Logical::new(left, operator.into(), right), span)
+    }
+
+    fn span_between(&self, first: ExprId, last: ExprId) -> Span {
+        self.ast.exprs.span(first).to(self.ast.exprs.span(last))
+    }
+
+    fn consume(&mut self, ty: &TokenType, message: &str) -> Result<Token<'a>, ParseError<'a>> {
         if self.check(ty) {
             Ok(self.advance())
         } else {
-            Err(ParseError::new(&self.peek(), message))
+            Err(ParseError::new(self.peek(), message))
         }
     }
 
@@ -187,9 +843,10 @@ impl Parser {
         }
     }
 
-    fn advance(&mut self) -> Token {
+    fn advance(&mut self) -> Token<'a> {
         if !self.is_at_end() {
-            self.current += 1;
+            self.previous = self.lookahead.pop_front().unwrap();
+            self.fill_lookahead(1);
         }
         self.previous()
     }
@@ -198,14 +855,34 @@ impl Parser {
         self.peek().ty == EOF
     }
 
-    fn peek(&self) -> Token {
-        self.tokens[self.current].clone()
+    fn peek(&self) -> &Token<'a> {
+        &self.lookahead[0]
+    }
+
+    fn previous(&self) -> Token<'a> {
+        self.previous
     }
 
-    fn previous(&self) -> Token {
-        self.tokens[self.current - 1].clone()
+    /// The type of the token `n` places after the current one, if the
+    /// source has that many tokens left.
+    fn lookahead_type(&mut self, n: usize) -> Option<TokenType> {
+        self.fill_lookahead(n + 1);
+        self.lookahead.get(n).map(|token| token.ty)
     }
 
+    /// Pulls tokens from the scanner until `n` are waiting, or the scanner
+    /// is exhausted. Scan errors are set aside to be reported by `parse`.
+    fn fill_lookahead(&mut self, n: usize) {
+        while self.lookahead.len() < n {
+            match self.scanner.next() {
+                Some(Ok(token)) => self.lookahead.push_back(token),
+                Some(Err(e)) => self.scan_errors.push(e),
+                None => break,
+            }
+        }
+    }
+
+    #[allow(dead_code)]
     fn synchronize(&mut self) {
         self.advance();
 
@@ -215,7 +892,7 @@ impl Parser {
             }
 
             match self.peek().ty {
-                CLASS | FUN | VAR | FOR | IF | WHILE | PRINT | RETURN => {
+                CLASS | FUN | VAR | FOR | IF | WHILE | PRINT | RETURN | BREAK | CONTINUE | IMPORT | THROW | TRY => {
                     return;
                 }
                 _ => {}
@@ -226,16 +903,16 @@ impl Parser {
     }
 }
 
-impl ParseError {
-    fn new(token: &Token, message: &str) -> Self {
+impl<'a> ParseError<'a> {
+    fn new(token: &Token<'a>, message: &str) -> Self {
         Self {
-            token: token.clone(),
+            token: *token,
             message: message.to_string(),
         }
     }
 }
 
-impl Display for ParseError {
+impl Display for ParseError<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if self.token.ty == EOF {
             write!(
@@ -257,4 +934,64 @@ impl Display for ParseError {
     }
 }
 
-impl Error for ParseError {}
+impl Error for ParseError<'_> {}
+
+impl SyntaxError<'_> {
+    /// Where the error is. A parse error at the end of the source points
+    /// just past it.
+    pub(crate) fn span(&self) -> Span {
+        match self {
+            SyntaxError::Scan(e) => e.span(),
+            SyntaxError::Parse(e) => e.token.span(),
+        }
+    }
+
+    pub(crate) fn message(&self) -> &str {
+        match self {
+            SyntaxError::Scan(e) => e.message(),
+            SyntaxError::Parse(e) => &e.message,
+        }
+    }
+}
+
+impl Display for SyntaxError<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyntaxError::Scan(e) => e.fmt(f),
+            SyntaxError::Parse(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for SyntaxError<'_> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_spans() {
+        let source = "var a;\nprint (a +\n  2) * -a;";
+        let interner = Rc::new(Interner::new());
+        let ast = Parser::new(Scanner::new(source, interner.clone()), interner).parse().unwrap();
+        let text = |span: Span| &source[span.start..span.end];
+
+        // Statements span from their keyword to their semicolon, and
+        // expressions from their first token to their last, each starting
+        // on the line of its first token.
+        let print = ast.statements[1];
+        assert_eq!(ast.stmts.span(print), Span::new(7, 28, 2));
+        let Stmt::Print(print) = &ast[print] else { panic!() };
+        let Expr::Binary(product) = &ast[print.expression] else { panic!() };
+        assert_eq!(text(ast.exprs.span(print.expression)), "(a +\n  2) * -a");
+        let Expr::Grouping(group) = &ast[product.left] else { panic!() };
+        assert_eq!(ast.exprs.span(product.left), Span::new(13, 22, 2));
+        let Expr::Binary(sum) = &ast[group.expression] else { panic!() };
+        assert_eq!(ast.exprs.span(sum.right), Span::new(20, 21, 3));
+        assert_eq!(ast.exprs.span(product.right), Span::new(25, 27, 3));
+
+        // Ids are distinct, and index the node they were handed out for.
+        assert_ne!(product.left, product.right);
+        assert!(matches!(ast[product.right], Expr::Unary(_)));
+    }
+}
@@ -0,0 +1,415 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Display;
+
+use crate::ast::{Ast, ExprId, Span, StmtId};
+use crate::expr::*;
+use crate::interner::Symbol;
+use crate::limits;
+use crate::stmt::*;
+use crate::Lox;
+
+/// A static pass over a parsed tree, run before it is interpreted. It
+/// works out which declaration each variable refers to, and reports
+/// mistakes the grammar lets through, such as a `break` outside of any
+/// loop.
+pub(crate) struct Resolver<'a> {
+    source: &'a str,
+    // The local scopes around the current node, innermost last, each
+    // mapping a name to whether its initializer has run. Globals are not
+    // tracked.
+    scopes: Vec<HashMap<Symbol, bool>>,
+    // How many scopes out from its use each local variable is declared.
+    locals: HashMap<ExprId, usize>,
+    // Loops around the current statement, within the innermost function.
+    loop_depth: usize,
+    function_depth: usize,
+    errors: Vec<ResolveError<'a>>,
+}
+
+#[derive(Debug)]
+pub(crate) struct ResolveError<'a> {
+    lexeme: &'a str,
+    span: Span,
+    message: String,
+}
+
+impl<'a> Resolver<'a> {
+    /// A resolver for trees parsed from `source`, which it quotes in errors.
+    pub(crate) fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            scopes: vec![],
+            locals: HashMap::new(),
+            loop_depth: 0,
+            function_depth: 0,
+            errors: vec![],
+        }
+    }
+
+    /// Resolves code that runs within local scopes, such as code evaluated
+    /// by a debugger in a paused function. `scopes` holds the names of
+    /// each, outermost first.
+    pub(crate) fn within(mut self, scopes: Vec<Vec<Symbol>>) -> Self {
+        self.scopes = scopes.into_iter().map(|names| names.into_iter().map(|name| (name, true)).collect()).collect();
+        self
+    }
+
+    /// Checks the whole program, returning how many scopes out each local
+    /// variable, keyed by the expression using it, is declared. Variables
+    /// left out are globals. Fails with every error found.
+    pub(crate) fn resolve(mut self, ast: &Ast) -> Result<HashMap<ExprId, usize>, Vec<ResolveError<'a>>> {
+        for &statement in &ast.statements {
+            self.resolve_stmt(ast, statement);
+        }
+
+        if self.errors.is_empty() {
+            Ok(self.locals)
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn resolve_stmt(&mut self, ast: &Ast, statement: StmtId) {
+        limits::with_stack(|| statement.accept(ast, self))
+    }
+
+    fn resolve_expr(&mut self, ast: &Ast, expr: ExprId) {
+        limits::with_stack(|| expr.accept(ast, self))
+    }
+
+    fn resolve_exprs(&mut self, ast: &Ast, exprs: &[ExprId]) {
+        for &expr in exprs {
+            self.resolve_expr(ast, expr);
+        }
+    }
+
+    fn resolve_loop_body(&mut self, ast: &Ast, body: StmtId) {
+        self.loop_depth += 1;
+        self.resolve_stmt(ast, body);
+        self.loop_depth -= 1;
+    }
+
+    /// Records the scope declaring `name` for `expr`, if it is a local.
+    fn resolve_local(&mut self, expr: ExprId, name: &Identifier) {
+        if let Some(depth) = self.scopes.iter().rev().position(|scope| scope.contains_key(&name.symbol)) {
+            self.locals.insert(expr, depth);
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// Adds `name` to the innermost scope, if any, as not yet usable.
+    fn declare(&mut self, name: &Identifier) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.symbol, false);
+        }
+    }
+
+    /// Marks `name` as usable in the innermost scope, if any.
+    fn define(&mut self, name: &Identifier) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.symbol, true);
+        }
+    }
+
+    fn error(&mut self, span: Span, message: &str) {
+        self.errors.push(ResolveError {
+            lexeme: &self.source[span.start..span.end],
+            span,
+            message: message.to_string(),
+        });
+    }
+}
+
+impl StmtVisitor<()> for Resolver<'_> {
+    fn visit_block_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &BlockStmt) {
+        self.begin_scope();
+        for &statement in &stmt.statements {
+            self.resolve_stmt(ast, statement);
+        }
+        self.end_scope();
+    }
+
+    fn visit_break_stmt(&mut self, _: &Ast, _: StmtId, stmt: &BreakStmt) {
+        if self.loop_depth == 0 {
+            self.error(stmt.keyword, "Can't use 'break' outside of a loop.");
+        }
+    }
+
+    fn visit_continue_stmt(&mut self, _: &Ast, _: StmtId, stmt: &ContinueStmt) {
+        if self.loop_depth == 0 {
+            self.error(stmt.keyword, "Can't use 'continue' outside of a loop.");
+        }
+    }
+
+    fn visit_expression_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &ExpressionStmt) {
+        self.resolve_expr(ast, stmt.expression);
+    }
+
+    fn visit_for_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &ForStmt) {
+        // The initializer's variable lives in a scope around the loop.
+        self.begin_scope();
+        if let Some(initializer) = stmt.initializer {
+            self.resolve_stmt(ast, initializer);
+        }
+        if let Some(condition) = stmt.condition {
+            self.resolve_expr(ast, condition);
+        }
+        if let Some(increment) = stmt.increment {
+            self.resolve_expr(ast, increment);
+        }
+        self.resolve_loop_body(ast, stmt.body);
+        self.end_scope();
+    }
+
+    fn visit_for_in_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &ForInStmt) {
+        self.resolve_expr(ast, stmt.iterable);
+        self.begin_scope();
+        self.define(&stmt.name);
+        self.resolve_loop_body(ast, stmt.body);
+        self.end_scope();
+    }
+
+    fn visit_function_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &FunctionStmt) {
+        // The function can call itself.
+        self.define(&stmt.name);
+        self.resolve_expr(ast, stmt.function);
+    }
+
+    fn visit_if_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &IfStmt) {
+        self.resolve_expr(ast, stmt.condition);
+        self.resolve_stmt(ast, stmt.then_branch);
+        if let Some(else_branch) = stmt.else_branch {
+            self.resolve_stmt(ast, else_branch);
+        }
+    }
+
+    fn visit_import_stmt(&mut self, _: &Ast, _: StmtId, stmt: &ImportStmt) {
+        self.define(&stmt.name);
+    }
+
+    fn visit_print_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &PrintStmt) {
+        self.resolve_expr(ast, stmt.expression);
+    }
+
+    fn visit_return_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &ReturnStmt) {
+        if self.function_depth == 0 {
+            self.error(stmt.keyword, "Can't return from top-level code.");
+        }
+        if let Some(value) = stmt.value {
+            self.resolve_expr(ast, value);
+        }
+    }
+
+    fn visit_throw_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &ThrowStmt) {
+        self.resolve_expr(ast, stmt.value);
+    }
+
+    fn visit_try_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &TryStmt) {
+        self.resolve_stmt(ast, stmt.body);
+        if let Some(catch) = &stmt.catch {
+            self.begin_scope();
+            self.define(&catch.name);
+            self.resolve_stmt(ast, catch.body);
+            self.end_scope();
+        }
+        if let Some(finally) = stmt.finally {
+            self.resolve_stmt(ast, finally);
+        }
+    }
+
+    fn visit_var_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &VarStmt) {
+        self.declare(&stmt.name);
+        if let Some(initializer) = stmt.initializer {
+            self.resolve_expr(ast, initializer);
+        }
+        self.define(&stmt.name);
+    }
+
+    fn visit_while_stmt(&mut self, ast: &Ast, _: StmtId, stmt: &WhileStmt) {
+        self.resolve_expr(ast, stmt.condition);
+        self.resolve_loop_body(ast, stmt.body);
+    }
+}
+
+impl ExprVisitor<()> for Resolver<'_> {
+    fn visit_assign_expr(&mut self, ast: &Ast, id: ExprId, expr: &Assign) {
+        self.resolve_expr(ast, expr.value);
+        self.resolve_local(id, &expr.name);
+    }
+
+    fn visit_binary_expr(&mut self, ast: &Ast, _: ExprId, expr: &Binary) {
+        self.resolve_exprs(ast, &[expr.left, expr.right]);
+    }
+
+    fn visit_call_expr(&mut self, ast: &Ast, _: ExprId, expr: &Call) {
+        self.resolve_expr(ast, expr.callee);
+        self.resolve_exprs(ast, &expr.arguments);
+    }
+
+    fn visit_get_expr(&mut self, ast: &Ast, _: ExprId, expr: &Get) {
+        self.resolve_expr(ast, expr.object);
+    }
+
+    fn visit_grouping_expr(&mut self, ast: &Ast, _: ExprId, expr: &Grouping) {
+        self.resolve_expr(ast, expr.expression);
+    }
+
+    fn visit_lambda_expr(&mut self, ast: &Ast, _: ExprId, expr: &Lambda) {
+        // A loop outside the function can't be left from inside it.
+        let loop_depth = std::mem::take(&mut self.loop_depth);
+        self.function_depth += 1;
+        // The parameters and the body share one scope.
+        self.begin_scope();
+        for param in &expr.params {
+            self.define(param);
+        }
+        for &statement in &expr.body {
+            self.resolve_stmt(ast, statement);
+        }
+        self.end_scope();
+        self.function_depth -= 1;
+        self.loop_depth = loop_depth;
+    }
+
+    fn visit_list_expr(&mut self, ast: &Ast, _: ExprId, expr: &List) {
+        self.resolve_exprs(ast, &expr.elements);
+    }
+
+    fn visit_literal_expr(&mut self, _: &Ast, _: ExprId, _: &Literal) {}
+
+    fn visit_logical_expr(&mut self, ast: &Ast, _: ExprId, expr: &Logical) {
+        self.resolve_exprs(ast, &[expr.left, expr.right]);
+    }
+
+    fn visit_map_expr(&mut self, ast: &Ast, _: ExprId, expr: &Map) {
+        for &(key, value) in &expr.entries {
+            self.resolve_exprs(ast, &[key, value]);
+        }
+    }
+
+    fn visit_set_expr(&mut self, ast: &Ast, _: ExprId, expr: &Set) {
+        self.resolve_exprs(ast, &[expr.object, expr.value]);
+    }
+
+    fn visit_set_subscript_expr(&mut self, ast: &Ast, _: ExprId, expr: &SetSubscript) {
+        self.resolve_exprs(ast, &[expr.object, expr.index, expr.value]);
+    }
+
+    fn visit_subscript_expr(&mut self, ast: &Ast, _: ExprId, expr: &Subscript) {
+        self.resolve_exprs(ast, &[expr.object, expr.index]);
+    }
+
+    fn visit_template_expr(&mut self, ast: &Ast, _: ExprId, expr: &Template) {
+        self.resolve_exprs(ast, &expr.parts);
+    }
+
+    fn visit_ternary_expr(&mut self, ast: &Ast, _: ExprId, expr: &Ternary) {
+        self.resolve_exprs(ast, &[expr.left, expr.mid, expr.right]);
+    }
+
+    fn visit_unary_expr(&mut self, ast: &Ast, _: ExprId, expr: &Unary) {
+        self.resolve_expr(ast, expr.right);
+    }
+
+    fn visit_update_expr(&mut self, ast: &Ast, _: ExprId, expr: &Update) {
+        self.resolve_expr(ast, expr.target);
+    }
+
+    fn visit_variable_expr(&mut self, _: &Ast, id: ExprId, expr: &Variable) {
+        if self.scopes.last().and_then(|scope| scope.get(&expr.name.symbol)) == Some(&false) {
+            self.error(expr.name.span, "Can't read local variable in its own initializer.");
+        }
+        self.resolve_local(id, &expr.name);
+    }
+}
+
+impl ResolveError<'_> {
+    pub(crate) fn span(&self) -> Span {
+        self.span
+    }
+
+    pub(crate) fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for ResolveError<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            Lox::error_message(self.span.line, &format!(" at '{}'", self.lexeme), &self.message),
+        )
+    }
+}
+
+impl Error for ResolveError<'_> {}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use crate::interner::Interner;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    use super::*;
+
+    fn resolve(source: &str) -> Vec<String> {
+        let interner = Rc::new(Interner::new());
+        let scanner = Scanner::new(source, interner.clone());
+        let ast = Parser::new(scanner, interner).parse().unwrap();
+        match Resolver::new(source).resolve(&ast) {
+            Ok(_) => vec![],
+            Err(errors) => errors.iter().map(|e| e.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_jumps() {
+        assert!(resolve("while (true) { if (true) break; else { continue; } }").is_empty());
+        assert!(resolve("for (x in []) for (;;) break;").is_empty());
+        assert_eq!(
+            resolve("break;\n{ if (true) continue; }"),
+            vec![
+                "[line 1] Error at 'break': Can't use 'break' outside of a loop.",
+                "[line 2] Error at 'continue': Can't use 'continue' outside of a loop.",
+            ]
+        );
+
+        assert!(resolve("fun f() { return (x) => { return x; }; }").is_empty());
+        assert_eq!(
+            resolve("return 1;\nwhile (true) { var f = fun () { break; }; }"),
+            vec![
+                "[line 1] Error at 'return': Can't return from top-level code.",
+                "[line 2] Error at 'break': Can't use 'break' outside of a loop.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scopes() {
+        let source = "var a; { var b; fun f(c) { a; b; c; { var d = 1; b = d; } } }";
+        let interner = Rc::new(Interner::new());
+        let ast = Parser::new(Scanner::new(source, interner.clone()), interner).parse().unwrap();
+        let locals = Resolver::new(source).resolve(&ast).unwrap();
+        let span = |id: &ExprId| ast.exprs.span(*id);
+        let mut depths: Vec<_> = locals.iter().map(|(id, &depth)| (&source[span(id).start..span(id).end], depth)).collect();
+        depths.sort();
+        // `a` is a global, and is left out.
+        assert_eq!(depths, vec![("b", 1), ("b = d", 2), ("c", 0), ("d", 0)]);
+
+        assert_eq!(
+            resolve("var a = 1; { var a = a; }"),
+            vec!["[line 1] Error at 'a': Can't read local variable in its own initializer."]
+        );
+    }
+}
@@ -1,74 +1,154 @@
 use derive_new::new;
 
+use crate::ast::{Ast, ExprId};
 use crate::expr::*;
+use crate::interner::Interner;
 
 #[derive(new)]
-pub(crate) struct RpnPrinter;
-
-impl RpnPrinter {
-    pub(crate) fn print(e: Expr) -> String {
-        match e {
-            Expr::Binary(e) => Self::print_binary_expr(*e),
-            Expr::Grouping(e) => Self::print_grouping_expr(*e),
-            Expr::Literal(e) => Self::print_literal_expr(*e),
-            Expr::Ternary(e) => Self::print_ternary_expr(*e),
-            Expr::Unary(e) => Self::print_unary_expr(*e),
+pub(crate) struct RpnPrinter<'a> {
+    interner: &'a Interner,
+}
+
+impl RpnPrinter<'_> {
+    pub(crate) fn print(ast: &Ast, interner: &Interner, e: ExprId) -> String {
+        e.accept(ast, &mut RpnPrinter::new(interner))
+    }
+
+    // Operands first, then the operator.
+    fn postfix(&mut self, ast: &Ast, exprs: &[ExprId], operator: &str) -> String {
+        let mut result = String::new();
+        for expr in exprs {
+            result.push_str(&expr.accept(ast, self));
+            result.push(' ');
         }
+        result.push_str(operator);
+        result
+    }
+}
+
+impl ExprVisitor<String> for RpnPrinter<'_> {
+    fn visit_assign_expr(&mut self, ast: &Ast, _: ExprId, e: &Assign) -> String {
+        let equals = e.operator.map_or("=".to_string(), |operator| format!("{}=", operator.ty));
+        let operator = format!("{} {}", self.interner.resolve(e.name.symbol), equals);
+        self.postfix(ast, &[e.value], &operator)
     }
 
-    fn print_binary_expr(e: Binary) -> String {
+    fn visit_binary_expr(&mut self, ast: &Ast, _: ExprId, e: &Binary) -> String {
         format!(
             "{} {} {}",
-            Self::print(e.left),
-            Self::print(e.right),
-            e.operator.lexeme,
+            e.left.accept(ast, self),
+            e.right.accept(ast, self),
+            e.operator.ty,
         )
     }
 
-    fn print_grouping_expr(e: Grouping) -> String {
-        format!("{}", Self::print(e.expression))
+    fn visit_call_expr(&mut self, ast: &Ast, _: ExprId, e: &Call) -> String {
+        let mut exprs = vec![e.callee];
+        exprs.extend(&e.arguments);
+        self.postfix(ast, &exprs, &format!("call({})", e.arguments.len()))
     }
 
-    fn print_literal_expr(e: Literal) -> String {
-        match &e.value {
-            Some(v) => v.print(),
-            None => "nil".to_string(),
-        }
+    fn visit_get_expr(&mut self, ast: &Ast, _: ExprId, e: &Get) -> String {
+        let operator = format!(".{}", self.interner.resolve(e.name.symbol));
+        self.postfix(ast, &[e.object], &operator)
+    }
+
+    fn visit_grouping_expr(&mut self, ast: &Ast, _: ExprId, e: &Grouping) -> String {
+        e.expression.accept(ast, self)
     }
 
-    fn print_ternary_expr(e: Ternary) -> String {
+    fn visit_lambda_expr(&mut self, _: &Ast, _: ExprId, e: &Lambda) -> String {
+        format!("fun({})", e.params.len())
+    }
+
+    fn visit_list_expr(&mut self, ast: &Ast, _: ExprId, e: &List) -> String {
+        self.postfix(ast, &e.elements, &format!("list({})", e.elements.len()))
+    }
+
+    fn visit_literal_expr(&mut self, _: &Ast, _: ExprId, e: &Literal) -> String {
+        e.value.print()
+    }
+
+    fn visit_logical_expr(&mut self, ast: &Ast, _: ExprId, e: &Logical) -> String {
+        format!(
+            "{} {} {}",
+            e.left.accept(ast, self),
+            e.right.accept(ast, self),
+            e.operator.ty,
+        )
+    }
+
+    fn visit_map_expr(&mut self, ast: &Ast, _: ExprId, e: &Map) -> String {
+        let exprs: Vec<ExprId> = e.entries.iter().flat_map(|&(k, v)| [k, v]).collect();
+        self.postfix(ast, &exprs, &format!("map({})", e.entries.len()))
+    }
+
+    fn visit_set_expr(&mut self, ast: &Ast, _: ExprId, e: &Set) -> String {
+        let equals = e.operator.map_or("=".to_string(), |operator| format!("{}=", operator.ty));
+        let operator = format!(".{} {}", self.interner.resolve(e.name.symbol), equals);
+        self.postfix(ast, &[e.object, e.value], &operator)
+    }
+
+    fn visit_set_subscript_expr(&mut self, ast: &Ast, _: ExprId, e: &SetSubscript) -> String {
+        let operator = e.operator.map_or("[]=".to_string(), |operator| format!("[]{}=", operator.ty));
+        self.postfix(ast, &[e.object, e.index, e.value], &operator)
+    }
+
+    fn visit_subscript_expr(&mut self, ast: &Ast, _: ExprId, e: &Subscript) -> String {
+        self.postfix(ast, &[e.object, e.index], "[]")
+    }
+
+    fn visit_template_expr(&mut self, ast: &Ast, _: ExprId, e: &Template) -> String {
+        self.postfix(ast, &e.parts, &format!("template({})", e.parts.len()))
+    }
+
+    fn visit_ternary_expr(&mut self, ast: &Ast, _: ExprId, e: &Ternary) -> String {
         format!(
             "{} {} {} ?:",
-            Self::print(e.left),
-            Self::print(e.mid),
-            Self::print(e.right),
+            e.left.accept(ast, self),
+            e.mid.accept(ast, self),
+            e.right.accept(ast, self),
         )
     }
 
-    fn print_unary_expr(e: Unary) -> String {
-        format!("{}{}", e.operator.lexeme, Self::print(e.right))
+    fn visit_unary_expr(&mut self, ast: &Ast, _: ExprId, e: &Unary) -> String {
+        format!("{}{}", e.operator.ty, e.right.accept(ast, self))
+    }
+
+    fn visit_update_expr(&mut self, ast: &Ast, _: ExprId, e: &Update) -> String {
+        if e.prefix {
+            format!("{}{}", e.operator.ty, e.target.accept(ast, self))
+        } else {
+            format!("{}{}", e.target.accept(ast, self), e.operator.ty)
+        }
+    }
+
+    fn visit_variable_expr(&mut self, _: &Ast, _: ExprId, e: &Variable) -> String {
+        self.interner.resolve(e.name.symbol).to_string()
     }
 }
 
+#[cfg(test)]
 mod test {
-    use crate::token::{LiteralToken, Token};
+    use crate::ast::Span;
+    use crate::token::LiteralToken;
     use crate::token_type::TokenType;
 
     use super::*;
 
     #[test]
     fn test_print() {
-        let expr: Expr = Binary::new(
-            Unary::new(
-                Token::new(TokenType::MINUS, "-", None, 1),
-                Literal::new(Some(LiteralToken::Number(123.0))).into(),
-            )
-            .into(),
-            Token::new(TokenType::STAR, "*", None, 1),
-            Grouping::new(Literal::new(Some(LiteralToken::Number(45.67))).into()).into(),
-        )
-        .into();
+        let mut ast = Ast::default();
+        let span = Span::default();
+        let n123 = ast.exprs.alloc(Literal::new(LiteralToken::Int(123)), span);
+        let minus = Operator::new(TokenType::MINUS, span);
+        let left = ast.exprs.alloc(Unary::new(minus, n123), span);
+        let n4567 = ast.exprs.alloc(Literal::new(LiteralToken::Number(45.67)), span);
+        let right = ast.exprs.alloc(Grouping::new(n4567), span);
+        let star = Operator::new(TokenType::STAR, span);
+        let expr = ast.exprs.alloc(Binary::new(left, star, right), span);
 
-        assert_eq!(RpnPrinter::print(expr), "-123 45.67 *");
+        let interner = Interner::new();
+        assert_eq!(RpnPrinter::print(&ast, &interner, expr), "-123 45.67 *");
     }
 }
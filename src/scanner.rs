@@ -1,106 +1,194 @@
 use std::error::Error;
 use std::fmt::Display;
+use std::rc::Rc;
 
+use crate::ast::Span;
+use crate::interner::{Interner, Symbol};
 use crate::token_type::TokenType::{self, *};
-use crate::{token::*, Lox};
-
-pub(crate) struct Scanner {
-    source: String,
-    tokens: Vec<Token>,
+use crate::token::*;
+use crate::Lox;
+
+/// Every keyword, with the token it scans as.
+pub(crate) static KEYWORDS: &[(&str, TokenType)] = &[
+    ("and", AND),
+    ("as", AS),
+    ("break", BREAK),
+    ("catch", CATCH),
+    ("class", CLASS),
+    ("continue", CONTINUE),
+    ("else", ELSE),
+    ("false", FALSE),
+    ("finally", FINALLY),
+    ("for", FOR),
+    ("fun", FUN),
+    ("if", IF),
+    ("import", IMPORT),
+    ("in", IN),
+    ("nil", NIL),
+    ("or", OR),
+    ("print", PRINT),
+    ("return", RETURN),
+    ("super", SUPER),
+    ("this", THIS),
+    ("throw", THROW),
+    ("true", TRUE),
+    ("try", TRY),
+    ("var", VAR),
+    ("while", WHILE),
+];
+
+/// Turns source text into tokens on demand. Each call to `next` scans just
+/// enough of the source to produce one token or error, ending with a
+/// single `EOF` token.
+pub(crate) struct Scanner<'a> {
+    source: &'a str,
+    interner: Rc<Interner>,
     start: usize,
     current: usize,
     line: usize,
+    finished: bool,
+    // Braces opened inside each `${...}` being scanned, innermost last. The
+    // `}` that closes the interpolation goes back to scanning the string.
+    interpolations: Vec<usize>,
+    // Whether comments come out as tokens, rather than being skipped.
+    comments: bool,
 }
 
 #[derive(Debug)]
-struct ScanError {
-    line: usize,
+pub(crate) struct ScanError {
+    span: Span,
     message: String,
 }
 
-impl Scanner {
+impl<'a> Scanner<'a> {
     fn keywords(text: &str) -> Option<TokenType> {
-        match text {
-            "and" => Some(AND),
-            "class" => Some(CLASS),
-            "else" => Some(ELSE),
-            "false" => Some(FALSE),
-            "for" => Some(FOR),
-            "fun" => Some(FUN),
-            "if" => Some(IF),
-            "nil" => Some(NIL),
-            "or" => Some(OR),
-            "print" => Some(PRINT),
-            "return" => Some(RETURN),
-            "super" => Some(SUPER),
-            "this" => Some(THIS),
-            "true" => Some(TRUE),
-            "var" => Some(VAR),
-            "while" => Some(WHILE),
-            _ => None,
-        }
+        KEYWORDS.iter().find(|&&(keyword, _)| keyword == text).map(|&(_, token_type)| token_type)
+    }
+
+    /// Whether `text` would scan as exactly one identifier.
+    pub(crate) fn is_identifier(text: &str) -> bool {
+        text.starts_with(|c: char| c.is_ascii_alphabetic())
+            && text.chars().all(|c| c.is_ascii_alphanumeric())
+            && Self::keywords(text).is_none()
     }
 
-    pub fn new(source: String) -> Self {
+    pub fn new(source: &'a str, interner: Rc<Interner>) -> Self {
         Self {
             source,
-            tokens: vec![],
+            interner,
             start: 0,
             current: 0,
             line: 1,
+            finished: false,
+            interpolations: vec![],
+            comments: false,
         }
     }
 
-    pub(crate) fn scan_tokens(&mut self, lox: &mut Lox) -> Vec<Token> {
-        while !(self.is_at_end()) {
-            self.start = self.current;
-            self.scan_token(lox);
-        }
-
-        let token = Token::new(EOF, "", LiteralToken::Null, self.line);
-        self.tokens.push(token);
-        self.tokens.clone()
+    /// Makes comments come out as `COMMENT` tokens, for tools such as the
+    /// formatter that keep them. The parser can't take these.
+    pub(crate) fn with_comments(mut self) -> Self {
+        self.comments = true;
+        self
     }
 
-    fn scan_token(&mut self, lox: &mut Lox) {
+    /// Scans the lexeme starting at `start`. Returns `None` if it was
+    /// whitespace, or a comment that is not kept.
+    fn scan_token(&mut self) -> Option<Result<Token<'a>, ScanError>> {
         let c: char = self.advance();
-        match c {
-            '(' => self.add_token(LEFT_PAREN, LiteralToken::Null),
-            ')' => self.add_token(RIGHT_PAREN, LiteralToken::Null),
-            '{' => self.add_token(LEFT_BRACE, LiteralToken::Null),
-            '}' => self.add_token(RIGHT_BRACE, LiteralToken::Null),
-            ',' => self.add_token(COMMA, LiteralToken::Null),
-            '.' => self.add_token(DOT, LiteralToken::Null),
-            '-' => self.add_token(MINUS, LiteralToken::Null),
-            '+' => self.add_token(PLUS, LiteralToken::Null),
-            ';' => self.add_token(SEMICOLON, LiteralToken::Null),
-            '*' => self.add_token(STAR, LiteralToken::Null),
-            '?' => self.add_token(QUESTION, LiteralToken::Null),
-            ':' => self.add_token(COLON, LiteralToken::Null),
+        let token_type = match c {
+            '(' => LEFT_PAREN,
+            ')' => RIGHT_PAREN,
+            '{' => {
+                if let Some(braces) = self.interpolations.last_mut() {
+                    *braces += 1;
+                }
+                LEFT_BRACE
+            }
+            '}' => match self.interpolations.last_mut() {
+                Some(0) => {
+                    self.interpolations.pop();
+                    return Some(self.string());
+                }
+                Some(braces) => {
+                    *braces -= 1;
+                    RIGHT_BRACE
+                }
+                None => RIGHT_BRACE,
+            },
+            '[' => LEFT_BRACKET,
+            ']' => RIGHT_BRACKET,
+            ',' => COMMA,
+            '.' => DOT,
+            ';' => SEMICOLON,
+            '%' => PERCENT,
+            '?' => QUESTION,
+            ':' => COLON,
+            '&' => AMPERSAND,
+            '|' => PIPE,
+            '^' => CARET,
+
+            '-' => {
+                if self.next_is('=') {
+                    MINUS_EQUAL
+                } else if self.next_is('-') {
+                    MINUS_MINUS
+                } else {
+                    MINUS
+                }
+            }
+            '+' => {
+                if self.next_is('=') {
+                    PLUS_EQUAL
+                } else if self.next_is('+') {
+                    PLUS_PLUS
+                } else {
+                    PLUS
+                }
+            }
+            '*' => {
+                if self.next_is('=') {
+                    STAR_EQUAL
+                } else if self.next_is('*') {
+                    STAR_STAR
+                } else {
+                    STAR
+                }
+            }
 
             '!' => {
-                let t = if self.next_is('=') { BANG_EQUAL } else { BANG };
-                self.add_token(t, LiteralToken::Null);
+                if self.next_is('=') { BANG_EQUAL } else { BANG }
             }
             '=' => {
-                let t = if self.next_is('=') {
+                if self.next_is('=') {
                     EQUAL_EQUAL
+                } else if self.next_is('>') {
+                    ARROW
                 } else {
                     EQUAL
-                };
-                self.add_token(t, LiteralToken::Null);
+                }
             }
             '<' => {
-                let t = if self.next_is('=') { LESS_EQUAL } else { LESS };
-                self.add_token(t, LiteralToken::Null);
+                if self.next_is('=') {
+                    LESS_EQUAL
+                } else if self.next_is('<') {
+                    LESS_LESS
+                } else {
+                    LESS
+                }
             }
             '>' => {
-                let t = if self.next_is('=') {
+                if self.next_is('=') {
                     GREATER_EQUAL
+                } else if self.next_is('>') {
+                    GREATER_GREATER
                 } else {
                     GREATER
-                };
-                self.add_token(t, LiteralToken::Null);
+                }
+            }
+
+            '~' => {
+                if self.next_is('/') { TILDE_SLASH } else { TILDE }
             }
 
             '/' => {
@@ -109,6 +197,7 @@ impl Scanner {
                     while self.peek() != '\n' && !self.is_at_end() {
                         self.advance();
                     }
+                    return self.comment();
                 } else if self.next_is('*') {
                     while !self.is_at_end() && (self.peek() != '*' || self.peek_next() != '/') {
                         if self.peek() == '\n' {
@@ -117,47 +206,73 @@ impl Scanner {
                         self.advance();
                     }
                     if self.is_at_end() {
-                        lox.error(ScanError::new(self.line, "Unterminated multiline comment."));
+                        return Some(Err(ScanError::new(self.span(), "Unterminated multiline comment.")));
                     }
                     self.advance();
                     self.advance();
+                    return self.comment();
+                } else if self.next_is('=') {
+                    SLASH_EQUAL
                 } else {
-                    self.add_token(SLASH, LiteralToken::Null);
+                    SLASH
                 }
             }
 
-            ' ' | '\r' | '\t' => {}
+            ' ' | '\r' | '\t' => return None,
 
-            '\n' => self.line += 1,
+            '\n' => {
+                self.line += 1;
+                return None;
+            }
 
-            '"' => self.string(lox),
+            '"' => return Some(self.string()),
 
             // TODO: coalesce a run of invalid characters into a single error
             _ => {
                 if c.is_ascii_digit() {
-                    self.number();
+                    self.number()
                 } else if c.is_ascii_alphabetic() {
-                    self.identifier();
+                    return Some(Ok(self.identifier()));
                 } else {
-                    lox.error(ScanError::new(self.line, "Unexpected character"));
+                    return Some(Err(ScanError::new(self.span(), "Unexpected character")));
                 }
             }
-        }
+        };
+
+        Some(Ok(self.make_token(token_type, None)))
+    }
+
+    fn comment(&self) -> Option<Result<Token<'a>, ScanError>> {
+        self.comments.then(|| Ok(self.make_token(COMMENT, None)))
     }
 
-    fn identifier(&mut self) {
+    fn identifier(&mut self) -> Token<'a> {
         while self.peek().is_ascii_alphanumeric() {
             self.advance();
         }
 
-        let text = self.source.get(self.start..self.current).unwrap();
-        let token_type = Self::keywords(text).unwrap_or(IDENTIFIER);
-
-        self.add_token(token_type, LiteralToken::Null);
+        let text = &self.source[self.start..self.current];
+        match Self::keywords(text) {
+            Some(token_type) => self.make_token(token_type, None),
+            None => {
+                let symbol = self.interner.intern(text);
+                self.make_token(IDENTIFIER, Some(symbol))
+            }
+        }
     }
 
-    fn string(&mut self, lox: &mut Lox) {
+    /// Scans the rest of a string literal, after its opening quote or the
+    /// `}` of an interpolation. Stops early at the next `${`.
+    fn string(&mut self) -> Result<Token<'a>, ScanError> {
         while self.peek() != '"' && !self.is_at_end() {
+            if self.peek() == '$' && self.peek_next() == '{' {
+                self.advance();
+                self.advance();
+                self.interpolations.push(0);
+                let value = &self.source[(self.start + 1)..(self.current - 2)];
+                let symbol = self.interner.intern(value);
+                return Ok(self.make_token(INTERPOLATION, Some(symbol)));
+            }
             if self.peek() == '\n' {
                 self.line += 1;
             }
@@ -165,87 +280,78 @@ impl Scanner {
         }
 
         if self.is_at_end() {
-            lox.error(ScanError::new(self.line, "Unterminated string"));
-            return;
+            return Err(ScanError::new(self.span(), "Unterminated string"));
         }
 
         // The closing "
         self.advance();
 
-        // Trim the surrounding quotes
-        let value = self
-            .source
-            .get((self.start + 1)..(self.current - 1))
-            .unwrap()
-            .to_string();
-        self.add_token(STRING, LiteralToken::String(value));
+        // Trim the surrounding quotes, or the `}` and the quote
+        let value = &self.source[(self.start + 1)..(self.current - 1)];
+        let symbol = self.interner.intern(value);
+        Ok(self.make_token(STRING, Some(symbol)))
     }
 
-    fn number(&mut self) {
+    /// Scans an integer literal, or a float literal if it has a fractional
+    /// part. An `n` suffix on an integer makes it a big integer, and a `d`
+    /// suffix on either makes it an exact decimal.
+    fn number(&mut self) -> TokenType {
         while self.peek().is_ascii_digit() {
             self.advance();
         }
 
+        let mut ty = INTEGER;
         if self.peek() == '.' && self.peek_next().is_ascii_digit() {
             // Consume the '.'
             self.advance();
             while self.peek().is_ascii_digit() {
                 self.advance();
             }
+            ty = NUMBER;
         }
 
-        // TODO: what if there is no digit after the decimal point?
-        let value = self
-            .source
-            .get(self.start..self.current)
-            .unwrap()
-            .parse::<f64>()
-            .unwrap();
-        self.add_token(NUMBER, LiteralToken::Number(value));
+        if ty == INTEGER && self.next_is('n') {
+            BIGINT
+        } else if self.next_is('d') {
+            DECIMAL
+        } else {
+            // TODO: what if there is no digit after the decimal point?
+            ty
+        }
     }
 
     fn next_is(&mut self, expected: char) -> bool {
-        if self.is_at_end() {
-            false
-        } else if self.char_at(self.current) != expected {
+        if self.is_at_end() || self.peek() != expected {
             false
         } else {
-            self.current += 1;
+            self.current += expected.len_utf8();
             true
         }
     }
 
     fn peek(&self) -> char {
-        if self.is_at_end() {
-            '\0'
-        } else {
-            self.char_at(self.current)
-        }
+        self.source[self.current..].chars().next().unwrap_or('\0')
     }
 
     fn peek_next(&self) -> char {
-        if self.current + 1 >= self.source.len() {
-            '\0'
-        } else {
-            self.char_at(self.current + 1)
-        }
+        self.source[self.current..].chars().nth(1).unwrap_or('\0')
     }
 
+    // `start` and `current` are byte offsets, always on a char boundary.
     fn advance(&mut self) -> char {
-        // TODO: this is wildly inefficient, as it reiterate over the source
-        // string everytime. Maybe use the iterator directly?
-        self.current += 1;
-        self.char_at(self.current - 1)
+        let c = self.peek();
+        self.current += c.len_utf8();
+        c
     }
 
-    fn char_at(&self, position: usize) -> char {
-        self.source.chars().nth(position).unwrap()
+    // The lexeme scanned so far, ending on the current line.
+    fn span(&self) -> Span {
+        Span::new(self.start, self.current, self.line)
     }
 
-    fn add_token(&mut self, token_type: TokenType, literal: LiteralToken) {
-        let text = self.source.get(self.start..self.current).unwrap();
-        let token = Token::new(token_type, text, literal, self.line);
-        self.tokens.push(token);
+    fn make_token(&self, token_type: TokenType, symbol: Option<Symbol>) -> Token<'a> {
+        let text = &self.source[self.start..self.current];
+        Token::new(token_type, text, symbol, self.line, self.start)
     }
 
     fn is_at_end(&self) -> bool {
@@ -253,19 +359,82 @@ impl Scanner {
     }
 }
 
+impl<'a> Iterator for Scanner<'a> {
+    type Item = Result<Token<'a>, ScanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.is_at_end() {
+            self.start = self.current;
+            if let Some(result) = self.scan_token() {
+                return Some(result);
+            }
+        }
+
+        if self.finished {
+            None
+        } else {
+            self.finished = true;
+            Some(Ok(Token::new(EOF, "", None, self.line, self.current)))
+        }
+    }
+}
+
 impl ScanError {
-    fn new(line: usize, message: &str) -> Self {
+    fn new(span: Span, message: &str) -> Self {
         Self {
-            line,
+            span,
             message: message.to_string(),
         }
     }
+
+    /// The lexeme that failed to scan.
+    pub(crate) fn span(&self) -> Span {
+        self.span
+    }
+
+    pub(crate) fn message(&self) -> &str {
+        &self.message
+    }
 }
 
 impl Display for ScanError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", Lox::error_message(self.line, "", &self.message),)
+        write!(f, "{}", Lox::error_message(self.span.line, "", &self.message),)
     }
 }
 
 impl Error for ScanError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_scan_lazily() {
+        let mut scanner = Scanner::new("print \"héllo\"; @ 1.5", Rc::new(Interner::new()));
+        assert_eq!(scanner.next().unwrap().unwrap().ty, PRINT);
+        assert_eq!(scanner.next().unwrap().unwrap().lexeme, "\"héllo\"");
+        assert_eq!(scanner.next().unwrap().unwrap().ty, SEMICOLON);
+        assert!(scanner.next().unwrap().is_err());
+
+        let number = scanner.next().unwrap().unwrap();
+        assert_eq!((number.ty, number.lexeme, number.offset), (NUMBER, "1.5", 18));
+        assert_eq!(scanner.next().unwrap().unwrap().ty, EOF);
+        assert!(scanner.next().is_none());
+    }
+
+    #[test]
+    fn test_scan_comments() {
+        let source = "a // one
+/* two
+ */ b";
+        fn lexemes(scanner: Scanner<'_>) -> Vec<&str> {
+            scanner.map(|token| token.unwrap().lexeme).collect()
+        }
+        assert_eq!(lexemes(Scanner::new(source, Rc::new(Interner::new()))), vec!["a", "b", ""]);
+        assert_eq!(
+            lexemes(Scanner::new(source, Rc::new(Interner::new())).with_comments()),
+            vec!["a", "// one", "/* two\n */", "b", ""]
+        );
+    }
+}
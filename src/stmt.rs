@@ -1,17 +1,307 @@
-use crate::expr::Expr;
+use std::rc::Rc;
+
+use crate::ast::{Ast, ExprId, Span, StmtId};
+use crate::expr::Identifier;
 use derive_new::new;
 
+#[derive(Debug)]
 pub(crate) enum Stmt {
+    Block(BlockStmt),
+    Break(BreakStmt),
+    Continue(ContinueStmt),
     Expression(ExpressionStmt),
+    For(ForStmt),
+    ForIn(ForInStmt),
+    Function(FunctionStmt),
+    If(IfStmt),
+    Import(ImportStmt),
     Print(PrintStmt),
+    Return(ReturnStmt),
+    Throw(ThrowStmt),
+    Try(TryStmt),
+    Var(VarStmt),
+    While(WhileStmt),
+}
+
+#[derive(new, Debug)]
+pub(crate) struct BlockStmt {
+    pub(crate) statements: Vec<StmtId>,
+}
+
+#[derive(new, Debug)]
+pub(crate) struct BreakStmt {
+    pub(crate) keyword: Span,
+}
+
+#[derive(new, Debug)]
+pub(crate) struct ContinueStmt {
+    pub(crate) keyword: Span,
 }
 
 #[derive(new, Debug)]
 pub(crate) struct ExpressionStmt {
-    pub(crate) expression: Expr,
+    pub(crate) expression: ExprId,
+}
+
+/// A C-style `for`. It is kept as is rather than turned into a `while`, so
+/// its increment stays a distinct part of the loop.
+#[derive(new, Debug)]
+pub(crate) struct ForStmt {
+    pub(crate) initializer: Option<StmtId>,
+    pub(crate) condition: Option<ExprId>,
+    pub(crate) increment: Option<ExprId>,
+    pub(crate) body: StmtId,
+}
+
+/// A `for (name in iterable)` loop.
+#[derive(new, Debug)]
+pub(crate) struct ForInStmt {
+    pub(crate) name: Identifier,
+    pub(crate) iterable: ExprId,
+    pub(crate) body: StmtId,
+}
+
+/// A named function declaration. Its `function` is the `Lambda` holding
+/// the parameters and body.
+#[derive(new, Debug)]
+pub(crate) struct FunctionStmt {
+    pub(crate) name: Identifier,
+    pub(crate) function: ExprId,
+}
+
+#[derive(new, Debug)]
+pub(crate) struct IfStmt {
+    pub(crate) condition: ExprId,
+    pub(crate) then_branch: StmtId,
+    pub(crate) else_branch: Option<StmtId>,
+}
+
+/// An `import "path" as name;` declaration, binding `name` to the namespace
+/// of the module at `path`. Without `as`, the name is the file name of the
+/// path, without its extension.
+#[derive(new, Debug)]
+pub(crate) struct ImportStmt {
+    pub(crate) keyword: Span,
+    pub(crate) path: Rc<str>,
+    pub(crate) name: Identifier,
 }
 
 #[derive(new, Debug)]
 pub(crate) struct PrintStmt {
-    pub(crate) expression: Expr,
+    pub(crate) expression: ExprId,
+}
+
+#[derive(new, Debug)]
+pub(crate) struct ReturnStmt {
+    pub(crate) keyword: Span,
+    pub(crate) value: Option<ExprId>,
+}
+
+#[derive(new, Debug)]
+pub(crate) struct ThrowStmt {
+    pub(crate) keyword: Span,
+    pub(crate) value: ExprId,
+}
+
+/// A `try` block with a `catch` clause, a `finally` block, or both.
+#[derive(new, Debug)]
+pub(crate) struct TryStmt {
+    pub(crate) body: StmtId,
+    pub(crate) catch: Option<CatchClause>,
+    pub(crate) finally: Option<StmtId>,
+}
+
+/// The `catch (name) { ... }` of a `try`. Its body runs with `name` bound
+/// to the value thrown.
+#[derive(new, Debug)]
+pub(crate) struct CatchClause {
+    pub(crate) name: Identifier,
+    pub(crate) body: StmtId,
+}
+
+#[derive(new, Debug)]
+pub(crate) struct VarStmt {
+    pub(crate) name: Identifier,
+    pub(crate) initializer: Option<ExprId>,
+}
+
+#[derive(new, Debug)]
+pub(crate) struct WhileStmt {
+    pub(crate) condition: ExprId,
+    pub(crate) body: StmtId,
+}
+
+impl From<BlockStmt> for Stmt {
+    fn from(s: BlockStmt) -> Self {
+        Self::Block(s)
+    }
+}
+
+impl From<BreakStmt> for Stmt {
+    fn from(s: BreakStmt) -> Self {
+        Self::Break(s)
+    }
+}
+
+impl From<ContinueStmt> for Stmt {
+    fn from(s: ContinueStmt) -> Self {
+        Self::Continue(s)
+    }
+}
+
+impl From<ExpressionStmt> for Stmt {
+    fn from(s: ExpressionStmt) -> Self {
+        Self::Expression(s)
+    }
+}
+
+impl From<ForStmt> for Stmt {
+    fn from(s: ForStmt) -> Self {
+        Self::For(s)
+    }
+}
+
+impl From<ForInStmt> for Stmt {
+    fn from(s: ForInStmt) -> Self {
+        Self::ForIn(s)
+    }
+}
+
+impl From<FunctionStmt> for Stmt {
+    fn from(s: FunctionStmt) -> Self {
+        Self::Function(s)
+    }
+}
+
+impl From<IfStmt> for Stmt {
+    fn from(s: IfStmt) -> Self {
+        Self::If(s)
+    }
+}
+
+impl From<ImportStmt> for Stmt {
+    fn from(s: ImportStmt) -> Self {
+        Self::Import(s)
+    }
+}
+
+impl From<PrintStmt> for Stmt {
+    fn from(s: PrintStmt) -> Self {
+        Self::Print(s)
+    }
+}
+
+impl From<ReturnStmt> for Stmt {
+    fn from(s: ReturnStmt) -> Self {
+        Self::Return(s)
+    }
+}
+
+impl From<ThrowStmt> for Stmt {
+    fn from(s: ThrowStmt) -> Self {
+        Self::Throw(s)
+    }
+}
+
+impl From<TryStmt> for Stmt {
+    fn from(s: TryStmt) -> Self {
+        Self::Try(s)
+    }
+}
+
+impl From<VarStmt> for Stmt {
+    fn from(s: VarStmt) -> Self {
+        Self::Var(s)
+    }
+}
+
+impl From<WhileStmt> for Stmt {
+    fn from(s: WhileStmt) -> Self {
+        Self::While(s)
+    }
+}
+
+/// Walks statements by shared reference, producing an `R` for each node.
+/// Each method receives the tree, the node's id, and the node itself.
+pub(crate) trait StmtVisitor<R> {
+    fn visit_block_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &BlockStmt) -> R;
+    fn visit_break_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &BreakStmt) -> R;
+    fn visit_continue_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &ContinueStmt) -> R;
+    fn visit_expression_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &ExpressionStmt) -> R;
+    fn visit_for_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &ForStmt) -> R;
+    fn visit_for_in_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &ForInStmt) -> R;
+    fn visit_function_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &FunctionStmt) -> R;
+    fn visit_if_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &IfStmt) -> R;
+    fn visit_import_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &ImportStmt) -> R;
+    fn visit_print_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &PrintStmt) -> R;
+    fn visit_return_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &ReturnStmt) -> R;
+    fn visit_throw_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &ThrowStmt) -> R;
+    fn visit_try_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &TryStmt) -> R;
+    fn visit_var_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &VarStmt) -> R;
+    fn visit_while_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &WhileStmt) -> R;
+}
+
+/// Walks statements with mutable access to the tree, for passes that
+/// rewrite it in place. Each method receives the id of a node of the
+/// matching variant.
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) trait StmtVisitorMut<R> {
+    fn visit_block_stmt(&mut self, ast: &mut Ast, id: StmtId) -> R;
+    fn visit_break_stmt(&mut self, ast: &mut Ast, id: StmtId) -> R;
+    fn visit_continue_stmt(&mut self, ast: &mut Ast, id: StmtId) -> R;
+    fn visit_expression_stmt(&mut self, ast: &mut Ast, id: StmtId) -> R;
+    fn visit_for_stmt(&mut self, ast: &mut Ast, id: StmtId) -> R;
+    fn visit_for_in_stmt(&mut self, ast: &mut Ast, id: StmtId) -> R;
+    fn visit_function_stmt(&mut self, ast: &mut Ast, id: StmtId) -> R;
+    fn visit_if_stmt(&mut self, ast: &mut Ast, id: StmtId) -> R;
+    fn visit_import_stmt(&mut self, ast: &mut Ast, id: StmtId) -> R;
+    fn visit_print_stmt(&mut self, ast: &mut Ast, id: StmtId) -> R;
+    fn visit_return_stmt(&mut self, ast: &mut Ast, id: StmtId) -> R;
+    fn visit_throw_stmt(&mut self, ast: &mut Ast, id: StmtId) -> R;
+    fn visit_try_stmt(&mut self, ast: &mut Ast, id: StmtId) -> R;
+    fn visit_var_stmt(&mut self, ast: &mut Ast, id: StmtId) -> R;
+    fn visit_while_stmt(&mut self, ast: &mut Ast, id: StmtId) -> R;
+}
+
+impl StmtId {
+    pub(crate) fn accept<R>(self, ast: &Ast, visitor: &mut impl StmtVisitor<R>) -> R {
+        match &ast[self] {
+            Stmt::Block(s) => visitor.visit_block_stmt(ast, self, s),
+            Stmt::Break(s) => visitor.visit_break_stmt(ast, self, s),
+            Stmt::Continue(s) => visitor.visit_continue_stmt(ast, self, s),
+            Stmt::Expression(s) => visitor.visit_expression_stmt(ast, self, s),
+            Stmt::For(s) => visitor.visit_for_stmt(ast, self, s),
+            Stmt::ForIn(s) => visitor.visit_for_in_stmt(ast, self, s),
+            Stmt::Function(s) => visitor.visit_function_stmt(ast, self, s),
+            Stmt::If(s) => visitor.visit_if_stmt(ast, self, s),
+            Stmt::Import(s) => visitor.visit_import_stmt(ast, self, s),
+            Stmt::Print(s) => visitor.visit_print_stmt(ast, self, s),
+            Stmt::Return(s) => visitor.visit_return_stmt(ast, self, s),
+            Stmt::Throw(s) => visitor.visit_throw_stmt(ast, self, s),
+            Stmt::Try(s) => visitor.visit_try_stmt(ast, self, s),
+            Stmt::Var(s) => visitor.visit_var_stmt(ast, self, s),
+            Stmt::While(s) => visitor.visit_while_stmt(ast, self, s),
+        }
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub(crate) fn accept_mut<R>(self, ast: &mut Ast, visitor: &mut impl StmtVisitorMut<R>) -> R {
+        match &ast[self] {
+            Stmt::Block(_) => visitor.visit_block_stmt(ast, self),
+            Stmt::Break(_) => visitor.visit_break_stmt(ast, self),
+            Stmt::Continue(_) => visitor.visit_continue_stmt(ast, self),
+            Stmt::Expression(_) => visitor.visit_expression_stmt(ast, self),
+            Stmt::For(_) => visitor.visit_for_stmt(ast, self),
+            Stmt::ForIn(_) => visitor.visit_for_in_stmt(ast, self),
+            Stmt::Function(_) => visitor.visit_function_stmt(ast, self),
+            Stmt::If(_) => visitor.visit_if_stmt(ast, self),
+            Stmt::Import(_) => visitor.visit_import_stmt(ast, self),
+            Stmt::Print(_) => visitor.visit_print_stmt(ast, self),
+            Stmt::Return(_) => visitor.visit_return_stmt(ast, self),
+            Stmt::Throw(_) => visitor.visit_throw_stmt(ast, self),
+            Stmt::Try(_) => visitor.visit_try_stmt(ast, self),
+            Stmt::Var(_) => visitor.visit_var_stmt(ast, self),
+            Stmt::While(_) => visitor.visit_while_stmt(ast, self),
+        }
+    }
 }
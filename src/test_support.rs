@@ -0,0 +1,55 @@
+use std::fs;
+use std::io::{self, Cursor, Write};
+use std::sync::{Arc, Mutex};
+
+use serde_json::Value;
+
+use crate::protocol::read_message;
+
+/// Output that a test can read while, or after, a debugger or server
+/// writes it, from any thread.
+#[derive(Clone, Default)]
+pub(crate) struct Transcript(Arc<Mutex<Vec<u8>>>);
+
+impl Transcript {
+    /// What was written so far.
+    pub(crate) fn contents(&self) -> Vec<u8> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// The messages written whole so far, as the protocols frame them.
+    pub(crate) fn messages(&self) -> Vec<Value> {
+        let mut output = Cursor::new(self.contents());
+        let mut messages = vec![];
+        while let Ok(Some(message)) = read_message(&mut output) {
+            messages.push(message);
+        }
+        messages
+    }
+}
+
+impl Write for Transcript {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The messages in `output`, each after its `Content-Length` header.
+pub(crate) fn read_messages(output: Vec<u8>) -> Vec<Value> {
+    let mut output = Cursor::new(output);
+    let mut messages = vec![];
+    while let Some(message) = read_message(&mut output).unwrap() {
+        messages.push(message);
+    }
+    messages
+}
+
+/// The messages of a scripted session, from a JSON array in `path`.
+pub(crate) fn read_fixture(path: &str) -> Vec<Value> {
+    let json = fs::read_to_string(path).unwrap();
+    serde_json::from_str(&json).unwrap()
+}
@@ -1,11 +1,21 @@
+use std::rc::Rc;
+
+use crate::ast::Span;
+use crate::bignum::{BigInt, Decimal};
+use crate::interner::Symbol;
 use crate::token_type::TokenType;
+use crate::value::Value;
 
 #[derive(Debug, Clone)]
 pub(crate) enum LiteralToken {
     Null,
     Bool(bool),
+    Int(i64),
+    BigInt(Rc<BigInt>),
+    Decimal(Rc<Decimal>),
     Number(f64),
-    String(String),
+    /// An interned string constant, shared with every equal constant.
+    String(Rc<str>),
 }
 
 impl LiteralToken {
@@ -13,32 +23,46 @@ impl LiteralToken {
         match self {
             LiteralToken::Null => "nil".to_string(),
             LiteralToken::Bool(b) => format!("{}", b),
-            LiteralToken::Number(n) => format!("{}", n),
+            LiteralToken::Int(n) => format!("{}", n),
+            LiteralToken::BigInt(n) => format!("{}n", n),
+            LiteralToken::Decimal(n) => format!("{}d", n),
+            LiteralToken::Number(n) => Value::Number(*n).to_string(),
             LiteralToken::String(s) => format!("\"{}\"", s),
         }
     }
 }
 
-#[derive(Debug, Clone)]
-pub(crate) struct Token {
+/// A token as produced by the scanner. The lexeme borrows from the source
+/// instead of owning a copy, so tokens are cheap to copy around.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Token<'a> {
     pub(crate) ty: TokenType,
-    pub(crate) lexeme: String,
-    pub(crate) literal: LiteralToken,
+    pub(crate) lexeme: &'a str,
+    /// The interned name of an identifier, or contents of a string literal.
+    pub(crate) symbol: Option<Symbol>,
     pub(crate) line: usize,
+    /// Where the lexeme starts in the source.
+    pub(crate) offset: usize,
 }
 
-impl Token {
+impl<'a> Token<'a> {
     pub(crate) fn new(
         token_type: TokenType,
-        lexeme: &str,
-        literal: LiteralToken,
+        lexeme: &'a str,
+        symbol: Option<Symbol>,
         line: usize,
+        offset: usize,
     ) -> Self {
         Self {
             ty: token_type,
-            lexeme: lexeme.to_string(),
-            literal,
+            lexeme,
+            symbol,
             line,
+            offset,
         }
     }
+
+    pub(crate) fn span(&self) -> Span {
+        Span::new(self.offset, self.offset + self.lexeme.len(), self.line)
+    }
 }
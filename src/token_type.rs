@@ -1,11 +1,15 @@
-#[derive(Debug, Clone, PartialEq)]
-#[allow(non_camel_case_types)]
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
 pub(crate) enum TokenType {
     // Single-character tokens.
     LEFT_PAREN,
     RIGHT_PAREN,
     LEFT_BRACE,
     RIGHT_BRACE,
+    LEFT_BRACKET,
+    RIGHT_BRACKET,
     COMMA,
     DOT,
     MINUS,
@@ -13,41 +17,153 @@ pub(crate) enum TokenType {
     SEMICOLON,
     SLASH,
     STAR,
+    PERCENT,
     QUESTION,
     COLON,
+    AMPERSAND,
+    PIPE,
+    CARET,
+    TILDE,
 
     // One or two character tokens.
     BANG,
     BANG_EQUAL,
     EQUAL,
     EQUAL_EQUAL,
+    ARROW,
     GREATER,
     GREATER_EQUAL,
     LESS,
     LESS_EQUAL,
+    TILDE_SLASH,
+    MINUS_EQUAL,
+    MINUS_MINUS,
+    PLUS_EQUAL,
+    PLUS_PLUS,
+    SLASH_EQUAL,
+    STAR_EQUAL,
+    STAR_STAR,
+    LESS_LESS,
+    GREATER_GREATER,
 
     // Literals.
     IDENTIFIER,
     STRING,
+    // The text of a string up to an interpolated `${`. The string goes on
+    // with another one of these, or a `STRING` up to the closing quote,
+    // after the expression's closing `}`.
+    INTERPOLATION,
+    INTEGER,
+    BIGINT,
+    DECIMAL,
     NUMBER,
 
     // Keywords.
     AND,
+    AS,
+    BREAK,
+    CATCH,
     CLASS,
+    CONTINUE,
     ELSE,
     FALSE,
+    FINALLY,
     FUN,
     FOR,
     IF,
+    IMPORT,
+    IN,
     NIL,
     OR,
     PRINT,
     RETURN,
     SUPER,
     THIS,
+    THROW,
     TRUE,
+    TRY,
     VAR,
     WHILE,
 
+    // Trivia, only scanned when asked for.
+    COMMENT,
+
     EOF,
 }
+
+impl Display for TokenType {
+    /// Writes how the token is spelled in source, or its name for tokens
+    /// without a fixed spelling.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use TokenType::*;
+
+        let text = match self {
+            LEFT_PAREN => "(",
+            RIGHT_PAREN => ")",
+            LEFT_BRACE => "{",
+            RIGHT_BRACE => "}",
+            LEFT_BRACKET => "[",
+            RIGHT_BRACKET => "]",
+            COMMA => ",",
+            DOT => ".",
+            MINUS => "-",
+            PLUS => "+",
+            SEMICOLON => ";",
+            SLASH => "/",
+            STAR => "*",
+            PERCENT => "%",
+            QUESTION => "?",
+            COLON => ":",
+            AMPERSAND => "&",
+            PIPE => "|",
+            CARET => "^",
+            TILDE => "~",
+            BANG => "!",
+            BANG_EQUAL => "!=",
+            EQUAL => "=",
+            EQUAL_EQUAL => "==",
+            ARROW => "=>",
+            GREATER => ">",
+            GREATER_EQUAL => ">=",
+            LESS => "<",
+            LESS_EQUAL => "<=",
+            TILDE_SLASH => "~/",
+            MINUS_EQUAL => "-=",
+            MINUS_MINUS => "--",
+            PLUS_EQUAL => "+=",
+            PLUS_PLUS => "++",
+            SLASH_EQUAL => "/=",
+            STAR_EQUAL => "*=",
+            STAR_STAR => "**",
+            LESS_LESS => "<<",
+            GREATER_GREATER => ">>",
+            AND => "and",
+            AS => "as",
+            BREAK => "break",
+            CATCH => "catch",
+            CLASS => "class",
+            CONTINUE => "continue",
+            ELSE => "else",
+            FALSE => "false",
+            FINALLY => "finally",
+            FUN => "fun",
+            FOR => "for",
+            IF => "if",
+            IMPORT => "import",
+            IN => "in",
+            NIL => "nil",
+            OR => "or",
+            PRINT => "print",
+            RETURN => "return",
+            SUPER => "super",
+            THIS => "this",
+            THROW => "throw",
+            TRUE => "true",
+            TRY => "try",
+            VAR => "var",
+            WHILE => "while",
+            _ => return write!(f, "{:?}", self),
+        };
+        write!(f, "{}", text)
+    }
+}
@@ -0,0 +1,367 @@
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use indexmap::IndexMap;
+
+use crate::bignum::{BigInt, Decimal};
+use crate::function::LoxFunction;
+use crate::module::Module;
+use crate::token::LiteralToken;
+
+// In Java, all generic values of Lox is fitted into Object, using 
+// `istanceOf` for type-checking and finding runtime errors. This is
+// not feasible in Rust, so for now we just wrap every possible Lox
+// values in an enum instead of a Trait. In effect, we have implemented
+// a type system for Lox in Rust.
+#[derive(Debug, Clone)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    // Integers are exact: arithmetic on them fails rather than overflow.
+    Int(i64),
+    // Integers of any size, written with an `n` suffix. They stay big even
+    // when small enough for `Int`, so their type doesn't depend on their
+    // value.
+    BigInt(Rc<BigInt>),
+    // Exact decimals, written with a `d` suffix. They never mix with floats.
+    Decimal(Rc<Decimal>),
+    Number(f64),
+    // Strings are immutable, so values share them. Comparing two `Rc`s that
+    // point to the same allocation skips the byte comparison, which makes
+    // equality between interned constants a pointer check.
+    String(Rc<str>),
+    // Lists are mutable and shared: every copy of the value sees changes
+    // made through any other.
+    List(Rc<RefCell<Vec<Value>>>),
+    // Maps are shared like lists, and remember the order in which their
+    // keys were first inserted.
+    Map(Rc<RefCell<IndexMap<MapKey, Value>>>),
+    Range(Range),
+    NativeFunction(&'static NativeFunction),
+    NativeMethod(Rc<NativeMethod>),
+    Function(Rc<LoxFunction>),
+    Module(Rc<Module>),
+    Error(Rc<ErrorObject>),
+}
+
+/// The numbers from `start` up to, but not including, `end`, `step` apart.
+/// A negative `step` counts down instead. Ranges are immutable and produce
+/// their numbers on demand, as integers if all three bounds are integers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Range {
+    Int { start: i64, end: i64, step: i64 },
+    Float { start: f64, end: f64, step: f64 },
+}
+
+/// A value that can key a map. Only immutable values qualify, and two keys
+/// are the same exactly when `Interpreter::is_equal` holds between them.
+#[derive(Debug, Clone)]
+pub(crate) struct MapKey(Value);
+
+/// A function implemented in Rust. It is called with exactly `arity`
+/// arguments, and fails with the message of a runtime error.
+#[derive(Debug)]
+pub(crate) struct NativeFunction {
+    pub(crate) name: &'static str,
+    pub(crate) arity: usize,
+    pub(crate) function: fn(&[Value]) -> Result<Value, String>,
+}
+
+/// A native method looked up on a value, which it is called on. The method
+/// receives that value as its first argument, before the `arity` others.
+#[derive(Debug)]
+pub(crate) struct NativeMethod {
+    pub(crate) receiver: Value,
+    pub(crate) method: &'static NativeFunction,
+}
+
+/// A runtime error raised by the interpreter, as a `catch` clause sees it.
+/// Its fields are read as `e.message` and `e.line`.
+#[derive(Debug)]
+pub(crate) struct ErrorObject {
+    pub(crate) message: Rc<str>,
+    pub(crate) line: usize,
+}
+
+impl Value {
+    pub(crate) fn list(values: Vec<Value>) -> Self {
+        Value::List(Rc::new(RefCell::new(values)))
+    }
+
+    pub(crate) fn map(entries: IndexMap<MapKey, Value>) -> Self {
+        Value::Map(Rc::new(RefCell::new(entries)))
+    }
+
+    pub(crate) fn bigint(n: BigInt) -> Self {
+        Value::BigInt(Rc::new(n))
+    }
+
+    pub(crate) fn decimal(n: Decimal) -> Self {
+        Value::Decimal(Rc::new(n))
+    }
+
+    /// The value as a big integer, if it is an integer of either kind.
+    pub(crate) fn to_bigint(&self) -> Option<BigInt> {
+        match self {
+            Value::Int(n) => Some(BigInt::from(*n)),
+            Value::BigInt(n) => Some((**n).clone()),
+            _ => None,
+        }
+    }
+
+    /// The value as a decimal, if it is an exact number.
+    pub(crate) fn to_decimal(&self) -> Option<Decimal> {
+        match self {
+            Value::Decimal(n) => Some((**n).clone()),
+            _ => self.to_bigint().map(Decimal::from),
+        }
+    }
+
+    /// Converts `self` into a position in a sequence where `0..bound` are
+    /// valid positions. Floats with no fractional part are accepted too.
+    pub(crate) fn to_index(&self, bound: usize) -> Result<usize, String> {
+        let n = match self {
+            Value::Int(n) => *n,
+            Value::Number(n) => exact_int(*n).ok_or("Index must be an integer.")?,
+            _ => return Err("Index must be an integer.".to_string()),
+        };
+
+        if n < 0 {
+            Err(format!("Index {} is negative.", n))
+        } else if n as u64 >= bound as u64 {
+            Err(format!("Index {} is out of bounds.", n))
+        } else {
+            Ok(n as usize)
+        }
+    }
+
+    /// How the value would be spelled as a literal, for quoting it in
+    /// messages.
+    pub(crate) fn repr(&self) -> String {
+        match self {
+            Value::String(v) => format!("\"{}\"", v),
+            _ => self.to_string(),
+        }
+    }
+
+    // Writes how the value would be spelled as a literal, so that strings
+    // nested in a collection stand out from the surrounding punctuation.
+    fn fmt_nested(&self, f: &mut std::fmt::Formatter<'_>, printing: &mut Vec<*const ()>) -> std::fmt::Result {
+        match self {
+            Value::String(v) => write!(f, "\"{}\"", v),
+            _ => self.fmt_within(f, printing),
+        }
+    }
+
+    // Writes the value inside the collections in `printing`, which are
+    // being written already. A collection holding one of them, directly or
+    // not, shows it as `[...]` or `{...}` rather than recursing forever.
+    fn fmt_within(&self, f: &mut std::fmt::Formatter<'_>, printing: &mut Vec<*const ()>) -> std::fmt::Result {
+        match self {
+            Value::List(v) => {
+                let pointer = Rc::as_ptr(v) as *const ();
+                if printing.contains(&pointer) {
+                    return write!(f, "[...]");
+                }
+                printing.push(pointer);
+                write!(f, "[")?;
+                for (i, value) in v.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    value.fmt_nested(f, printing)?;
+                }
+                printing.pop();
+                write!(f, "]")
+            }
+            Value::Map(v) => {
+                let pointer = Rc::as_ptr(v) as *const ();
+                if printing.contains(&pointer) {
+                    return write!(f, "{{...}}");
+                }
+                printing.push(pointer);
+                write!(f, "{{")?;
+                for (i, (key, value)) in v.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", key)?;
+                    value.fmt_nested(f, printing)?;
+                }
+                printing.pop();
+                write!(f, "}}")
+            }
+            _ => write!(f, "{}", self),
+        }
+    }
+}
+
+/// The integer equal to `n`, if there is one.
+pub(crate) fn exact_int(n: f64) -> Option<i64> {
+    // `i64::MAX as f64` rounds up to 2^63, which is out of range.
+    let in_range = n >= i64::MIN as f64 && n < i64::MAX as f64;
+    (n.fract() == 0.0 && in_range).then_some(n as i64)
+}
+
+impl Range {
+    /// The `index`th number of the range, if it has that many.
+    pub(crate) fn get(&self, index: usize) -> Option<Value> {
+        match *self {
+            Range::Int { start, end, step } => {
+                let n = i64::try_from(index).ok()?.checked_mul(step)?.checked_add(start)?;
+                let inside = if step > 0 { n < end } else { n > end };
+                inside.then_some(Value::Int(n))
+            }
+            Range::Float { start, end, step } => {
+                // Multiplying, rather than adding `step` repeatedly, keeps
+                // rounding errors from piling up over long ranges.
+                let n = start + index as f64 * step;
+                let inside = if step > 0.0 { n < end } else { n > end };
+                inside.then_some(Value::Number(n))
+            }
+        }
+    }
+}
+
+impl MapKey {
+    pub(crate) fn new(value: Value) -> Result<Self, String> {
+        match value {
+            // NaN is not equal to itself, so it could never be looked up.
+            Value::Number(n) if n.is_nan() => Err("NaN can't be a map key.".to_string()),
+            Value::Null
+            | Value::Bool(_)
+            | Value::Int(_)
+            | Value::BigInt(_)
+            | Value::Decimal(_)
+            | Value::Number(_)
+            | Value::String(_) => Ok(MapKey(value)),
+            _ => Err("Map keys must be nil, booleans, numbers or strings.".to_string()),
+        }
+    }
+
+    pub(crate) fn value(&self) -> &Value {
+        &self.0
+    }
+}
+
+impl PartialEq for MapKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl Eq for MapKey {}
+
+impl Hash for MapKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match &self.0 {
+            Value::Null => 0.hash(state),
+            Value::Bool(b) => (1, b).hash(state),
+            // Equal numbers must hash alike whatever their type, so each is
+            // hashed as the simplest type that holds it. That covers `-0.0`,
+            // which is equal to `0`.
+            Value::Int(n) => (2, n).hash(state),
+            Value::Number(n) => match (exact_int(*n), BigInt::from_f64(*n)) {
+                (Some(n), _) => (2, n).hash(state),
+                (None, Some(n)) => (5, n).hash(state),
+                (None, None) => (3, n.to_bits()).hash(state),
+            },
+            Value::BigInt(n) => match n.to_i64() {
+                Some(n) => (2, n).hash(state),
+                None => (5, n).hash(state),
+            },
+            Value::Decimal(n) => match n.to_bigint() {
+                Some(n) => MapKey(Value::bigint(n)).hash(state),
+                None => (6, n.normalized().to_string()).hash(state),
+            },
+            Value::String(s) => (4, s).hash(state),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Display for MapKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt_nested(f, &mut vec![])
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Null, Value::Null) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            // Exact, unlike converting the integer to a float, so that
+            // equality stays transitive.
+            (Value::Int(a), Value::Number(b)) | (Value::Number(b), Value::Int(a)) => exact_int(*b) == Some(*a),
+            (Value::BigInt(a), Value::Number(b)) | (Value::Number(b), Value::BigInt(a)) => {
+                BigInt::from_f64(*b).as_ref() == Some(a)
+            }
+            // Decimals only equal exact numbers, as they don't mix with
+            // floats.
+            (Value::Int(_) | Value::BigInt(_) | Value::Decimal(_), Value::Int(_) | Value::BigInt(_) | Value::Decimal(_)) => {
+                self.to_decimal() == other.to_decimal()
+            }
+            (Value::String(a), Value::String(b)) => a == b,
+            // Mutable values are only equal to themselves.
+            (Value::List(a), Value::List(b)) => Rc::ptr_eq(a, b),
+            (Value::Map(a), Value::Map(b)) => Rc::ptr_eq(a, b),
+            (Value::Range(a), Value::Range(b)) => a == b,
+            (Value::NativeFunction(a), Value::NativeFunction(b)) => std::ptr::eq(*a, *b),
+            (Value::NativeMethod(a), Value::NativeMethod(b)) => Rc::ptr_eq(a, b),
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::Module(a), Value::Module(b)) => Rc::ptr_eq(a, b),
+            (Value::Error(a), Value::Error(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl From<LiteralToken> for Value {
+    fn from(token: LiteralToken) -> Self {
+        match token {
+            LiteralToken::Null => Value::Null,
+            LiteralToken::Bool(b) => Value::Bool(b),
+            LiteralToken::Int(n) => Value::Int(n),
+            LiteralToken::BigInt(n) => Value::BigInt(n),
+            LiteralToken::Decimal(n) => Value::Decimal(n),
+            LiteralToken::Number(n) => Value::Number(n),
+            LiteralToken::String(s) => Value::String(s),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Null => write!(f, "nil"),
+            Value::Bool(v) => write!(f, "{}", v),
+            Value::Int(v) => write!(f, "{}", v),
+            Value::BigInt(v) => write!(f, "{}", v),
+            Value::Decimal(v) => write!(f, "{}", v),
+            // Floats always show a fractional part, to tell them apart from
+            // integers.
+            Value::Number(v) if v.is_finite() && v.fract() == 0.0 => write!(f, "{:.1}", v),
+            Value::Number(v) => write!(f, "{}", v),
+            Value::String(v) => write!(f, "{}", v),
+            Value::List(_) | Value::Map(_) => self.fmt_within(f, &mut vec![]),
+            Value::Range(Range::Int { start, end, step }) => write!(f, "range({}, {}, {})", start, end, step),
+            Value::Range(Range::Float { start, end, step }) => {
+                let (start, end, step) = (Value::Number(*start), Value::Number(*end), Value::Number(*step));
+                write!(f, "range({}, {}, {})", start, end, step)
+            }
+            Value::NativeFunction(v) => write!(f, "<native fn {}>", v.name),
+            Value::NativeMethod(v) => write!(f, "<native method {}>", v.method.name),
+            Value::Function(v) => match &v.name {
+                Some(name) => write!(f, "<fn {}>", name),
+                None => write!(f, "<fn>"),
+            },
+            Value::Module(v) => write!(f, "<module {}>", v.name),
+            Value::Error(v) => write!(f, "<error: {}>", v.message),
+        }
+    }
+}